@@ -0,0 +1,120 @@
+use std::fmt;
+
+use anyhow::bail;
+use semver::BuildMetadata;
+
+use crate::template;
+
+/// What a build metadata template can contain, in `{}`.
+const PLACEHOLDERS: [&str; 4] = ["short_hash", "hash", "date", "env:NAME"];
+
+/// Build metadata with placeholders, e.g. `g{short_hash}.{date}`, or `ci.{env:BUILD_NUMBER}`.
+///
+/// `{date}` is `20240131`, `{env:NAME}` is the environment variable `NAME`, e.g. the CI build number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildTemplate {
+	template: String,
+}
+
+impl BuildTemplate {
+	pub fn new(template: &str) -> anyhow::Result<Self> {
+		if template.trim().is_empty() {
+			bail!("Build metadata template must not be empty");
+		}
+		template::check(template, &PLACEHOLDERS, "build metadata template")?;
+		Ok(Self {
+			template: template.to_string(),
+		})
+	}
+
+	/// The build metadata for the commit `hash`, fails on unset variables, and invalid results.
+	///
+	/// `date` is `2024-01-31`.
+	pub fn render(
+		&self,
+		hash: &str,
+		date: &str,
+		env: impl Fn(&str) -> Option<String>,
+	) -> anyhow::Result<BuildMetadata> {
+		let mut unset = None;
+		let build = template::expand(&self.template, "build metadata template", |p| match p {
+			"short_hash" => hash.chars().take(7).collect(),
+			"hash" => hash.to_string(),
+			"date" => date.replace('-', ""),
+			p => {
+				let name = p.strip_prefix("env:").unwrap_or(p);
+				env(name).unwrap_or_else(|| {
+					unset.get_or_insert(name.to_string());
+					String::new()
+				})
+			},
+		})?;
+		if let Some(name) = unset {
+			bail!(
+				"Environment variable {} is not set, build metadata template `{}` needs it",
+				name,
+				self
+			);
+		}
+		match BuildMetadata::new(&build) {
+			Ok(build) => Ok(build),
+			Err(e) => bail!(
+				"Invalid build metadata {:?} from template `{}`: {}",
+				build,
+				self,
+				e
+			),
+		}
+	}
+}
+
+impl fmt::Display for BuildTemplate {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", &self.template)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn env(name: &str) -> Option<String> {
+		match name {
+			"BUILD_NUMBER" => Some("42".to_string()),
+			"BRANCH" => Some("feature/x".to_string()),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn renders_placeholders() {
+		let render = |t: &str| {
+			BuildTemplate::new(t)
+				.unwrap()
+				.render("4f012a5c0ffee", "2026-10-18", env)
+				.map(|b| b.to_string())
+		};
+		assert_eq!(render("g{short_hash}.{date}").unwrap(), "g4f012a5.20261018");
+		assert_eq!(render("{hash}").unwrap(), "4f012a5c0ffee");
+		assert_eq!(render("ci.{env:BUILD_NUMBER}").unwrap(), "ci.42");
+		assert_eq!(
+			render("{env:MISSING}").unwrap_err().to_string(),
+			"Environment variable MISSING is not set, build metadata template `{env:MISSING}` needs it"
+		);
+		assert_eq!(
+			render("{env:BRANCH}").unwrap_err().to_string(),
+			"Invalid build metadata \"feature/x\" from template `{env:BRANCH}`: unexpected character in build metadata"
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_templates() {
+		let err = BuildTemplate::new("{sha}").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Unknown placeholder `{sha}` in build metadata template \"{sha}\", should be one of {short_hash}/{hash}/{date}/{env:NAME}"
+		);
+		assert!(BuildTemplate::new("{env:}").is_err());
+		assert!(BuildTemplate::new("").is_err());
+	}
+}
@@ -1,11 +1,14 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::bail;
+use cargo::core::dependency::DepKind;
 use cargo::core::Workspace;
 use cargo::ops::{self, UpdateOptions};
 use cargo::util::context::GlobalContext;
 use path_absolutize::*;
 
+use crate::event::StatusSink;
+
 pub struct Cargo /*<'a>*/ {
 	path: PathBuf,
 	cfg:  Option<GlobalContext>,
@@ -73,6 +76,71 @@ impl Cargo /*<'a>*/ {
 			bail!("No Config");
 		}
 	}
+	fn workspace(&self) -> anyhow::Result<Workspace<'_>> {
+		if let Some(cfg) = &self.cfg {
+			let manifest_path = std::path::Path::new(&self.path).join("Cargo.toml");
+			let p = manifest_path.absolutize()?;
+
+			match Workspace::new(&p, cfg) {
+				Ok(ws) => Ok(ws),
+				Err(e) => bail!("Failed to create Cargo workspace: {}", e),
+			}
+		} else {
+			bail!("No Config");
+		}
+	}
+
+	/// Manifest paths of all workspace members, call `open` first.
+	pub fn member_manifests(&self) -> anyhow::Result<Vec<PathBuf>> {
+		let ws = self.workspace()?;
+		Ok(ws
+			.members()
+			.map(|m| m.manifest_path().to_path_buf())
+			.collect())
+	}
+
+	/// Names of all workspace members, dependencies before their dependents.
+	///
+	/// Dev-dependencies are ignored, since they are allowed to form cycles.
+	pub fn members_in_dependency_order(&self) -> anyhow::Result<Vec<String>> {
+		let ws = self.workspace()?;
+		let members: Vec<(String, Vec<String>)> = ws
+			.members()
+			.map(|m| {
+				let dependencies = m
+					.dependencies()
+					.iter()
+					.filter(|d| d.kind() != DepKind::Development)
+					.map(|d| d.package_name().to_string())
+					.collect();
+				(m.name().to_string(), dependencies)
+			})
+			.collect();
+		let names: Vec<&String> = members.iter().map(|(n, _)| n).collect();
+
+		let mut ordered: Vec<String> = Vec::new();
+		while ordered.len() < members.len() {
+			let mut progress = false;
+			for (name, dependencies) in members.iter() {
+				if ordered.contains(name) {
+					continue;
+				}
+				let ready = dependencies
+					.iter()
+					.filter(|d| *d != name && names.contains(d))
+					.all(|d| ordered.contains(d));
+				if ready {
+					ordered.push(name.clone());
+					progress = true;
+				}
+			}
+			if !progress {
+				bail!("Dependency cycle between workspace members");
+			}
+		}
+		Ok(ordered)
+	}
+
 	/*
 	WorkspaceRootConfig::
 	pub fn new(
@@ -93,7 +161,7 @@ impl Cargo /*<'a>*/ {
 	*/
 	// Config::default()
 
-	pub fn update_workspace(&mut self) -> anyhow::Result<()> {
+	pub fn update_workspace(&mut self, status: StatusSink) -> anyhow::Result<()> {
 		//		let cfg = WorkspaceRootConfig::new(".")
 		//		ops::update_lockfile(&ws, &update_opts)?;
 		if let Some(cfg) = &self.cfg {
@@ -130,7 +198,7 @@ impl Cargo /*<'a>*/ {
 			// Update lockfile
 			match ops::update_lockfile(&ws, &update_opts) {
 				Ok(_) => {
-					println!("Updated Cargo.lock for {}", &p.display());
+					status!(status, "Updated Cargo.lock for {}", &p.display());
 					Ok(())
 				},
 				Err(e) => bail!("Failed to update Cargo.lock: {}", e),
@@ -149,3 +217,111 @@ impl Cargo /*<'a>*/ {
 		*/
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::manifest::Manifests;
+
+	/// A workspace with the root manifest `root`, and a library for every member.
+	fn workspace(root: &str, members: &[(&str, &str)]) -> tempfile::TempDir {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::write(dir.path().join("Cargo.toml"), root).unwrap();
+		std::fs::write(dir.path().join("Cargo.lock"), "").unwrap();
+		for (name, manifest) in members {
+			let member = dir.path().join(name);
+			std::fs::create_dir_all(member.join("src")).unwrap();
+			std::fs::write(member.join("src/lib.rs"), "").unwrap();
+			std::fs::write(
+				member.join("Cargo.toml"),
+				format!("[package]\nname = \"{}\"\n{}", name, manifest),
+			)
+			.unwrap();
+		}
+		dir
+	}
+
+	fn open(dir: &Path) -> Cargo {
+		let mut cargo = Cargo::new(dir);
+		cargo.open().unwrap();
+		cargo
+	}
+
+	#[test]
+	fn members_in_dependency_order() {
+		let dir = workspace(
+			"[workspace]\nmembers = [\"app\", \"core\", \"util\"]\n",
+			&[
+				(
+					"app",
+					"version = \"0.1.0\"\n[dependencies]\nutil = { path = \"../util\" }\n",
+				),
+				(
+					"core",
+					"version = \"0.1.0\"\n[dev-dependencies]\napp = { path = \"../app\" }\n",
+				),
+				(
+					"util",
+					"version = \"0.1.0\"\n[build-dependencies]\ncore = { path = \"../core\" }\n",
+				),
+			],
+		);
+		// the dev-dependency of core on app is no cycle
+		assert_eq!(
+			open(dir.path()).members_in_dependency_order().unwrap(),
+			["core", "util", "app"]
+		);
+	}
+
+	#[test]
+	fn dependency_cycle_between_members() {
+		let dir = workspace(
+			"[workspace]\nmembers = [\"a\", \"b\"]\n",
+			&[
+				(
+					"a",
+					"version = \"0.1.0\"\n[dependencies]\nb = { path = \"../b\" }\n",
+				),
+				(
+					"b",
+					"version = \"0.1.0\"\n[dependencies]\na = { path = \"../a\" }\n",
+				),
+			],
+		);
+		let err = open(dir.path()).members_in_dependency_order().unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Dependency cycle between workspace members"
+		);
+	}
+
+	#[test]
+	fn inherited_and_explicit_versions() {
+		let root =
+			"[workspace]\nmembers = [\"a\", \"b\"]\n[workspace.package]\nversion = \"0.2.0-dev\"\n";
+		let dir = workspace(
+			root,
+			&[
+				("a", "version.workspace = true\n"),
+				("b", "version.workspace = true\n"),
+			],
+		);
+		let members = open(dir.path()).member_manifests().unwrap();
+		assert_eq!(members.len(), 2);
+		let m = Manifests::load(&dir.path().join("Cargo.toml"), &members).unwrap();
+		assert_eq!(m.get_pretty_version().unwrap(), "0.2.0-dev");
+
+		// a member with a version of its own must agree with the workspace
+		let dir = workspace(
+			root,
+			&[
+				("a", "version.workspace = true\n"),
+				("b", "version = \"0.1.0\"\n"),
+			],
+		);
+		let members = open(dir.path()).member_manifests().unwrap();
+		let m = Manifests::load(&dir.path().join("Cargo.toml"), &members).unwrap();
+		let err = m.get_version().unwrap_err().to_string();
+		assert!(err.starts_with("Versions differ"), "{}", err);
+	}
+}
@@ -0,0 +1,345 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use regex::Regex;
+
+use crate::conventional::ConventionalCommit;
+
+pub const CHANGELOG_FILE: &str = "CHANGELOG.md";
+
+/// Puts every commit with a header matching `pattern` under `### {title}`.
+///
+/// The entry is the `description` capture group if there is one, the whole header otherwise.
+#[derive(Debug, Clone)]
+pub struct ChangelogGroup {
+	title:   String,
+	pattern: Regex,
+}
+
+impl ChangelogGroup {
+	pub fn new(title: &str, pattern: &str) -> anyhow::Result<Self> {
+		let pattern = match Regex::new(pattern) {
+			Ok(p) => p,
+			Err(e) => bail!("Invalid changelog pattern {:?} {}", pattern, e),
+		};
+		Ok(Self {
+			title: title.to_string(),
+			pattern,
+		})
+	}
+
+	fn entry(&self, header: &str) -> Option<String> {
+		let captures = self.pattern.captures(header)?;
+		let entry = match captures.name("description") {
+			Some(d) => d.as_str(),
+			None => header,
+		};
+		Some(entry.trim().to_string())
+	}
+}
+
+/// A changelog in the [Keep a Changelog](https://keepachangelog.com) format.
+#[derive(Debug)]
+pub struct Changelog {
+	path:   PathBuf,
+	groups: Vec<ChangelogGroup>,
+}
+
+/// `### {title}` and its entries.
+type Section = Vec<(String, Vec<String>)>;
+
+impl Changelog {
+	/// Without `groups` commits are grouped by their Conventional Commit type.
+	pub fn new(path: &Path, groups: &[ChangelogGroup]) -> Self {
+		Self {
+			path:   path.to_owned(),
+			groups: groups.to_vec(),
+		}
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Groups the commit headers, commits that don't belong to any group are left out.
+	pub fn sections(&self, commits: &[(String, String)]) -> Section {
+		let mut section = Section::new();
+		// oldest first
+		for (hash, message) in commits.iter().rev() {
+			let header = message.lines().next().unwrap_or_default().trim();
+			let entry = if self.groups.is_empty() {
+				ConventionalCommit::parse(hash, message).and_then(|c| {
+					let title = match (c.kind(), c.is_breaking()) {
+						(_, true) => "Changed",
+						("feat", _) => "Added",
+						("fix", _) => "Fixed",
+						("perf", _) | ("refactor", _) => "Changed",
+						("revert", _) => "Removed",
+						_ => return None,
+					};
+					let description = if c.is_breaking() {
+						format!("**BREAKING** {}", c.description())
+					} else {
+						c.description().to_string()
+					};
+					Some((title.to_string(), description))
+				})
+			} else {
+				self.groups
+					.iter()
+					.find_map(|g| g.entry(header).map(|e| (g.title.clone(), e)))
+			};
+			if let Some((title, entry)) = entry {
+				add_entry(&mut section, &title, format!("- {}", entry));
+			}
+		}
+		section
+	}
+
+	/// Returns the changelog with a new `## [{version}] - {date}` section,
+	/// containing the `## [Unreleased]` entries, and the `commits`.
+	pub fn render(
+		&self,
+		contents: &str,
+		version: &str,
+		date: &str,
+		commits: &[(String, String)],
+	) -> String {
+		let mut lines: Vec<&str> = contents.lines().collect();
+		let is_release = |l: &&str| l.starts_with("## ");
+		let is_unreleased = |l: &&str| l.trim().eq_ignore_ascii_case("## [Unreleased]");
+
+		let mut section = Section::new();
+		let insert_at = match lines.iter().position(is_unreleased) {
+			Some(unreleased) => {
+				let end = lines[unreleased + 1..]
+					.iter()
+					.position(is_release)
+					.map(|e| unreleased + 1 + e)
+					.unwrap_or(lines.len());
+				section = parse_section(&lines[unreleased + 1..end]);
+				lines.drain(unreleased + 1..end);
+				unreleased + 1
+			},
+			None => lines.iter().position(is_release).unwrap_or(lines.len()),
+		};
+		for (title, entries) in self.sections(commits) {
+			for e in entries {
+				add_entry(&mut section, &title, e);
+			}
+		}
+
+		let mut new = vec![String::new(), format!("## [{}] - {}", version, date)];
+		for (title, entries) in section {
+			new.push(String::new());
+			if !title.is_empty() {
+				new.push(format!("### {}", title));
+			}
+			new.extend(entries);
+		}
+
+		let mut result: Vec<String> = Vec::new();
+		if lines.is_empty() {
+			result.push("# Changelog".to_string());
+			result.push(String::new());
+			result.push("## [Unreleased]".to_string());
+		}
+		let (before, after) = lines.split_at(insert_at);
+		let before = trim_blank_end(before);
+		let after: Vec<&str> = after
+			.iter()
+			.skip_while(|l| l.trim().is_empty())
+			.copied()
+			.collect();
+		result.extend(before.iter().map(|l| l.to_string()));
+		result.extend(new);
+		if !after.is_empty() {
+			result.push(String::new());
+			result.extend(after.iter().map(|l| l.to_string()));
+		}
+
+		let mut result = result.join("\n");
+		result.push('\n');
+		result
+	}
+
+	/// Writes the new section to the changelog, an empty changelog is created if there is none.
+	pub fn update(
+		&self,
+		version: &str,
+		date: &str,
+		commits: &[(String, String)],
+	) -> anyhow::Result<()> {
+		let contents = if self.path.exists() {
+			std::fs::read_to_string(&self.path)?
+		} else {
+			String::new()
+		};
+		std::fs::write(&self.path, self.render(&contents, version, date, commits))?;
+		Ok(())
+	}
+}
+
+fn add_entry(section: &mut Section, title: &str, entry: String) {
+	match section.iter_mut().find(|(t, _)| t == title) {
+		Some((_, entries)) => entries.push(entry),
+		None => section.push((title.to_string(), vec![entry])),
+	}
+}
+
+/// Splits the lines of a release into `### ` groups, lines before the first group have no title.
+fn parse_section(lines: &[&str]) -> Section {
+	let mut section = Section::new();
+	let mut title = String::new();
+	for l in lines {
+		if let Some(t) = l.strip_prefix("### ") {
+			title = t.trim().to_string();
+		} else if !l.trim().is_empty() {
+			add_entry(&mut section, &title, l.to_string());
+		}
+	}
+	section
+}
+
+fn trim_blank_end<'a>(lines: &'a [&'a str]) -> &'a [&'a str] {
+	let end = lines
+		.iter()
+		.rposition(|l| !l.trim().is_empty())
+		.map(|e| e + 1)
+		.unwrap_or(0);
+	&lines[..end]
+}
+
+/// Today in UTC, e.g. `2024-01-31`.
+pub fn today() -> String {
+	time::OffsetDateTime::now_utc().date().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Hash, and message, newest first, like `commit_messages` returns them.
+	fn commits(messages: &[&str]) -> Vec<(String, String)> {
+		messages
+			.iter()
+			.enumerate()
+			.map(|(i, m)| (format!("{:07x}", i), m.to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn unreleased_entries_move_to_the_new_version() {
+		let contents = r#"# Changelog
+
+## [Unreleased]
+
+### Added
+- Manual entry
+
+## [0.1.0] - 2024-01-01
+
+### Fixed
+- Old fix
+"#;
+		let changelog = Changelog::new(Path::new(CHANGELOG_FILE), &[]);
+		let result = changelog.render(
+			contents,
+			"0.2.0",
+			"2024-02-01",
+			&commits(&["fix: crash", "feat: new thing"]),
+		);
+		assert_eq!(
+			result,
+			r#"# Changelog
+
+## [Unreleased]
+
+## [0.2.0] - 2024-02-01
+
+### Added
+- Manual entry
+- new thing
+
+### Fixed
+- crash
+
+## [0.1.0] - 2024-01-01
+
+### Fixed
+- Old fix
+"#
+		);
+	}
+
+	#[test]
+	fn missing_changelog_is_created() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join(CHANGELOG_FILE);
+		let changelog = Changelog::new(&path, &[]);
+		changelog
+			.update("0.1.0", "2024-01-31", &commits(&["feat: first"]))
+			.unwrap();
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap(),
+			r#"# Changelog
+
+## [Unreleased]
+
+## [0.1.0] - 2024-01-31
+
+### Added
+- first
+"#
+		);
+	}
+
+	#[test]
+	fn commits_are_grouped_by_type() {
+		let changelog = Changelog::new(Path::new(CHANGELOG_FILE), &[]);
+		let sections = changelog.sections(&commits(&[
+			"Not conventional",
+			"chore: tidy up",
+			"revert: feat: old thing",
+			"perf: faster",
+			"feat(cli)!: drop --old",
+			"fix: crash",
+			"feat: new thing",
+		]));
+		let expected = [
+			("Added", vec!["- new thing"]),
+			("Fixed", vec!["- crash"]),
+			("Changed", vec!["- **BREAKING** drop --old", "- faster"]),
+			("Removed", vec!["- feat: old thing"]),
+		];
+		assert_eq!(
+			sections,
+			expected
+				.iter()
+				.map(|(t, e)| (t.to_string(), e.iter().map(|e| e.to_string()).collect()))
+				.collect::<Section>()
+		);
+
+		let groups = [ChangelogGroup::new("Docs", r"^docs: (?P<description>.*)").unwrap()];
+		let changelog = Changelog::new(Path::new(CHANGELOG_FILE), &groups);
+		let sections = changelog.sections(&commits(&["fix: crash", "docs: explain"]));
+		assert_eq!(
+			sections,
+			[("Docs".to_string(), vec!["- explain".to_string()])]
+		);
+	}
+
+	#[test]
+	fn existing_sections_stay_byte_identical() {
+		let released = "## [0.1.0] - 2024-01-01\n\nSome notes, *not* a list.  \n\n\n### Fixed\n* Old fix\n  continued\n\n## 0.0.1\n- First\n";
+		let contents = format!("# Changelog\n\nIntro.\n\n## [Unreleased]\n\n{}", released);
+		let changelog = Changelog::new(Path::new(CHANGELOG_FILE), &[]);
+		let result = changelog.render(&contents, "0.2.0", "2024-02-01", &commits(&["fix: crash"]));
+		assert!(result.starts_with("# Changelog\n\nIntro.\n\n## [Unreleased]\n\n## [0.2.0]"));
+		assert!(
+			result.ends_with(&format!("- crash\n\n{}", released)),
+			"{}",
+			result
+		);
+	}
+}
@@ -0,0 +1,446 @@
+use std::path::Path;
+
+use anyhow::bail;
+use toml_edit::{Document, Item};
+
+use crate::build_template::BuildTemplate;
+use crate::changelog::ChangelogGroup;
+use crate::git_status::DirtyPolicy;
+use crate::signing::SignatureFormat;
+use crate::step::{GitCommitMessage, Step, COMMIT_PLACEHOLDERS};
+use crate::tag_template::TagTemplate;
+use crate::template;
+
+pub const CONFIG_FILE: &str = "omr-bumper.toml";
+
+/// Everything a config can contain.
+const KEYS: [&str; 16] = [
+	"steps",
+	"changelog",
+	"dirty_policy",
+	"branches",
+	"remote",
+	"target_branch",
+	"sign",
+	"signing_key",
+	"signing_format",
+	"pre_release_counter",
+	"tag_template",
+	"tag_build_metadata",
+	"build_metadata",
+	"release_commit_message",
+	"dev_commit_message",
+	"commit_trailers",
+];
+
+/// Project specific settings from `omr-bumper.toml`,
+/// or the `[package.metadata.omr-bumper]`/`[workspace.metadata.omr-bumper]` table in `Cargo.toml`.
+#[derive(Debug, Default)]
+pub struct Config {
+	source:              String,
+	steps:               Option<Vec<Step>>,
+	changelog:           bool,
+	changelog_path:      Option<String>,
+	changelog_groups:    Vec<ChangelogGroup>,
+	dirty_policy:        Option<DirtyPolicy>,
+	branches:            Option<Vec<String>>,
+	remote:              Option<String>,
+	target_branch:       Option<String>,
+	sign:                bool,
+	pre_release_counter: bool,
+	signing_key:         Option<String>,
+	signing_format:      Option<SignatureFormat>,
+	tag_template:        Option<TagTemplate>,
+	tag_build_metadata:  bool,
+	build_metadata:      Option<BuildTemplate>,
+	release_message:     Option<String>,
+	dev_message:         Option<String>,
+	commit_trailers:     Vec<String>,
+}
+
+impl Config {
+	/// Loads the config for the project at `path`, returns the default config if there is none.
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let config_path = path.join(CONFIG_FILE);
+		if config_path.exists() {
+			let doc = Self::load_document(&config_path)?;
+			return Self::from_item(doc.as_item(), &config_path.display().to_string());
+		}
+
+		let manifest_path = path.join("Cargo.toml");
+		if manifest_path.exists() {
+			let doc = Self::load_document(&manifest_path)?;
+			for table in ["package", "workspace"] {
+				let item = doc
+					.get(table)
+					.and_then(|t| t.get("metadata"))
+					.and_then(|m| m.get("omr-bumper"));
+				if let Some(item) = item {
+					let source = format!(
+						"{} [{}.metadata.omr-bumper]",
+						manifest_path.display(),
+						table
+					);
+					return Self::from_item(item, &source);
+				}
+			}
+		}
+
+		Ok(Self::default())
+	}
+
+	fn load_document(path: &Path) -> anyhow::Result<Document> {
+		let toml = std::fs::read_to_string(path)?;
+		match toml.parse::<Document>() {
+			Ok(doc) => Ok(doc),
+			Err(e) => bail!("Couldn't load config from >>{:?}<< {}", &path, &e),
+		}
+	}
+
+	fn from_item(item: &Item, source: &str) -> anyhow::Result<Self> {
+		let Some(table) = item.as_table_like() else {
+			bail!("{}: Config must be a table", source);
+		};
+		// e.g. a typo, that would be ignored silently otherwise
+		for (key, _) in table.iter() {
+			if !KEYS.contains(&key) {
+				bail!(
+					"{}: Unknown key `{}`, should be one of {}",
+					source,
+					key,
+					KEYS.join(", ")
+				);
+			}
+		}
+
+		let mut config = Self {
+			source: source.to_string(),
+			..Default::default()
+		};
+
+		match item.get("steps") {
+			None => {},
+			Some(Item::Value(toml_edit::Value::Array(a))) => {
+				let mut steps = Vec::new();
+				for (i, s) in a.iter().enumerate() {
+					let step = match s.as_str() {
+						Some(s) => s.parse::<Step>(),
+						None => bail!("{}: step #{} must be a string", source, i + 1),
+					};
+					match step {
+						Ok(step) => steps.push(step),
+						Err(e) => bail!("{}: step #{} {}", source, i + 1, e),
+					}
+				}
+				Step::validate(&steps, source)?;
+				config.steps = Some(steps);
+			},
+			Some(_) => bail!("{}: `steps` must be an array of strings", source),
+		}
+
+		match item.get("changelog") {
+			None => {},
+			Some(changelog) if changelog.is_table_like() => {
+				config.changelog = true;
+				for (key, _) in changelog.as_table_like().into_iter().flat_map(|t| t.iter()) {
+					if !["path", "groups"].contains(&key) {
+						bail!(
+							"{}: Unknown key `changelog.{}`, should be one of path, groups",
+							source,
+							key
+						);
+					}
+				}
+				match changelog.get("path") {
+					None => {},
+					Some(p) => match p.as_str() {
+						Some(p) => config.changelog_path = Some(p.to_string()),
+						None => bail!("{}: `changelog.path` must be a string", source),
+					},
+				}
+				if let Some(groups) = changelog.get("groups") {
+					let groups = match groups.as_array_of_tables() {
+						Some(groups) => groups,
+						None => bail!("{}: `changelog.groups` must be an array of tables", source),
+					};
+					for (i, g) in groups.iter().enumerate() {
+						match (
+							g.get("title").and_then(|t| t.as_str()),
+							g.get("pattern").and_then(|p| p.as_str()),
+						) {
+							(Some(title), Some(pattern)) => {
+								match ChangelogGroup::new(title, pattern) {
+									Ok(g) => config.changelog_groups.push(g),
+									Err(e) => bail!("{}: changelog group #{} {}", source, i + 1, e),
+								}
+							},
+							_ => bail!(
+								"{}: changelog group #{} needs a `title`, and a `pattern`",
+								source,
+								i + 1
+							),
+						}
+					}
+				}
+			},
+			Some(_) => bail!("{}: `changelog` must be a table", source),
+		}
+
+		match item.get("dirty_policy") {
+			None => {},
+			Some(p) => match p.as_str().map(|p| p.parse::<DirtyPolicy>()) {
+				Some(Ok(p)) => config.dirty_policy = Some(p),
+				Some(Err(e)) => bail!("{}: {}", source, e),
+				None => bail!("{}: `dirty_policy` must be a string", source),
+			},
+		}
+
+		for (key, value) in [
+			("remote", &mut config.remote),
+			("target_branch", &mut config.target_branch),
+			("signing_key", &mut config.signing_key),
+		] {
+			match item.get(key) {
+				None => {},
+				Some(v) => match v.as_str() {
+					Some(v) => *value = Some(v.to_string()),
+					None => bail!("{}: `{}` must be a string", source, key),
+				},
+			}
+		}
+
+		for (key, value) in [
+			("sign", &mut config.sign),
+			("pre_release_counter", &mut config.pre_release_counter),
+			("tag_build_metadata", &mut config.tag_build_metadata),
+		] {
+			match item.get(key) {
+				None => {},
+				Some(v) => match v.as_bool() {
+					Some(v) => *value = v,
+					None => bail!("{}: `{}` must be a boolean", source, key),
+				},
+			}
+		}
+
+		match item.get("signing_format") {
+			None => {},
+			Some(f) => match f.as_str().map(|f| f.parse::<SignatureFormat>()) {
+				Some(Ok(f)) => config.signing_format = Some(f),
+				Some(Err(e)) => bail!("{}: {}", source, e),
+				None => bail!("{}: `signing_format` must be a string", source),
+			},
+		}
+
+		match item.get("tag_template") {
+			None => {},
+			Some(t) => match t.as_str().map(TagTemplate::new) {
+				Some(Ok(t)) => config.tag_template = Some(t),
+				Some(Err(e)) => bail!("{}: {}", source, e),
+				None => bail!("{}: `tag_template` must be a string", source),
+			},
+		}
+
+		match item.get("build_metadata") {
+			None => {},
+			Some(t) => match t.as_str().map(BuildTemplate::new) {
+				Some(Ok(t)) => config.build_metadata = Some(t),
+				Some(Err(e)) => bail!("{}: {}", source, e),
+				None => bail!("{}: `build_metadata` must be a string", source),
+			},
+		}
+
+		for (key, value) in [
+			("release_commit_message", &mut config.release_message),
+			("dev_commit_message", &mut config.dev_message),
+		] {
+			match item.get(key) {
+				None => {},
+				Some(v) => match v.as_str() {
+					Some(v) => match template::check(v, &COMMIT_PLACEHOLDERS, "commit message") {
+						Ok(()) => *value = Some(v.to_string()),
+						Err(e) => bail!("{}: `{}` {}", source, key, e),
+					},
+					None => bail!("{}: `{}` must be a string", source, key),
+				},
+			}
+		}
+
+		match item.get("commit_trailers") {
+			None => {},
+			Some(Item::Value(toml_edit::Value::Array(a))) => {
+				for (i, t) in a.iter().enumerate() {
+					match t.as_str() {
+						Some(t) => match template::check(t, &COMMIT_PLACEHOLDERS, "trailer") {
+							Ok(()) => config.commit_trailers.push(t.to_string()),
+							Err(e) => bail!("{}: trailer #{} {}", source, i + 1, e),
+						},
+						None => bail!("{}: trailer #{} must be a string", source, i + 1),
+					}
+				}
+			},
+			Some(_) => bail!("{}: `commit_trailers` must be an array of strings", source),
+		}
+
+		match item.get("branches") {
+			None => {},
+			Some(Item::Value(toml_edit::Value::Array(a))) => {
+				let mut branches = Vec::new();
+				for (i, b) in a.iter().enumerate() {
+					match b.as_str() {
+						Some(b) => branches.push(b.to_string()),
+						None => bail!("{}: branch #{} must be a string", source, i + 1),
+					}
+				}
+				config.branches = Some(branches);
+			},
+			Some(_) => bail!("{}: `branches` must be an array of strings", source),
+		}
+
+		Ok(config)
+	}
+
+	/// Where the config was loaded from, empty for the default config.
+	pub fn source(&self) -> &str {
+		&self.source
+	}
+
+	pub fn steps(&self) -> Option<&Vec<Step>> {
+		self.steps.as_ref()
+	}
+
+	/// `true` if there is a `[changelog]` table, even an empty one.
+	pub fn changelog(&self) -> bool {
+		self.changelog
+	}
+
+	pub fn changelog_path(&self) -> Option<&str> {
+		self.changelog_path.as_deref()
+	}
+
+	pub fn changelog_groups(&self) -> &[ChangelogGroup] {
+		&self.changelog_groups
+	}
+
+	pub fn dirty_policy(&self) -> Option<DirtyPolicy> {
+		self.dirty_policy
+	}
+
+	pub fn remote(&self) -> Option<&str> {
+		self.remote.as_deref()
+	}
+
+	pub fn target_branch(&self) -> Option<&str> {
+		self.target_branch.as_deref()
+	}
+
+	/// `true` if release commits, and tags are signed.
+	pub fn sign(&self) -> bool {
+		self.sign
+	}
+
+	/// `true` if pre-releases are numbered, e.g. `alpha.1`.
+	pub fn pre_release_counter(&self) -> bool {
+		self.pre_release_counter
+	}
+
+	pub fn signing_key(&self) -> Option<&str> {
+		self.signing_key.as_deref()
+	}
+
+	pub fn signing_format(&self) -> Option<SignatureFormat> {
+		self.signing_format
+	}
+
+	/// Tag name for steps without one, e.g. `GitTag` instead of `GitTag(v{version})`.
+	pub fn tag_template(&self) -> Option<&TagTemplate> {
+		self.tag_template.as_ref()
+	}
+
+	/// `true` if `{version}` in tag names keeps the build metadata.
+	pub fn tag_build_metadata(&self) -> bool {
+		self.tag_build_metadata
+	}
+
+	/// Build metadata appended to every version set, e.g. `g{short_hash}`.
+	pub fn build_metadata(&self) -> Option<&BuildTemplate> {
+		self.build_metadata.as_ref()
+	}
+
+	/// Template for the message of `GitCommitManifest(m)`.
+	pub fn commit_message(&self, m: &GitCommitMessage) -> Option<&str> {
+		match m {
+			GitCommitMessage::BumpVersionForRelease => self.release_message.as_deref(),
+			GitCommitMessage::BumpVersionForDev => self.dev_message.as_deref(),
+		}
+	}
+
+	/// Lines appended to every release commit, e.g. `[skip ci]`.
+	pub fn commit_trailers(&self) -> &[String] {
+		&self.commit_trailers
+	}
+
+	/// Branches releases are allowed from, `*` matches anything, e.g. `release/*`.
+	pub fn branches(&self) -> Option<&Vec<String>> {
+		self.branches.as_ref()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn load(file: &str, contents: &str) -> anyhow::Result<Config> {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::write(dir.path().join(file), contents).unwrap();
+		Config::load(dir.path()).map_err(|e| {
+			// the temporary directory differs every time
+			anyhow::anyhow!(e
+				.to_string()
+				.replace(&dir.path().display().to_string(), "."))
+		})
+	}
+
+	#[test]
+	fn loads_the_config_file() {
+		let config = load(
+			CONFIG_FILE,
+			"sign = true\ntag_template = \"v{version}\"\nbranches = [\"main\"]\n[changelog]\npath = \"CHANGES.md\"\n",
+		)
+		.unwrap();
+		assert!(config.sign());
+		assert_eq!(config.tag_template().unwrap().to_string(), "v{version}");
+		assert_eq!(config.branches().unwrap(), &["main"]);
+		assert_eq!(config.changelog_path(), Some("CHANGES.md"));
+	}
+
+	#[test]
+	fn unknown_keys_are_rejected() {
+		let err = load(CONFIG_FILE, "sign = true\ntag_tempalte = \"v{version}\"\n").unwrap_err();
+		assert!(
+			err.to_string().starts_with(
+				"./omr-bumper.toml: Unknown key `tag_tempalte`, should be one of steps, "
+			),
+			"{}",
+			err
+		);
+
+		let err = load(
+			"Cargo.toml",
+			"[package]\nname = \"a\"\n[package.metadata.omr-bumper]\nremotes = \"upstream\"\n",
+		)
+		.unwrap_err();
+		assert!(
+			err.to_string()
+				.starts_with("./Cargo.toml [package.metadata.omr-bumper]: Unknown key `remotes`"),
+			"{}",
+			err
+		);
+
+		let err = load(CONFIG_FILE, "[changelog]\nfile = \"CHANGES.md\"\n").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"./omr-bumper.toml: Unknown key `changelog.file`, should be one of path, groups"
+		);
+	}
+}
@@ -0,0 +1,112 @@
+use semver::Version;
+
+use crate::release::BumpLevel;
+
+/// A commit message following https://www.conventionalcommits.org
+#[derive(Debug, Clone)]
+pub struct ConventionalCommit {
+	hash:        String,
+	kind:        String,
+	breaking:    bool,
+	header:      String,
+	description: String,
+}
+
+impl ConventionalCommit {
+	/// Returns `None` for messages that are not conventional commits.
+	pub fn parse(hash: &str, message: &str) -> Option<Self> {
+		let message = message.trim();
+		let header = message.lines().next()?.trim();
+		let (prefix, description) = header.split_once(':')?;
+		if description.trim().is_empty() {
+			return None;
+		}
+
+		let (prefix, mut breaking) = match prefix.strip_suffix('!') {
+			Some(p) => (p, true),
+			None => (prefix, false),
+		};
+		let kind = match prefix.split_once('(') {
+			Some((kind, scope)) if scope.ends_with(')') => kind,
+			Some(_) => return None,
+			None => prefix,
+		};
+		if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric()) {
+			return None;
+		}
+
+		// footers, e.g. `BREAKING CHANGE: removed --foo`
+		breaking |= message
+			.lines()
+			.skip(1)
+			.any(|l| l.starts_with("BREAKING CHANGE:") || l.starts_with("BREAKING-CHANGE:"));
+
+		Some(Self {
+			hash: hash.to_string(),
+			kind: kind.to_ascii_lowercase(),
+			breaking,
+			header: header.to_string(),
+			description: description.trim().to_string(),
+		})
+	}
+
+	pub fn short_hash(&self) -> &str {
+		&self.hash[..self.hash.len().min(7)]
+	}
+
+	pub fn header(&self) -> &str {
+		&self.header
+	}
+
+	/// The type, e.g. `feat`.
+	pub fn kind(&self) -> &str {
+		&self.kind
+	}
+
+	pub fn is_breaking(&self) -> bool {
+		self.breaking
+	}
+
+	/// The header without type and scope.
+	pub fn description(&self) -> &str {
+		&self.description
+	}
+
+	/// The bump level this commit needs, `None` if it doesn't need a release on its own.
+	///
+	/// Before 1.0.0 breaking changes only bump the minor version.
+	pub fn bump_level(&self, version: &Version) -> Option<BumpLevel> {
+		if self.breaking {
+			if version.major == 0 {
+				Some(BumpLevel::Minor)
+			} else {
+				Some(BumpLevel::Major)
+			}
+		} else {
+			match self.kind.as_str() {
+				"feat" => Some(BumpLevel::Minor),
+				"fix" | "perf" => Some(BumpLevel::Patch),
+				_ => None,
+			}
+		}
+	}
+}
+
+/// The highest bump level needed by any of the `commits`, and the commits that need it.
+///
+/// Falls back to a patch bump if no commit needs a release.
+pub fn bump_level<'a>(
+	commits: &'a [ConventionalCommit],
+	version: &Version,
+) -> (BumpLevel, Vec<&'a ConventionalCommit>) {
+	let level = commits
+		.iter()
+		.filter_map(|c| c.bump_level(version))
+		.max()
+		.unwrap_or(BumpLevel::Patch);
+	let drivers = commits
+		.iter()
+		.filter(|c| c.bump_level(version) == Some(level))
+		.collect();
+	(level, drivers)
+}
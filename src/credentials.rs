@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use git2::{Cred, CredentialType};
+
+/// Gives up after this many requests for credentials, libgit2 asks again after every rejection.
+const MAX_ATTEMPTS: usize = 10;
+
+/// Keys tried after the ssh-agent, in `~/.ssh`, after the ones configured with `omr-bumper.sshKey`.
+const DEFAULT_KEYS: [&str; 3] = ["id_ed25519", "id_rsa", "id_ecdsa"];
+
+/// A way to authenticate, in the order they are tried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+	/// Only the user name, libgit2 asks for it before the ssh credentials if the url has none.
+	Username(String),
+	SshAgent(String),
+	SshKey {
+		username: String,
+		private:  PathBuf,
+		public:   Option<PathBuf>,
+	},
+	/// `credential.helper` from the git config.
+	CredentialHelper,
+	/// The program in `GIT_ASKPASS`, asked for user name, and password.
+	AskPass(String),
+	/// e.g. NTLM, or Kerberos.
+	Default,
+}
+
+/// The credential callback of the git2 backend, remembers what was tried already.
+///
+/// Every method is only tried once, libgit2 calls again if it was rejected.
+pub struct CredentialChain {
+	config:   git2::Config,
+	keys:     Vec<PathBuf>,
+	askpass:  Option<String>,
+	attempts: usize,
+	tried:    Vec<Method>,
+}
+
+impl CredentialChain {
+	/// Uses the keys configured with `omr-bumper.sshKey`, and the default keys in `~/.ssh`.
+	pub fn new(config: git2::Config) -> Self {
+		let home = std::env::var("HOME").unwrap_or_default();
+		let mut keys = Vec::new();
+		if let Ok(mut entries) = config.multivar("omr-bumper.sshkey", None) {
+			while let Some(Ok(entry)) = entries.next() {
+				if let Some(key) = entry.value() {
+					keys.push(expand_home(key, &home));
+				}
+			}
+		}
+		for key in DEFAULT_KEYS {
+			keys.push(Path::new(&home).join(".ssh").join(key));
+		}
+		Self {
+			config,
+			keys,
+			askpass: std::env::var("GIT_ASKPASS").ok().filter(|a| !a.is_empty()),
+			attempts: 0,
+			tried: Vec::new(),
+		}
+	}
+
+	/// For `RemoteCallbacks::credentials`.
+	pub fn credentials(
+		&mut self,
+		url: &str,
+		username_from_url: Option<&str>,
+		allowed_types: CredentialType,
+	) -> Result<Cred, git2::Error> {
+		self.attempts += 1;
+		if self.attempts > MAX_ATTEMPTS {
+			return Err(git2::Error::from_str(&format!(
+				"Authentication for {} failed {} times, giving up",
+				url, MAX_ATTEMPTS
+			)));
+		}
+		while let Some(method) = self.next_method(username_from_url, allowed_types) {
+			tracing::debug!("Authenticating for {} with {:?}", url, &method);
+			match self.cred(url, username_from_url, &method) {
+				Ok(cred) => return Ok(cred),
+				Err(e) => tracing::debug!("{:?} failed: {}", &method, e),
+			}
+		}
+		Err(git2::Error::from_str(&format!(
+			"No credentials left to try for {} (ssh-agent, ssh keys, credential helper, GIT_ASKPASS, default)",
+			url
+		)))
+	}
+
+	/// The next method that wasn't tried yet, and is allowed by the server.
+	fn next_method(
+		&mut self,
+		username_from_url: Option<&str>,
+		allowed_types: CredentialType,
+	) -> Option<Method> {
+		let username = username_from_url.unwrap_or("git").to_string();
+		let mut candidates = Vec::new();
+		if allowed_types.contains(CredentialType::USERNAME) {
+			candidates.push(Method::Username(username.clone()));
+		}
+		if allowed_types.contains(CredentialType::SSH_KEY) {
+			candidates.push(Method::SshAgent(username.clone()));
+			for key in self.keys.iter().filter(|k| k.exists()) {
+				let mut public = key.clone().into_os_string();
+				public.push(".pub");
+				let public = PathBuf::from(public);
+				candidates.push(Method::SshKey {
+					username: username.clone(),
+					private:  key.clone(),
+					public:   public.exists().then_some(public),
+				});
+			}
+		}
+		if allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) {
+			candidates.push(Method::CredentialHelper);
+			if let Some(askpass) = &self.askpass {
+				candidates.push(Method::AskPass(askpass.clone()));
+			}
+		}
+		if allowed_types.contains(CredentialType::DEFAULT) {
+			candidates.push(Method::Default);
+		}
+
+		let method = candidates.into_iter().find(|c| !self.tried.contains(c))?;
+		self.tried.push(method.clone());
+		Some(method)
+	}
+
+	fn cred(
+		&self,
+		url: &str,
+		username_from_url: Option<&str>,
+		method: &Method,
+	) -> Result<Cred, git2::Error> {
+		match method {
+			Method::Username(username) => Cred::username(username),
+			Method::SshAgent(username) => Cred::ssh_key_from_agent(username),
+			Method::SshKey {
+				username,
+				private,
+				public,
+			} => Cred::ssh_key(username, public.as_deref(), private, None),
+			Method::CredentialHelper => {
+				Cred::credential_helper(&self.config, url, username_from_url)
+			},
+			Method::AskPass(askpass) => {
+				let username = match username_from_url {
+					Some(username) => username.to_string(),
+					None => ask(askpass, &format!("Username for '{}': ", url))?,
+				};
+				let password = ask(askpass, &format!("Password for '{}': ", url))?;
+				Cred::userpass_plaintext(&username, &password)
+			},
+			Method::Default => Cred::default(),
+		}
+	}
+}
+
+/// Runs the askpass program with `prompt`, returns the first line it printed.
+fn ask(askpass: &str, prompt: &str) -> Result<String, git2::Error> {
+	let output = match Command::new(askpass).arg(prompt).output() {
+		Ok(output) if output.status.success() => output,
+		Ok(output) => {
+			return Err(git2::Error::from_str(&format!(
+				"{} failed with {}",
+				askpass, output.status
+			)))
+		},
+		Err(e) => {
+			return Err(git2::Error::from_str(&format!(
+				"Couldn't run {}: {}",
+				askpass, e
+			)))
+		},
+	};
+	let answer = String::from_utf8_lossy(&output.stdout);
+	Ok(answer.lines().next().unwrap_or_default().to_string())
+}
+
+fn expand_home(path: &str, home: &str) -> PathBuf {
+	match path.strip_prefix("~/") {
+		Some(rest) => Path::new(home).join(rest),
+		None => PathBuf::from(path),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn chain(keys: &[PathBuf], askpass: Option<&str>) -> CredentialChain {
+		CredentialChain {
+			config:   git2::Config::new().unwrap(),
+			keys:     keys.to_vec(),
+			askpass:  askpass.map(|a| a.to_string()),
+			attempts: 0,
+			tried:    Vec::new(),
+		}
+	}
+
+	#[test]
+	fn ssh_tries_agent_then_existing_keys() {
+		let dir = tempfile::tempdir().unwrap();
+		let ed25519 = dir.path().join("id_ed25519");
+		let rsa = dir.path().join("id_rsa");
+		std::fs::write(&ed25519, "").unwrap();
+		std::fs::write(dir.path().join("id_ed25519.pub"), "").unwrap();
+		std::fs::write(&rsa, "").unwrap();
+		let missing = dir.path().join("id_ecdsa");
+		let mut chain = chain(&[ed25519.clone(), missing, rsa.clone()], None);
+
+		let mut methods = Vec::new();
+		while let Some(m) = chain.next_method(Some("deploy"), CredentialType::SSH_KEY) {
+			methods.push(m);
+		}
+		assert_eq!(
+			methods,
+			[
+				Method::SshAgent("deploy".to_string()),
+				Method::SshKey {
+					username: "deploy".to_string(),
+					private:  ed25519,
+					public:   Some(dir.path().join("id_ed25519.pub")),
+				},
+				Method::SshKey {
+					username: "deploy".to_string(),
+					private:  rsa,
+					public:   None,
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn https_tries_helper_askpass_then_default() {
+		let mut chain = chain(&[], Some("/usr/bin/askpass"));
+		let allowed = CredentialType::USER_PASS_PLAINTEXT | CredentialType::DEFAULT;
+
+		let mut methods = Vec::new();
+		while let Some(m) = chain.next_method(None, allowed) {
+			methods.push(m);
+		}
+		assert_eq!(
+			methods,
+			[
+				Method::CredentialHelper,
+				Method::AskPass("/usr/bin/askpass".to_string()),
+				Method::Default,
+			]
+		);
+	}
+
+	#[test]
+	fn gives_up() {
+		let mut chain = chain(&[], None);
+		let url = "ssh://example.com/repo.git";
+
+		// the username is sent once, then only the agent is left
+		assert!(chain
+			.credentials(url, None, CredentialType::USERNAME)
+			.is_ok());
+		let err = chain
+			.credentials(url, None, CredentialType::USERNAME)
+			.err()
+			.unwrap();
+		assert!(err.message().starts_with("No credentials left to try"));
+
+		for _ in chain.attempts..MAX_ATTEMPTS {
+			let _ = chain.credentials(url, Some("git"), CredentialType::SSH_KEY);
+		}
+		let err = chain
+			.credentials(url, Some("git"), CredentialType::SSH_KEY)
+			.err()
+			.unwrap();
+		assert_eq!(
+			err.message(),
+			"Authentication for ssh://example.com/repo.git failed 10 times, giving up"
+		);
+	}
+}
@@ -0,0 +1,239 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Where the human readable status output goes, see `Release::set_status_to_stderr`.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct StatusSink {
+	to_stderr: bool,
+}
+
+impl StatusSink {
+	/// Sends the output to stderr if `to_stderr`, e.g. while stdout carries json events.
+	pub fn new(to_stderr: bool) -> Self {
+		Self { to_stderr }
+	}
+
+	pub fn print(self, args: std::fmt::Arguments) {
+		if self.to_stderr {
+			eprintln!("{}", args);
+		} else {
+			println!("{}", args);
+		}
+	}
+}
+
+/// Like `println!`, but to the given `StatusSink`.
+macro_rules! status {
+	($sink:expr, $($arg:tt)*) => {
+		$sink.print(format_args!($($arg)*))
+	};
+}
+
+/// Progress of a release, see `Release::set_event_sender`.
+///
+/// Serializes to one json object with an `event` field, e.g. `{"event":"step_started",...}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+	StepStarted {
+		index: usize,
+		step:  String,
+	},
+	StepSucceeded {
+		index:       usize,
+		step:        String,
+		duration_ms: u64,
+		details:     Map<String, Value>,
+	},
+	/// The step didn't run, e.g. because it completed before `--resume`, or the release stopped.
+	StepSkipped {
+		index:  usize,
+		step:   String,
+		reason: String,
+	},
+	StepFailed {
+		index:       usize,
+		step:        String,
+		duration_ms: u64,
+		error:       String,
+		details:     Map<String, Value>,
+	},
+	/// One per step of a dry-run.
+	StepPlanned {
+		index:   usize,
+		step:    String,
+		actions: Vec<String>,
+	},
+	/// Always the last event.
+	Summary {
+		status:          Status,
+		/// The version that was committed for release, or tagged.
+		release_version: String,
+		/// The version of the last `ManifestPrintVersion`, e.g. the next dev version.
+		version:         String,
+		tags:            Vec<String>,
+		completed_steps: usize,
+		total_steps:     usize,
+		duration_ms:     u64,
+		error:           Option<String>,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+	Success,
+	/// A step asked to stop, e.g. because the repository is dirty.
+	Stopped,
+	Failed,
+	DryRun,
+}
+
+/// Details a step reports about what it did, e.g. the version, or the created tags.
+#[derive(Debug, Default)]
+pub(crate) struct Details {
+	values: Map<String, Value>,
+}
+
+impl Details {
+	pub fn set(&mut self, key: &str, value: impl Into<Value>) {
+		self.values.insert(key.to_string(), value.into());
+	}
+
+	/// Appends to the array at `key`.
+	pub fn push(&mut self, key: &str, value: impl Into<Value>) {
+		match self.values.get_mut(key) {
+			Some(Value::Array(a)) => a.push(value.into()),
+			_ => {
+				self.values
+					.insert(key.to_string(), Value::Array(vec![value.into()]));
+			},
+		}
+	}
+
+	pub fn take(&mut self) -> Map<String, Value> {
+		std::mem::take(&mut self.values)
+	}
+}
+
+pub(crate) fn millis(duration: Duration) -> u64 {
+	duration.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::release::{Command, Release};
+	use crate::vcs_fake::FakeVcs;
+
+	/// Runs `command` on a fresh project, returns the result, and every event.
+	fn run(command: Command, fake: impl FnOnce(&FakeVcs)) -> (anyhow::Result<()>, Vec<Event>) {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+		std::fs::create_dir_all(dir.path().join("src")).unwrap();
+		std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+		std::fs::write(
+			dir.path().join("Cargo.toml"),
+			"[package]\nname = \"a\"\nversion = \"0.1.0-dev\"\nedition = \"2021\"\n",
+		)
+		.unwrap();
+		std::fs::write(dir.path().join("Cargo.lock"), "").unwrap();
+		let vcs = FakeVcs::new(&dir.path().join(".git"));
+		fake(&vcs);
+
+		let (sender, receiver) = std::sync::mpsc::channel();
+		let mut release = Release::new();
+		release.set_path(dir.path().to_str().unwrap());
+		release.set_vcs_backend(Box::new(vcs));
+		release.load_config().unwrap();
+		release.set_command(command);
+		release.set_event_sender(sender);
+		let result = release.run();
+		drop(release);
+		(result, receiver.into_iter().collect())
+	}
+
+	/// `started 0`, `succeeded 0`, ... `summary success`.
+	fn names(events: &[Event]) -> Vec<String> {
+		events
+			.iter()
+			.map(|e| match e {
+				Event::StepStarted { index, .. } => format!("started {}", index),
+				Event::StepSucceeded { index, .. } => format!("succeeded {}", index),
+				Event::StepSkipped { index, .. } => format!("skipped {}", index),
+				Event::StepFailed { index, .. } => format!("failed {}", index),
+				Event::StepPlanned { index, .. } => format!("planned {}", index),
+				Event::Summary { status, .. } => format!("summary {:?}", status),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn every_step_starts_and_succeeds() {
+		let (result, events) = run(Command::Bump, |_| {});
+		result.unwrap();
+		let mut expected = Vec::new();
+		for i in 0..5 {
+			expected.push(format!("started {}", i));
+			expected.push(format!("succeeded {}", i));
+		}
+		expected.push("summary Success".to_string());
+		assert_eq!(names(&events), expected);
+
+		let Some(Event::Summary {
+			version,
+			completed_steps,
+			total_steps,
+			error,
+			..
+		}) = events.last()
+		else {
+			panic!("the summary is last");
+		};
+		assert_eq!(version, "0.1.1-dev");
+		assert_eq!((*completed_steps, *total_steps), (5, 5));
+		assert_eq!(error, &None);
+	}
+
+	#[test]
+	fn failed_release_ends_with_a_summary() {
+		let (result, events) = run(Command::Release, |fake| fake.fail_on("commit"));
+		let err = result.unwrap_err().to_string();
+
+		let failed = events
+			.iter()
+			.position(|e| matches!(e, Event::StepFailed { step, .. } if step == "GitCommitManifest(BumpVersionForRelease)"))
+			.unwrap();
+		// nothing after the failed step but the summary
+		assert_eq!(failed, events.len() - 2);
+		match events.last() {
+			Some(Event::Summary {
+				status: Status::Failed,
+				error: Some(error),
+				..
+			}) => assert_eq!(*error, err),
+			e => panic!("not a summary of a failure {:?}", e),
+		}
+		let json = serde_json::to_value(events.last().unwrap()).unwrap();
+		assert_eq!(json["event"], "summary");
+		assert_eq!(json["status"], "failed");
+	}
+
+	#[test]
+	fn details_push_appends() {
+		let mut details = Details::default();
+		details.push("tags", "a-v0.1.0");
+		details.push("tags", "b-v0.2.0");
+		details.set("version", "0.1.0");
+		// `push` replaces what isn't an array
+		details.push("version", "0.2.0");
+		let values = Value::Object(details.take());
+		assert_eq!(
+			values,
+			serde_json::json!({ "tags": ["a-v0.1.0", "b-v0.2.0"], "version": ["0.2.0"] })
+		);
+		assert_eq!(details.take(), Map::new());
+	}
+}
@@ -0,0 +1,442 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::bail;
+
+/// The state of a file in the index, or the worktree, the `X`, and `Y` of `git status`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+	#[default]
+	Unmodified,
+	Modified,
+	TypeChanged,
+	Added,
+	Deleted,
+	Renamed,
+	Copied,
+	/// Updated, but unmerged.
+	Unmerged,
+}
+
+impl FileState {
+	pub fn from_char(c: char) -> anyhow::Result<Self> {
+		Ok(match c {
+			'.' | ' ' => FileState::Unmodified,
+			'M' => FileState::Modified,
+			'T' => FileState::TypeChanged,
+			'A' => FileState::Added,
+			'D' => FileState::Deleted,
+			'R' => FileState::Renamed,
+			'C' => FileState::Copied,
+			'U' => FileState::Unmerged,
+			o => bail!("Unknown file state {:?}", o),
+		})
+	}
+
+	pub fn as_char(&self) -> char {
+		match self {
+			FileState::Unmodified => '.',
+			FileState::Modified => 'M',
+			FileState::TypeChanged => 'T',
+			FileState::Added => 'A',
+			FileState::Deleted => 'D',
+			FileState::Renamed => 'R',
+			FileState::Copied => 'C',
+			FileState::Unmerged => 'U',
+		}
+	}
+
+	pub fn is_changed(&self) -> bool {
+		*self != FileState::Unmodified
+	}
+}
+
+/// What changed inside a submodule.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubmoduleState {
+	/// The checked out commit differs from the recorded one.
+	pub commit_changed: bool,
+	/// Tracked files are modified.
+	pub modified:       bool,
+	pub untracked:      bool,
+}
+
+impl SubmoduleState {
+	/// Parses the `<sub>` field, `N...` for no submodule, or `S<c><m><u>`.
+	fn parse(field: &str) -> anyhow::Result<Option<Self>> {
+		let chars: Vec<char> = field.chars().collect();
+		match chars.as_slice() {
+			['N', '.', '.', '.'] => Ok(None),
+			['S', c, m, u] => Ok(Some(Self {
+				commit_changed: *c == 'C',
+				modified:       *m == 'M',
+				untracked:      *u == 'U',
+			})),
+			_ => bail!("Invalid submodule state {:?}", field),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+	Tracked,
+	/// A merge conflict.
+	Unmerged,
+	Untracked,
+	Ignored,
+}
+
+/// One file in `git status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+	pub kind:      EntryKind,
+	pub path:      String,
+	pub index:     FileState,
+	pub worktree:  FileState,
+	/// The original path of a rename, or copy.
+	pub source:    Option<String>,
+	pub submodule: Option<SubmoduleState>,
+}
+
+impl StatusEntry {
+	pub fn new(kind: EntryKind, path: &str) -> Self {
+		Self {
+			kind,
+			path: path.to_string(),
+			index: FileState::Unmodified,
+			worktree: FileState::Unmodified,
+			source: None,
+			submodule: None,
+		}
+	}
+
+	/// A tracked file with changes in the `index`, and/or the `worktree`.
+	pub fn tracked(path: &str, index: FileState, worktree: FileState) -> Self {
+		Self {
+			index,
+			worktree,
+			..Self::new(EntryKind::Tracked, path)
+		}
+	}
+
+	pub fn is_staged(&self) -> bool {
+		self.kind == EntryKind::Tracked && self.index.is_changed()
+	}
+
+	pub fn is_unstaged(&self) -> bool {
+		self.kind == EntryKind::Tracked && self.worktree.is_changed()
+	}
+
+	/// Whether this entry makes the repository dirty, according to `policy`.
+	pub fn is_dirty(&self, policy: DirtyPolicy) -> bool {
+		match self.kind {
+			EntryKind::Unmerged => return true,
+			EntryKind::Ignored => return false,
+			EntryKind::Untracked => return policy == DirtyPolicy::Strict,
+			EntryKind::Tracked => {},
+		}
+		if let Some(s) = self.submodule {
+			return match policy {
+				DirtyPolicy::IgnoreSubmodules => false,
+				DirtyPolicy::Tracked => s.commit_changed || s.modified || self.index.is_changed(),
+				DirtyPolicy::Strict => true,
+			};
+		}
+		self.is_staged() || self.is_unstaged()
+	}
+}
+
+impl fmt::Display for StatusEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.kind {
+			EntryKind::Untracked => write!(f, "?? {}", self.path)?,
+			EntryKind::Ignored => write!(f, "!! {}", self.path)?,
+			_ => write!(
+				f,
+				"{}{} {}",
+				self.index.as_char(),
+				self.worktree.as_char(),
+				self.path
+			)?,
+		}
+		if let Some(source) = &self.source {
+			write!(f, " (from {})", source)?;
+		}
+		Ok(())
+	}
+}
+
+/// The current branch, and how it relates to its upstream.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BranchStatus {
+	/// The commit of HEAD, `None` before the first commit.
+	pub oid:      Option<String>,
+	/// The branch name, `None` if HEAD is detached.
+	pub head:     Option<String>,
+	/// e.g. `origin/main`
+	pub upstream: Option<String>,
+	pub ahead:    usize,
+	pub behind:   usize,
+}
+
+/// The output of `git status`, see `GitStatus::parse_porcelain_v2`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GitStatus {
+	pub branch:  BranchStatus,
+	pub entries: Vec<StatusEntry>,
+}
+
+impl GitStatus {
+	/// Parses `git status --porcelain=v2 -z --branch`.
+	pub fn parse_porcelain_v2(output: &str) -> anyhow::Result<Self> {
+		let mut status = GitStatus::default();
+		let mut records = output.split('\0').filter(|r| !r.is_empty());
+		while let Some(record) = records.next() {
+			let Some((tag, rest)) = record.split_once(' ') else {
+				bail!("Invalid status record {:?}", record);
+			};
+			match tag {
+				"#" => status.parse_header(rest)?,
+				"1" => {
+					let fields: Vec<&str> = rest.splitn(8, ' ').collect();
+					let [xy, sub, _, _, _, _, _, path] = fields[..] else {
+						bail!("Invalid status record {:?}", record);
+					};
+					status
+						.entries
+						.push(Self::tracked(EntryKind::Tracked, xy, sub, path)?);
+				},
+				"2" => {
+					let fields: Vec<&str> = rest.splitn(9, ' ').collect();
+					let [xy, sub, _, _, _, _, _, _score, path] = fields[..] else {
+						bail!("Invalid status record {:?}", record);
+					};
+					let mut entry = Self::tracked(EntryKind::Tracked, xy, sub, path)?;
+					match records.next() {
+						Some(source) => entry.source = Some(source.to_string()),
+						None => bail!("Missing source path of {:?}", record),
+					}
+					status.entries.push(entry);
+				},
+				"u" => {
+					let fields: Vec<&str> = rest.splitn(10, ' ').collect();
+					let [xy, sub, _, _, _, _, _, _, _, path] = fields[..] else {
+						bail!("Invalid status record {:?}", record);
+					};
+					status
+						.entries
+						.push(Self::tracked(EntryKind::Unmerged, xy, sub, path)?);
+				},
+				"?" => status
+					.entries
+					.push(StatusEntry::new(EntryKind::Untracked, rest)),
+				"!" => status
+					.entries
+					.push(StatusEntry::new(EntryKind::Ignored, rest)),
+				o => bail!("Unknown status record type {:?}", o),
+			}
+		}
+		Ok(status)
+	}
+
+	fn parse_header(&mut self, header: &str) -> anyhow::Result<()> {
+		let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+		match key {
+			"branch.oid" if value != "(initial)" => self.branch.oid = Some(value.to_string()),
+			"branch.head" if value != "(detached)" => self.branch.head = Some(value.to_string()),
+			"branch.upstream" => self.branch.upstream = Some(value.to_string()),
+			"branch.ab" => {
+				let Some((ahead, behind)) = value.split_once(' ') else {
+					bail!("Invalid branch.ab {:?}", value);
+				};
+				self.branch.ahead = ahead.trim_start_matches('+').parse()?;
+				self.branch.behind = behind.trim_start_matches('-').parse()?;
+			},
+			// e.g. stash
+			_ => {},
+		}
+		Ok(())
+	}
+
+	fn tracked(kind: EntryKind, xy: &str, sub: &str, path: &str) -> anyhow::Result<StatusEntry> {
+		let mut chars = xy.chars();
+		let (Some(x), Some(y)) = (chars.next(), chars.next()) else {
+			bail!("Invalid XY {:?}", xy);
+		};
+		Ok(StatusEntry {
+			index: FileState::from_char(x)?,
+			worktree: FileState::from_char(y)?,
+			submodule: SubmoduleState::parse(sub)?,
+			..StatusEntry::new(kind, path)
+		})
+	}
+
+	/// The entries that make the repository dirty.
+	pub fn dirty(&self, policy: DirtyPolicy) -> Vec<&StatusEntry> {
+		self.entries.iter().filter(|e| e.is_dirty(policy)).collect()
+	}
+
+	pub fn untracked(&self) -> Vec<&StatusEntry> {
+		self.entries
+			.iter()
+			.filter(|e| e.kind == EntryKind::Untracked)
+			.collect()
+	}
+}
+
+/// Which changes make a repository dirty. Merge conflicts always do, ignored files never.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DirtyPolicy {
+	/// Staged, and unstaged changes of tracked files, and submodules with other commits,
+	/// or modified files.
+	#[default]
+	Tracked,
+	/// Everything, including untracked files.
+	Strict,
+	/// Like `Tracked`, but changes in submodules are fine.
+	IgnoreSubmodules,
+}
+
+impl fmt::Display for DirtyPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DirtyPolicy::Tracked => write!(f, "tracked"),
+			DirtyPolicy::Strict => write!(f, "strict"),
+			DirtyPolicy::IgnoreSubmodules => write!(f, "ignore-submodules"),
+		}
+	}
+}
+
+impl FromStr for DirtyPolicy {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		match s {
+			"tracked" => Ok(DirtyPolicy::Tracked),
+			"strict" => Ok(DirtyPolicy::Strict),
+			"ignore-submodules" => Ok(DirtyPolicy::IgnoreSubmodules),
+			o => bail!(
+				"Unknown dirty policy {:?}, should be tracked/strict/ignore-submodules",
+				o
+			),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_porcelain_v2() {
+		let output = [
+			"# branch.oid 1234567890abcdef1234567890abcdef12345678",
+			"# branch.head main",
+			"# branch.upstream origin/main",
+			"# branch.ab +2 -1",
+			"1 .M N... 100644 100644 100644 aaaa aaaa src/main.rs",
+			"1 M. N... 100644 100644 100644 aaaa bbbb Cargo.toml",
+			"1 A. N... 000000 100644 100644 0000 bbbb new file.rs",
+			"1 .D N... 100644 100644 000000 aaaa aaaa gone.rs",
+			"1 T. N... 100644 120000 120000 aaaa bbbb link",
+			"2 R. N... 100644 100644 100644 aaaa aaaa R100 renamed.rs",
+			"old.rs",
+			"u UU N... 100644 100644 100644 100644 aaaa bbbb cccc conflict.rs",
+			"1 .M SC.. 160000 160000 160000 aaaa aaaa vendor/lib",
+			"? untracked.txt",
+			"! target/",
+			"",
+		]
+		.join("\0");
+		let status = GitStatus::parse_porcelain_v2(&output).unwrap();
+
+		assert_eq!(
+			status.branch,
+			BranchStatus {
+				oid:      Some("1234567890abcdef1234567890abcdef12345678".to_string()),
+				head:     Some("main".to_string()),
+				upstream: Some("origin/main".to_string()),
+				ahead:    2,
+				behind:   1,
+			}
+		);
+		let entries: Vec<String> = status.entries.iter().map(|e| e.to_string()).collect();
+		assert_eq!(
+			entries,
+			[
+				".M src/main.rs",
+				"M. Cargo.toml",
+				"A. new file.rs",
+				".D gone.rs",
+				"T. link",
+				"R. renamed.rs (from old.rs)",
+				"UU conflict.rs",
+				".M vendor/lib",
+				"?? untracked.txt",
+				"!! target/",
+			]
+		);
+		assert_eq!(status.entries[6].kind, EntryKind::Unmerged);
+		assert_eq!(
+			status.entries[7].submodule,
+			Some(SubmoduleState {
+				commit_changed: true,
+				modified:       false,
+				untracked:      false,
+			})
+		);
+	}
+
+	#[test]
+	fn parses_detached_head_without_upstream() {
+		let output = "# branch.oid (initial)\0# branch.head (detached)\0";
+		let status = GitStatus::parse_porcelain_v2(output).unwrap();
+
+		assert_eq!(status.branch, BranchStatus::default());
+		assert!(status.entries.is_empty());
+	}
+
+	#[test]
+	fn staged_changes_are_dirty() {
+		let entry = StatusEntry::tracked("Cargo.toml", FileState::Added, FileState::Unmodified);
+
+		assert!(entry.is_dirty(DirtyPolicy::Tracked));
+		assert!(entry.is_dirty(DirtyPolicy::IgnoreSubmodules));
+	}
+
+	#[test]
+	fn policies() {
+		let untracked = StatusEntry::new(EntryKind::Untracked, "notes.txt");
+		let ignored = StatusEntry::new(EntryKind::Ignored, "target/");
+		let conflict = StatusEntry::new(EntryKind::Unmerged, "conflict.rs");
+		let submodule = |s: SubmoduleState| StatusEntry {
+			submodule: Some(s),
+			..StatusEntry::tracked("vendor/lib", FileState::Unmodified, FileState::Modified)
+		};
+		let sub_untracked = submodule(SubmoduleState {
+			untracked: true,
+			..Default::default()
+		});
+		let sub_modified = submodule(SubmoduleState {
+			modified: true,
+			..Default::default()
+		});
+
+		for (entry, tracked, strict, ignore_submodules) in [
+			(&untracked, false, true, false),
+			(&ignored, false, false, false),
+			(&conflict, true, true, true),
+			(&sub_untracked, false, true, false),
+			(&sub_modified, true, true, false),
+		] {
+			assert_eq!(entry.is_dirty(DirtyPolicy::Tracked), tracked, "{}", entry);
+			assert_eq!(entry.is_dirty(DirtyPolicy::Strict), strict, "{}", entry);
+			assert_eq!(
+				entry.is_dirty(DirtyPolicy::IgnoreSubmodules),
+				ignore_submodules,
+				"{}",
+				entry
+			);
+		}
+	}
+}
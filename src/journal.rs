@@ -0,0 +1,392 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use toml_edit::{value, Array, ArrayOfTables, Document, Item, Table};
+
+use crate::manifest::ManifestState;
+
+/// Persisted progress of a release, written after every completed step.
+///
+/// Lives in `.git/omr-bumper/journal.toml`, and is removed once the release finished.
+#[derive(Debug, Default, Clone)]
+pub struct Journal {
+	path: PathBuf,
+	steps: Vec<String>,
+	completed: usize,
+	release_version: String,
+	head: String,
+	package_versions: Vec<(String, String)>,
+	files: Vec<(PathBuf, String)>,
+	manifests: Vec<ManifestState>,
+	changelog: Option<PathBuf>,
+	date: String,
+	previous_version: String,
+	previous_package_versions: Vec<(String, String)>,
+	bump_level: Option<String>,
+}
+
+impl Journal {
+	pub fn new(git_dir: &Path) -> Self {
+		Self {
+			path: git_dir.join("omr-bumper").join("journal.toml"),
+			..Default::default()
+		}
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn exists(&self) -> bool {
+		self.path.exists()
+	}
+
+	pub fn load(&mut self) -> anyhow::Result<()> {
+		let toml = std::fs::read_to_string(&self.path)?;
+		let doc = match toml.parse::<Document>() {
+			Ok(doc) => doc,
+			Err(e) => bail!("Couldn't load journal from >>{:?}<< {}", &self.path, &e),
+		};
+
+		let completed = match doc.get("completed").and_then(|c| c.as_integer()) {
+			Some(c) if c >= 0 => c as usize,
+			_ => bail!("Journal {:?} has no valid `completed`", &self.path),
+		};
+		let steps = match doc.get("steps").and_then(|s| s.as_array()) {
+			Some(a) => a
+				.iter()
+				.map(|s| s.as_str().unwrap_or_default().to_string())
+				.collect(),
+			None => bail!("Journal {:?} has no `steps`", &self.path),
+		};
+		let get_str = |key: &str| -> anyhow::Result<String> {
+			match doc.get(key).and_then(|s| s.as_str()) {
+				Some(s) => Ok(s.to_string()),
+				None => bail!("Journal {:?} has no `{}`", &self.path, key),
+			}
+		};
+
+		self.steps = steps;
+		self.completed = completed;
+		self.release_version = get_str("release_version")?;
+		self.head = get_str("head")?;
+		self.date = get_str("date")?;
+		self.previous_version = get_str("previous_version")?;
+		self.bump_level = get_str("bump_level").ok();
+		self.changelog = doc
+			.get("changelog")
+			.and_then(|c| c.as_str())
+			.map(PathBuf::from);
+
+		let tables = |key: &str| -> Vec<&Table> {
+			match doc.get(key).and_then(|t| t.as_array_of_tables()) {
+				Some(a) => a.iter().collect(),
+				None => Vec::new(),
+			}
+		};
+		let mut files = Vec::new();
+		for t in tables("file") {
+			match (
+				t.get("path").and_then(|p| p.as_str()),
+				t.get("contents").and_then(|c| c.as_str()),
+			) {
+				(Some(p), Some(c)) => files.push((PathBuf::from(p), c.to_string())),
+				_ => bail!("Journal {:?} has an invalid `file`", &self.path),
+			}
+		}
+		let mut manifests = Vec::new();
+		for t in tables("manifest") {
+			match (
+				t.get("path").and_then(|p| p.as_str()),
+				t.get("contents").and_then(|c| c.as_str()),
+			) {
+				(Some(p), Some(c)) => manifests.push(ManifestState {
+					path:     PathBuf::from(p),
+					contents: c.to_string(),
+					modified: t.get("modified").and_then(|m| m.as_bool()).unwrap_or(false),
+				}),
+				_ => bail!("Journal {:?} has an invalid `manifest`", &self.path),
+			}
+		}
+		let package_versions = |key: &str| -> anyhow::Result<Vec<(String, String)>> {
+			let mut package_versions = Vec::new();
+			for t in tables(key) {
+				match (
+					t.get("name").and_then(|n| n.as_str()),
+					t.get("version").and_then(|v| v.as_str()),
+				) {
+					(Some(n), Some(v)) => package_versions.push((n.to_string(), v.to_string())),
+					_ => bail!("Journal {:?} has an invalid `{}`", &self.path, key),
+				}
+			}
+			Ok(package_versions)
+		};
+		self.package_versions = package_versions("package_version")?;
+		self.previous_package_versions = package_versions("previous_package_version")?;
+		self.files = files;
+		self.manifests = manifests;
+
+		Ok(())
+	}
+
+	pub fn save(&self) -> anyhow::Result<()> {
+		let mut doc = Document::new();
+		let mut steps = Array::new();
+		for s in self.steps.iter() {
+			steps.push(s.as_str());
+		}
+		doc["steps"] = value(steps);
+		doc["completed"] = value(self.completed as i64);
+		doc["release_version"] = value(&self.release_version);
+		doc["head"] = value(&self.head);
+		doc["date"] = value(&self.date);
+		doc["previous_version"] = value(&self.previous_version);
+		if let Some(bump_level) = &self.bump_level {
+			doc["bump_level"] = value(bump_level);
+		}
+		if let Some(changelog) = &self.changelog {
+			doc["changelog"] = value(changelog.display().to_string());
+		}
+
+		let package_versions = |versions: &[(String, String)]| {
+			let mut package_versions = ArrayOfTables::new();
+			for (name, version) in versions.iter() {
+				let mut t = Table::new();
+				t["name"] = value(name);
+				t["version"] = value(version);
+				package_versions.push(t);
+			}
+			Item::ArrayOfTables(package_versions)
+		};
+		doc["package_version"] = package_versions(&self.package_versions);
+		doc["previous_package_version"] = package_versions(&self.previous_package_versions);
+
+		let mut files = ArrayOfTables::new();
+		for (path, contents) in self.files.iter() {
+			let mut t = Table::new();
+			t["path"] = value(path.display().to_string());
+			t["contents"] = value(contents);
+			files.push(t);
+		}
+		doc["file"] = Item::ArrayOfTables(files);
+
+		let mut manifests = ArrayOfTables::new();
+		for m in self.manifests.iter() {
+			let mut t = Table::new();
+			t["path"] = value(m.path.display().to_string());
+			t["contents"] = value(&m.contents);
+			t["modified"] = value(m.modified);
+			manifests.push(t);
+		}
+		doc["manifest"] = Item::ArrayOfTables(manifests);
+
+		if let Some(parent) = self.path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		std::fs::write(&self.path, doc.to_string())?;
+		Ok(())
+	}
+
+	pub fn remove(&self) -> anyhow::Result<()> {
+		if self.path.exists() {
+			std::fs::remove_file(&self.path)?;
+		}
+		Ok(())
+	}
+
+	pub fn steps(&self) -> &[String] {
+		&self.steps
+	}
+
+	pub fn set_steps(&mut self, steps: Vec<String>) {
+		self.steps = steps;
+	}
+
+	pub fn completed(&self) -> usize {
+		self.completed
+	}
+
+	pub fn set_completed(&mut self, completed: usize) {
+		self.completed = completed;
+	}
+
+	pub fn release_version(&self) -> &str {
+		&self.release_version
+	}
+
+	pub fn set_release_version(&mut self, release_version: &str) {
+		self.release_version = release_version.to_string();
+	}
+
+	pub fn head(&self) -> &str {
+		&self.head
+	}
+
+	pub fn set_head(&mut self, head: &str) {
+		self.head = head.to_string();
+	}
+
+	/// Versions of independently versioned packages.
+	pub fn package_versions(&self) -> &[(String, String)] {
+		&self.package_versions
+	}
+
+	pub fn set_package_versions(&mut self, package_versions: Vec<(String, String)>) {
+		self.package_versions = package_versions;
+	}
+
+	/// Files as they were on disk when the journal was written.
+	pub fn files(&self) -> &[(PathBuf, String)] {
+		&self.files
+	}
+
+	pub fn set_files(&mut self, files: Vec<(PathBuf, String)>) {
+		self.files = files;
+	}
+
+	/// The in-memory manifests, which might contain changes that were not saved yet.
+	pub fn manifests(&self) -> &[ManifestState] {
+		&self.manifests
+	}
+
+	pub fn set_manifests(&mut self, manifests: Vec<ManifestState>) {
+		self.manifests = manifests;
+	}
+
+	/// The day the release started, for `{date}` in tag names.
+	pub fn date(&self) -> &str {
+		&self.date
+	}
+
+	pub fn set_date(&mut self, date: &str) {
+		self.date = date.to_string();
+	}
+
+	/// The version as of the last release commit, for `{old_version}` in commit messages.
+	pub fn previous_version(&self) -> &str {
+		&self.previous_version
+	}
+
+	pub fn set_previous_version(&mut self, previous_version: &str) {
+		self.previous_version = previous_version.to_string();
+	}
+
+	/// Versions of independently versioned packages as of the last release commit.
+	pub fn previous_package_versions(&self) -> &[(String, String)] {
+		&self.previous_package_versions
+	}
+
+	pub fn set_previous_package_versions(
+		&mut self,
+		previous_package_versions: Vec<(String, String)>,
+	) {
+		self.previous_package_versions = previous_package_versions;
+	}
+
+	/// The level of the last bump, e.g. `minor`.
+	pub fn bump_level(&self) -> Option<&str> {
+		self.bump_level.as_deref()
+	}
+
+	pub fn set_bump_level(&mut self, bump_level: Option<String>) {
+		self.bump_level = bump_level;
+	}
+
+	/// The changelog written for the next release commit.
+	pub fn changelog(&self) -> Option<&Path> {
+		self.changelog.as_deref()
+	}
+
+	pub fn set_changelog(&mut self, changelog: Option<PathBuf>) {
+		self.changelog = changelog;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut journal = Journal::new(dir.path());
+		journal.set_steps(vec![
+			"GitPreflight".to_string(),
+			"GitTag(v{version})".to_string(),
+		]);
+		journal.set_completed(1);
+		journal.set_release_version("0.2.0-alpha");
+		journal.set_head("4f012a5c0ffee");
+		journal.set_package_versions(vec![("a".to_string(), "0.2.0-alpha".to_string())]);
+		journal.set_files(vec![(
+			PathBuf::from("Cargo.toml"),
+			"version = \"0.2.0-alpha\"\n".to_string(),
+		)]);
+		journal.set_manifests(vec![ManifestState {
+			path:     PathBuf::from("a/Cargo.toml"),
+			contents: "[package]\nname = \"a\"\n".to_string(),
+			modified: true,
+		}]);
+		journal.set_changelog(Some(PathBuf::from("CHANGELOG.md")));
+		journal.set_date("2024-01-31");
+		journal.set_previous_version("0.2.0-dev");
+		journal.set_previous_package_versions(vec![("a".to_string(), "0.2.0-dev".to_string())]);
+		journal.set_bump_level(Some("minor".to_string()));
+		journal.save().unwrap();
+		assert_eq!(
+			journal.path(),
+			dir.path().join("omr-bumper").join("journal.toml")
+		);
+
+		let mut loaded = Journal::new(dir.path());
+		loaded.load().unwrap();
+		assert_eq!(loaded.steps(), journal.steps());
+		assert_eq!(loaded.completed(), 1);
+		assert_eq!(loaded.release_version(), "0.2.0-alpha");
+		assert_eq!(loaded.head(), "4f012a5c0ffee");
+		assert_eq!(loaded.package_versions(), journal.package_versions());
+		assert_eq!(loaded.files(), journal.files());
+		assert_eq!(loaded.manifests().len(), 1);
+		assert_eq!(loaded.manifests()[0].path, journal.manifests()[0].path);
+		assert_eq!(
+			loaded.manifests()[0].contents,
+			journal.manifests()[0].contents
+		);
+		assert!(loaded.manifests()[0].modified);
+		assert_eq!(loaded.changelog(), Some(Path::new("CHANGELOG.md")));
+		assert_eq!(loaded.date(), "2024-01-31");
+		assert_eq!(loaded.previous_version(), "0.2.0-dev");
+		assert_eq!(
+			loaded.previous_package_versions(),
+			journal.previous_package_versions()
+		);
+		assert_eq!(loaded.bump_level(), Some("minor"));
+
+		loaded.remove().unwrap();
+		assert!(!loaded.exists());
+		// nothing to remove is fine
+		loaded.remove().unwrap();
+	}
+
+	#[test]
+	fn invalid_journal() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut journal = Journal::new(dir.path());
+		std::fs::create_dir_all(journal.path().parent().unwrap()).unwrap();
+		std::fs::write(journal.path(), "completed = 3\n").unwrap();
+		let err = journal.load().unwrap_err().to_string();
+		assert!(err.ends_with("has no `steps`"), "{}", err);
+
+		std::fs::write(journal.path(), "steps = []\ncompleted = -1\n").unwrap();
+		let err = journal.load().unwrap_err().to_string();
+		assert!(err.ends_with("has no valid `completed`"), "{}", err);
+
+		std::fs::write(
+			journal.path(),
+			"steps = []\ncompleted = 0\nrelease_version = \"\"\nhead = \"\"\nprevious_version = \"\"\n",
+		)
+		.unwrap();
+		let err = journal.load().unwrap_err().to_string();
+		assert!(err.ends_with("has no `date`"), "{}", err);
+	}
+}
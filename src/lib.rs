@@ -6,12 +6,39 @@
 //!
 //! Sensible pull-requests, and issues welcome at: (https://github.com/AndreasOM/omr-bumper)
 
+#[macro_use]
+mod event;
+pub use event::{Event, Status};
+
 mod release;
-pub use release::Release;
+pub use release::{Command, Release};
 
+mod build_template;
+pub use build_template::BuildTemplate;
 mod cargo;
+mod changelog;
+mod config;
+mod credentials;
+mod git_status;
+pub use git_status::{
+	BranchStatus, DirtyPolicy, EntryKind, FileState, GitStatus, StatusEntry, SubmoduleState,
+};
+mod conventional;
+mod journal;
 mod manifest;
-// #[allow(dead_code)]
-// mod repository;
+mod plan;
+pub use plan::{Plan, PlanEntry};
+mod repository;
 mod repository_cmdgit;
-use repository_cmdgit::Repository;
+mod repository_gix;
+mod rollback;
+mod signing;
+pub use signing::{SignatureFormat, Signing};
+mod step;
+mod tag_template;
+pub use tag_template::TagTemplate;
+mod template;
+mod vcs;
+pub use vcs::{Upstream, Vcs, VcsBackend};
+#[cfg(test)]
+mod vcs_fake;
@@ -12,9 +12,10 @@
 //!		- Bumps the local version patch/minor/major
 //!		- Commits Cargo.toml
 //!		- Pushes to git
+#![allow(clippy::tabs_in_doc_comments)]
 
 use anyhow::bail;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use omr_bumper::Release;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -25,22 +26,118 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 #[command(version = VERSION)]
 #[command(about = "Bump version, and push to git with tag", long_about = None)]
 struct Cli {
-	#[arg(short = 'r', long)]
-	pre_release_suffix: Option<String>,
-	#[arg(short = 'b', long)]
-	bump_level:         Option<String>,
-	#[arg(long)]
-	allow_dirty:        bool,
-	#[arg(long)]
-	skip_git:           bool,
-	#[arg(long)]
-	skip_push:          bool,
-	#[arg(long)]
-	skip_tag:           bool,
-	#[arg(long)]
-	skip_all:           bool,
-	#[arg(long)]
-	path:               Option<String>,
+	#[command(subcommand)]
+	command:                Option<Command>,
+	/// release exactly this version, e.g. 1.0.0, instead of adding the pre-release suffix
+	#[arg(long = "release-version", value_name = "VERSION", conflicts_with_all = ["pre_release_suffix", "pre_release_counter", "stable"], global = true)]
+	target_version:         Option<String>,
+	/// release, or set a version that isn't greater than the latest release tag
+	#[arg(long, global = true)]
+	force:                  bool,
+	#[arg(short = 'r', long, alias = "pre-release", global = true)]
+	pre_release_suffix:     Option<String>,
+	/// release without a pre-release suffix, e.g. 0.3.20
+	#[arg(long, conflicts_with_all = ["pre_release_suffix", "pre_release_counter"], global = true)]
+	stable:                 bool,
+	/// number pre-releases, alpha.1, alpha.2, ..., default: pre_release_counter from config
+	#[arg(long, global = true)]
+	pre_release_counter:    bool,
+	#[arg(short = 'b', long, global = true)]
+	bump_level:             Option<String>,
+	#[arg(long, global = true)]
+	allow_dirty:            bool,
+	/// release from a branch that isn't configured, or a detached HEAD
+	#[arg(long, global = true)]
+	allow_any_branch:       bool,
+	#[arg(long, global = true)]
+	allow_no_upstream:      bool,
+	#[arg(long, global = true)]
+	allow_behind:           bool,
+	#[arg(long, global = true)]
+	allow_diverged:         bool,
+	#[arg(long, global = true)]
+	skip_git:               bool,
+	#[arg(long, global = true)]
+	skip_push:              bool,
+	#[arg(long, global = true)]
+	skip_tag:               bool,
+	#[arg(long, global = true)]
+	skip_all:               bool,
+	#[arg(long, global = true)]
+	dry_run:                bool,
+	#[arg(long, global = true)]
+	resume:                 bool,
+	#[arg(long, global = true)]
+	no_rollback:            bool,
+	#[arg(short = 'p', long = "package", global = true)]
+	packages:               Vec<String>,
+	#[arg(long, global = true)]
+	commit_per_package:     bool,
+	#[arg(long, global = true)]
+	path:                   Option<String>,
+	/// text, or json for one event per line on stdout
+	#[arg(long, global = true)]
+	output:                 Option<String>,
+	/// git-cli, git2, or gix
+	#[arg(long, global = true)]
+	vcs:                    Option<String>,
+	/// tracked, strict, or ignore-submodules
+	#[arg(long, global = true)]
+	dirty_policy:           Option<String>,
+	/// remote to fetch from, and push to, default: the one the branch tracks
+	#[arg(long, global = true)]
+	remote:                 Option<String>,
+	/// branch on the remote to push to, default: the one the branch tracks
+	#[arg(long, global = true)]
+	target_branch:          Option<String>,
+	/// sign the release commits, and tags
+	#[arg(long, global = true)]
+	sign:                   bool,
+	/// gpg key id, or path of the ssh key, default: user.signingKey
+	#[arg(long, global = true)]
+	signing_key:            Option<String>,
+	/// openpgp, or ssh, default: gpg.format
+	#[arg(long, global = true)]
+	signing_format:         Option<String>,
+	/// tag name, e.g. v{version}, placeholders: {version} {major} {minor} {patch} {pre} {build} {package} {date}
+	#[arg(long, global = true)]
+	tag_template:           Option<String>,
+	/// keep the build metadata in {version} of tag names, default: tag_build_metadata from config
+	#[arg(long, global = true)]
+	tag_build_metadata:     bool,
+	/// build metadata for every version set, e.g. g{short_hash}.{date}, placeholders: {short_hash} {hash} {date} {env:NAME}
+	#[arg(long, global = true)]
+	build_metadata:         Option<String>,
+	/// message of the release commit, placeholders: {old_version} {new_version} {bump_level} {suffix} {packages}
+	#[arg(long, global = true)]
+	release_commit_message: Option<String>,
+	/// message of the commit back to dev, same placeholders
+	#[arg(long, global = true)]
+	dev_commit_message:     Option<String>,
+	/// line appended to the commit messages, e.g. "[skip ci]", repeatable
+	#[arg(long = "commit-trailer", global = true)]
+	commit_trailers:        Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// show the version, branch, upstream with ahead/behind, the last release tag, and dirty files
+	Status,
+	/// preview the release without changing anything, like --dry-run
+	Plan,
+	/// run the full release, the default without a subcommand
+	Release,
+	/// only bump the version by --bump-level in the manifests, and the lockfile, without touching git
+	Bump,
+	/// tag HEAD with the version from the manifest, and push the tag
+	Tag,
+	/// revert the last release locally, reset the branch to before it, and delete its tags
+	Undo,
+	/// only set the version in the manifests, and the lockfile, without touching git
+	SetVersion {
+		/// e.g. 1.0.0, or 0.3.19-hotfix.1
+		version: String,
+	},
 }
 
 pub fn main() -> anyhow::Result<()> {
@@ -51,70 +148,327 @@ pub fn main() -> anyhow::Result<()> {
 		.pre_release_suffix
 		.unwrap_or(String::from("alpha"))
 		.to_string();
+	let pre_release_counter = cli.pre_release_counter;
+	let stable = cli.stable;
+	let force = cli.force;
+	let mut dry_run = cli.dry_run;
+	let mut target_version = cli.target_version;
+	let command = match cli.command {
+		None | Some(Command::Release) => omr_bumper::Command::Release,
+		Some(Command::Plan) => {
+			dry_run = true;
+			omr_bumper::Command::Release
+		},
+		Some(Command::Status) => omr_bumper::Command::Status,
+		Some(Command::Bump) => omr_bumper::Command::Bump,
+		Some(Command::Tag) => omr_bumper::Command::Tag,
+		Some(Command::Undo) => omr_bumper::Command::Undo,
+		Some(Command::SetVersion { version }) => {
+			target_version = Some(version);
+			omr_bumper::Command::SetVersion
+		},
+	};
 	let bump_level = cli.bump_level.unwrap_or(String::from("patch")).to_string();
 	let allow_dirty = cli.allow_dirty;
+	let allow_any_branch = cli.allow_any_branch;
+	let allow_no_upstream = cli.allow_no_upstream;
+	let allow_behind = cli.allow_behind;
+	let allow_diverged = cli.allow_diverged;
 	let skip_git = cli.skip_git;
 	let skip_push = cli.skip_push;
 	let skip_tag = cli.skip_tag;
 	let skip_all = cli.skip_all;
+	let resume = cli.resume;
+	let no_rollback = cli.no_rollback;
+	let packages = cli.packages;
+	let commit_per_package = cli.commit_per_package;
 	let path = cli.path.unwrap_or(String::from(".")).to_string();
+	let output = cli.output.unwrap_or(String::from("text")).to_string();
+	let vcs = cli.vcs.unwrap_or(String::from("git-cli")).to_string();
+	let dirty_policy = cli.dirty_policy;
+	let remote = cli.remote;
+	let target_branch = cli.target_branch;
+	let sign = cli.sign;
+	let signing_key = cli.signing_key;
+	let signing_format = cli.signing_format;
+	let tag_template = cli.tag_template;
+	let tag_build_metadata = cli.tag_build_metadata;
+	let build_metadata = cli.build_metadata;
+	let release_commit_message = cli.release_commit_message;
+	let dev_commit_message = cli.dev_commit_message;
+	let commit_trailers = cli.commit_trailers;
 
 	if ![
 		"patch".to_string(),
 		"minor".to_string(),
 		"major".to_string(),
+		"auto".to_string(),
 	]
 	.contains(&bump_level)
 	{
 		println!(
-			"Error: Invalid bump level {} should be patch/minor/major",
+			"Error: Invalid bump level {} should be patch/minor/major/auto",
 			&bump_level
 		);
 		bail!("Usage Error");
 	};
 
-	println!("Pre Release Suffix : {}", pre_release_suffix);
-	println!("Bump Level         : {}", bump_level);
-	//	println!( "Allow Dirty        : {}", allow_dirty?"yes":"no" );
-	println!(
-		"Allow Dirty        : {}",
-		if allow_dirty { "yes" } else { "no" }
-	);
-	println!(
-		"Skip Git           : {}",
-		if skip_git { "yes" } else { "no" }
-	);
-	println!(
-		"Skip Push          : {}",
-		if skip_push { "yes" } else { "no" }
-	);
-	println!(
-		"Skip Tag           : {}",
-		if skip_tag { "yes" } else { "no" }
-	);
-	println!(
-		"Skip All           : {}",
-		if skip_all { "yes" } else { "no" }
+	if !["text".to_string(), "json".to_string()].contains(&output) {
+		println!("Error: Invalid output {} should be text/json", &output);
+		bail!("Usage Error");
+	};
+	let json = output == "json";
+
+	if !["git-cli".to_string(), "git2".to_string(), "gix".to_string()].contains(&vcs) {
+		println!("Error: Invalid vcs {} should be git-cli/git2/gix", &vcs);
+		bail!("Usage Error");
+	};
+
+	if let Some(dirty_policy) = &dirty_policy {
+		if ![
+			"tracked".to_string(),
+			"strict".to_string(),
+			"ignore-submodules".to_string(),
+		]
+		.contains(dirty_policy)
+		{
+			println!(
+				"Error: Invalid dirty policy {} should be tracked/strict/ignore-submodules",
+				dirty_policy
+			);
+			bail!("Usage Error");
+		};
+	}
+
+	if let Some(signing_format) = &signing_format {
+		if !["openpgp".to_string(), "ssh".to_string()].contains(signing_format) {
+			println!(
+				"Error: Invalid signing format {} should be openpgp/ssh",
+				signing_format
+			);
+			bail!("Usage Error");
+		};
+	}
+
+	// the settings only matter for changing the version
+	let show_settings = matches!(
+		command,
+		omr_bumper::Command::Release | omr_bumper::Command::SetVersion
 	);
-	println!("Path               : {}", path);
+	if !json && show_settings {
+		if let Some(target_version) = &target_version {
+			println!("Version            : {}", target_version);
+			println!("Force              : {}", if force { "yes" } else { "no" });
+		} else if stable {
+			println!("Pre Release Suffix : none, stable release");
+		} else {
+			println!("Pre Release Suffix : {}", pre_release_suffix);
+		}
+		println!(
+			"Pre Release Counter: {}",
+			if pre_release_counter {
+				"yes"
+			} else {
+				"from config"
+			}
+		);
+		println!("Bump Level         : {}", bump_level);
+		//	println!( "Allow Dirty        : {}", allow_dirty?"yes":"no" );
+		println!(
+			"Allow Dirty        : {}",
+			if allow_dirty { "yes" } else { "no" }
+		);
+		println!(
+			"Allow Any Branch   : {}",
+			if allow_any_branch { "yes" } else { "no" }
+		);
+		println!(
+			"Allow No Upstream  : {}",
+			if allow_no_upstream { "yes" } else { "no" }
+		);
+		println!(
+			"Allow Behind       : {}",
+			if allow_behind { "yes" } else { "no" }
+		);
+		println!(
+			"Allow Diverged     : {}",
+			if allow_diverged { "yes" } else { "no" }
+		);
+		println!(
+			"Skip Git           : {}",
+			if skip_git { "yes" } else { "no" }
+		);
+		println!(
+			"Skip Push          : {}",
+			if skip_push { "yes" } else { "no" }
+		);
+		println!(
+			"Skip Tag           : {}",
+			if skip_tag { "yes" } else { "no" }
+		);
+		println!(
+			"Skip All           : {}",
+			if skip_all { "yes" } else { "no" }
+		);
+		println!(
+			"Dry Run            : {}",
+			if dry_run { "yes" } else { "no" }
+		);
+		println!("Resume             : {}", if resume { "yes" } else { "no" });
+		println!(
+			"No Rollback        : {}",
+			if no_rollback { "yes" } else { "no" }
+		);
+		println!("Packages           : {}", packages.join(", "));
+		println!(
+			"Commit Per Package : {}",
+			if commit_per_package { "yes" } else { "no" }
+		);
+		println!("Path               : {}", path);
+		println!("Vcs                : {}", vcs);
+		println!(
+			"Dirty Policy       : {}",
+			dirty_policy.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Remote             : {}",
+			remote.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Target Branch      : {}",
+			target_branch.as_deref().unwrap_or("from config")
+		);
+		println!("Sign               : {}", if sign { "yes" } else { "no" });
+		println!(
+			"Signing Key        : {}",
+			signing_key.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Signing Format     : {}",
+			signing_format.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Tag Template       : {}",
+			tag_template.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Tag Build Metadata : {}",
+			if tag_build_metadata {
+				"yes"
+			} else {
+				"from config"
+			}
+		);
+		println!(
+			"Build Metadata     : {}",
+			build_metadata.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Release Message    : {}",
+			release_commit_message.as_deref().unwrap_or("from config")
+		);
+		println!(
+			"Dev Message        : {}",
+			dev_commit_message.as_deref().unwrap_or("from config")
+		);
+		println!("Commit Trailers    : {:?}", &commit_trailers);
+	}
 
 	let mut release = Release::new();
 
-	release.set_pre_release_suffix(&pre_release_suffix);
+	release.set_pre_release_suffix(&pre_release_suffix)?;
+	release.set_stable(stable);
+	if let Some(target_version) = &target_version {
+		release.set_target_version(target_version)?;
+	}
+	release.set_force(force);
+	release.set_command(command);
+	release.set_pre_release_counter(pre_release_counter);
 	release.set_bump_level(&bump_level)?;
 	release.set_allow_dirty(allow_dirty);
+	release.set_allow_any_branch(allow_any_branch);
+	release.set_allow_no_upstream(allow_no_upstream);
+	release.set_allow_behind(allow_behind);
+	release.set_allow_diverged(allow_diverged);
 	release.set_skip_git(skip_git);
 	release.set_skip_push(skip_push);
 	release.set_skip_tag(skip_tag);
 	release.set_skip_all(skip_all);
+	release.set_dry_run(dry_run);
+	release.set_resume(resume);
+	release.set_no_rollback(no_rollback);
+	release.set_packages(&packages);
+	release.set_commit_per_package(commit_per_package);
 	release.set_path(&path);
+	release.set_vcs(&vcs)?;
+	if let Some(dirty_policy) = &dirty_policy {
+		release.set_dirty_policy(dirty_policy)?;
+	}
+	if let Some(remote) = &remote {
+		release.set_remote(remote);
+	}
+	if let Some(target_branch) = &target_branch {
+		release.set_target_branch(target_branch);
+	}
+	release.set_sign(sign);
+	if let Some(signing_key) = &signing_key {
+		release.set_signing_key(signing_key);
+	}
+	if let Some(signing_format) = &signing_format {
+		release.set_signing_format(signing_format)?;
+	}
+	if let Some(tag_template) = &tag_template {
+		release.set_tag_template(tag_template)?;
+	}
+	release.set_tag_build_metadata(tag_build_metadata);
+	if let Some(build_metadata) = &build_metadata {
+		release.set_build_metadata(build_metadata)?;
+	}
+	if let Some(release_commit_message) = &release_commit_message {
+		release.set_release_commit_message(release_commit_message)?;
+	}
+	if let Some(dev_commit_message) = &dev_commit_message {
+		release.set_dev_commit_message(dev_commit_message)?;
+	}
+	for trailer in commit_trailers.iter() {
+		release.add_commit_trailer(trailer)?;
+	}
+	release.set_status_to_stderr(json);
+	release.load_config()?;
 
 	//	dbg!( &release );
 
-	match release.run() {
+	let printer = if json {
+		let (sender, receiver) = std::sync::mpsc::channel();
+		release.set_event_sender(sender);
+		Some(std::thread::spawn(move || {
+			for event in receiver {
+				match serde_json::to_string(&event) {
+					Ok(event) => println!("{}", event),
+					Err(e) => eprintln!("Error: {}", e),
+				}
+			}
+		}))
+	} else {
+		None
+	};
+
+	let result = release.run();
+	// closes the event channel
+	drop(release);
+	if let Some(printer) = printer {
+		let _ = printer.join();
+	}
+	match result {
 		Ok(_) => {},
 		Err(e) => {
-			println!("Error: {}", e);
+			if json {
+				eprintln!("Error: {}", e);
+			} else {
+				println!("Error: {}", e);
+			}
+			// after the summary event, scripts check the exit status
+			std::process::exit(1);
 		},
 	};
 	Ok(())
@@ -1,24 +1,103 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::bail;
-use semver::{Prerelease, Version};
-use toml_edit::{value, Document, Formatted, Item, Value};
+use semver::{BuildMetadata, Op, Prerelease, Version, VersionReq};
+use toml_edit::{value, Document, Formatted, Item, TableLike, Value};
+
+/// Where a manifest keeps a version of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VersionLocation {
+	WorkspacePackage, // [workspace.package] version = "..."
+	Package,          // [package] version = "..."
+}
+
+impl VersionLocation {
+	fn item<'a>(&self, doc: &'a Document) -> Option<&'a Item> {
+		match self {
+			VersionLocation::WorkspacePackage => {
+				doc.get("workspace")?.get("package")?.get("version")
+			},
+			VersionLocation::Package => doc.get("package")?.get("version"),
+		}
+	}
+
+	fn item_mut<'a>(&self, doc: &'a mut Document) -> Option<&'a mut Item> {
+		match self {
+			VersionLocation::WorkspacePackage => doc
+				.get_mut("workspace")?
+				.as_table_like_mut()?
+				.get_mut("package")?
+				.as_table_like_mut()?
+				.get_mut("version"),
+			VersionLocation::Package => doc
+				.get_mut("package")?
+				.as_table_like_mut()?
+				.get_mut("version"),
+		}
+	}
+}
+
+const DEPENDENCY_TABLES: &[&str] = &[
+	"dependencies",
+	"dev-dependencies",
+	"dev_dependencies",
+	"build-dependencies",
+	"build_dependencies",
+];
+
+/// The in-memory state of a manifest, used to persist it in the journal.
+#[derive(Debug, Clone)]
+pub struct ManifestState {
+	pub path:     PathBuf,
+	pub contents: String,
+	pub modified: bool,
+}
 
 pub struct Manifest {
-	path: PathBuf,
-	doc:  Option<Document>,
+	path:     PathBuf,
+	doc:      Option<Document>,
+	modified: bool,
 }
 
 impl Manifest {
 	pub fn new(path: &Path) -> Self {
 		Self {
-			path: path.to_owned(),
-			doc:  None,
+			path:     path.to_owned(),
+			doc:      None,
+			modified: false,
 		}
 	}
 
+	pub fn from_state(state: &ManifestState) -> anyhow::Result<Self> {
+		let mut m = Manifest::new(&state.path);
+		m.load_from_str(&state.contents)?;
+		m.modified = state.modified;
+		Ok(m)
+	}
+
+	pub fn state(&self) -> Option<ManifestState> {
+		self.contents().map(|contents| ManifestState {
+			path: self.path.clone(),
+			contents,
+			modified: self.modified,
+		})
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// `true` if the manifest was changed since it was loaded.
+	pub fn is_modified(&self) -> bool {
+		self.modified
+	}
+
 	pub fn load(&mut self) -> anyhow::Result<()> {
 		let toml = std::fs::read_to_string(&self.path)?;
+		self.load_from_str(&toml)
+	}
+
+	pub fn load_from_str(&mut self, toml: &str) -> anyhow::Result<()> {
 		let doc = match toml.parse::<Document>() {
 			Ok(doc) => doc,
 			Err(e) => bail!("Couldn't load manifest from >>{:?}<< {}", &self.path, &e),
@@ -36,10 +115,179 @@ impl Manifest {
 		Ok(())
 	}
 
+	/// The (potentially modified) manifest as it would be saved.
+	pub fn contents(&self) -> Option<String> {
+		self.doc.as_ref().map(|doc| doc.to_string())
+	}
+
+	pub fn package_name(&self) -> Option<String> {
+		self.doc
+			.as_ref()
+			.and_then(|doc| doc.get("package"))
+			.and_then(|p| p.get("name"))
+			.and_then(|n| n.as_str())
+			.map(|n| n.to_string())
+	}
+
+	/// Paths to all dependency tables, including target specific ones, and `[workspace.dependencies]`.
+	fn dependency_table_paths(doc: &Document) -> Vec<Vec<String>> {
+		let mut paths = Vec::new();
+		for t in DEPENDENCY_TABLES {
+			paths.push(vec![t.to_string()]);
+		}
+		if let Some(targets) = doc.get("target").and_then(|t| t.as_table_like()) {
+			for (target, _) in targets.iter() {
+				for t in DEPENDENCY_TABLES {
+					paths.push(vec![
+						"target".to_string(),
+						target.to_string(),
+						t.to_string(),
+					]);
+				}
+			}
+		}
+		paths.push(vec!["workspace".to_string(), "dependencies".to_string()]);
+		paths
+	}
+
+	/// Updates the version requirement of all path dependencies on `name`.
+	///
+	/// The comparison operator, e.g. `=`, and the formatting are kept.
+	/// Other requirements, e.g. `>=0.3, <0.4`, are left alone, and fail if the version doesn't match them.
+	pub fn set_dependency_version(
+		&mut self,
+		name: &str,
+		version: &Version,
+	) -> anyhow::Result<bool> {
+		let mut changed = false;
+		if let Some(doc) = &mut self.doc {
+			for path in Self::dependency_table_paths(doc) {
+				// Note: `Item::get_mut` would insert missing tables
+				let mut item = Some(doc.as_item_mut());
+				for key in path.iter() {
+					item = item
+						.and_then(|i| i.as_table_like_mut())
+						.and_then(|t| t.get_mut(key));
+				}
+				let Some(table) = item.and_then(|i| i.as_table_like_mut()) else {
+					continue;
+				};
+				for (key, dependency) in table.iter_mut() {
+					let Some(dependency) = dependency.as_table_like_mut() else {
+						continue;
+					};
+					changed |=
+						Self::update_dependency(&self.path, key.get(), dependency, name, version)?;
+				}
+			}
+		} else {
+			bail!("No manifest loaded!");
+		}
+		if changed {
+			self.modified = true;
+		}
+		Ok(changed)
+	}
+
+	fn update_dependency(
+		manifest: &Path,
+		key: &str,
+		dependency: &mut dyn TableLike,
+		name: &str,
+		version: &Version,
+	) -> anyhow::Result<bool> {
+		let package = dependency
+			.get("package")
+			.and_then(|p| p.as_str())
+			.unwrap_or(key);
+		if package != name || dependency.get("path").is_none() {
+			return Ok(false);
+		}
+		let Some(Item::Value(old)) = dependency.get_mut("version") else {
+			return Ok(false);
+		};
+		let Some(req) = old.as_str() else {
+			return Ok(false);
+		};
+		// cargo ignores build metadata in requirements, and warns about it
+		let mut version = version.clone();
+		version.build = BuildMetadata::EMPTY;
+		let Ok(parsed) = VersionReq::parse(req) else {
+			bail!(
+				"{}: dependency `{}` has an invalid version requirement {:?}",
+				manifest.display(),
+				key,
+				req
+			);
+		};
+		let new_req = match parsed.comparators.as_slice() {
+			[c] if matches!(c.op, Op::Caret | Op::Exact | Op::Tilde) => {
+				let start = req.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+				format!("{}{}", &req[..start], version)
+			},
+			[] => return Ok(false), // `*`
+			_ if parsed.matches(&version) => return Ok(false),
+			_ => bail!(
+				"{}: dependency `{}` requires {:?}, {} doesn't match, update it by hand",
+				manifest.display(),
+				key,
+				req,
+				version
+			),
+		};
+		if new_req == req {
+			return Ok(false);
+		}
+
+		let decor = old.decor().clone();
+		*old = Value::from(new_req);
+		*old.decor_mut() = decor;
+		Ok(true)
+	}
+
+	fn version_locations(&self) -> Vec<VersionLocation> {
+		match &self.doc {
+			Some(doc) => [VersionLocation::WorkspacePackage, VersionLocation::Package]
+				.into_iter()
+				.filter(|l| matches!(l.item(doc), Some(Item::Value(Value::String(_)))))
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// `true` if the manifest has a version of its own, and doesn't inherit it.
+	pub fn has_version(&self) -> bool {
+		!self.version_locations().is_empty()
+	}
+
+	/// `true` for `version.workspace = true`
+	pub fn inherits_version(&self) -> bool {
+		match &self.doc {
+			Some(doc) => {
+				let workspace = doc
+					.get("package")
+					.and_then(|p| p.get("version"))
+					.and_then(|v| v.get("workspace"));
+				workspace.and_then(|w| w.as_bool()) == Some(true)
+			},
+			None => false,
+		}
+	}
+
 	fn get_formatted_version(&self) -> anyhow::Result<Formatted<String>> {
 		if let Some(doc) = &self.doc {
-			let fs = match &doc["package"]["version"] {
-				Item::Value(Value::String(s)) => s, //.to_string(),
+			let location = match self.version_locations().first() {
+				Some(l) => *l,
+				None if self.inherits_version() => {
+					bail!(
+						"Version of {:?} is inherited from the workspace",
+						&self.path
+					)
+				},
+				None => bail!("Unsupported version format!"),
+			};
+			let fs = match location.item(doc) {
+				Some(Item::Value(Value::String(s))) => s, //.to_string(),
 				_ => bail!("Unsupported version format!"),
 			};
 			Ok(fs.clone())
@@ -62,81 +310,418 @@ impl Manifest {
 		Ok(v.to_string())
 	}
 
+	/// Sets the version in every location the manifest has one.
+	#[allow(clippy::single_match)]
 	pub fn set_version(&mut self, version: &Version) -> anyhow::Result<()> {
-		let fs = self.get_formatted_version()?;
-
-		// :TODO: talk to the edit_toml team what they were thinking
-		//		let mut nfs = fs.clone();
-		//		nfs.value = version.to_string();
-		//		dbg!(&nfs);
+		let locations = self.version_locations();
 		if let Some(doc) = &mut self.doc {
-			let mut ni = value(version.to_string());
-			match &mut ni {
-				Item::Value(Value::String(s)) => {
-					//					dbg!(&s);
-					let d = s.decor_mut();
-					//					dbg!(&d);
-					let od = fs.decor();
-					match (od.prefix(), od.suffix()) {
-						(Some(p), Some(s)) => {
-							d.set_prefix(p.clone());
-							d.set_suffix(s.clone());
-						},
-						_ => {},
-					}
-				},
-				_ => {},
+			for location in locations {
+				let fs = match location.item(doc) {
+					Some(Item::Value(Value::String(s))) => s.clone(),
+					_ => bail!("Unsupported version format!"),
+				};
+
+				// :TODO: talk to the edit_toml team what they were thinking
+				//		let mut nfs = fs.clone();
+				//		nfs.value = version.to_string();
+				//		dbg!(&nfs);
+				let mut ni = value(version.to_string());
+				match &mut ni {
+					Item::Value(Value::String(s)) => {
+						//					dbg!(&s);
+						let d = s.decor_mut();
+						//					dbg!(&d);
+						let od = fs.decor();
+						match (od.prefix(), od.suffix()) {
+							(Some(p), Some(s)) => {
+								d.set_prefix(p.clone());
+								d.set_suffix(s.clone());
+							},
+							_ => {},
+						}
+					},
+					_ => {},
+				}
+				//			dbg!(&ni);
+				if let Some(item) = location.item_mut(doc) {
+					*item = ni;
+				}
 			}
-			//			dbg!(&ni);
-			doc["package"]["version"] = ni;
+			self.modified = true;
 		} else {
 			bail!("No manifest loaded!");
 		}
 		Ok(())
 	}
+}
 
-	pub fn bump_patch_version(&mut self) -> anyhow::Result<()> {
-		let old_version = self.get_version()?;
+/// The root manifest, and the manifests of all workspace members.
+///
+/// The version is bumped consistently in every manifest that has one of its own,
+/// members with `version.workspace = true` follow `[workspace.package]`.
+///
+/// With selected packages every package is versioned independently instead.
+pub struct Manifests {
+	manifests: Vec<Manifest>,
+	packages:  Vec<String>,
+}
 
-		let mut new_version = old_version.clone();
-		new_version.patch = old_version.patch + 1;
+impl Manifests {
+	/// Loads the manifest at `root`, and the manifests of the given workspace members.
+	pub fn load(root: &Path, members: &[PathBuf]) -> anyhow::Result<Self> {
+		let mut root_manifest = Manifest::new(root);
+		root_manifest.load()?;
+		let mut manifests = vec![root_manifest];
 
-		self.set_version(&new_version)?;
+		let root = Self::canonical(root);
+		for m in members.iter() {
+			if Self::canonical(m) == root {
+				continue;
+			}
+			let mut manifest = Manifest::new(m);
+			manifest.load()?;
+			manifests.push(manifest);
+		}
+
+		Ok(Self {
+			manifests,
+			packages: Vec::new(),
+		})
+	}
+
+	fn canonical(path: &Path) -> PathBuf {
+		path.canonicalize().unwrap_or(path.to_owned())
+	}
+
+	pub fn from_states(states: &[ManifestState]) -> anyhow::Result<Self> {
+		let manifests = states
+			.iter()
+			.map(Manifest::from_state)
+			.collect::<anyhow::Result<Vec<_>>>()?;
+		Ok(Self {
+			manifests,
+			packages: Vec::new(),
+		})
+	}
+
+	/// Versions the given packages independently, in the given order.
+	pub fn select_packages(&mut self, packages: &[String]) -> anyhow::Result<()> {
+		for p in packages.iter() {
+			let m = self.package_manifest(p)?;
+			if !m.has_version() {
+				bail!(
+					"Package {} has no version of its own, can not version it independently",
+					p
+				);
+			}
+		}
+		self.packages = packages.to_vec();
 		Ok(())
 	}
 
-	pub fn bump_minor_version(&mut self) -> anyhow::Result<()> {
-		let old_version = self.get_version()?;
+	fn package_manifest(&self, name: &str) -> anyhow::Result<&Manifest> {
+		match self
+			.manifests
+			.iter()
+			.find(|m| m.package_name().as_deref() == Some(name))
+		{
+			Some(m) => Ok(m),
+			None => bail!("Package {} is not part of the workspace", name),
+		}
+	}
+
+	pub fn package_manifest_path(&self, name: &str) -> anyhow::Result<PathBuf> {
+		Ok(self.package_manifest(name)?.path().to_owned())
+	}
+
+	/// Versions of the selected packages.
+	pub fn package_versions(&self) -> anyhow::Result<Vec<(String, Version)>> {
+		self.packages
+			.iter()
+			.map(|p| Ok((p.clone(), self.package_manifest(p)?.get_version()?)))
+			.collect()
+	}
+
+	/// The version, or the versions of all selected packages, e.g. `a 0.1.2, b 1.2.0`.
+	pub fn get_pretty_versions(&self) -> anyhow::Result<String> {
+		if self.packages.is_empty() {
+			return self.get_pretty_version();
+		}
+		let versions: Vec<String> = self
+			.package_versions()?
+			.iter()
+			.map(|(p, v)| format!("{} {}", p, v))
+			.collect();
+		Ok(versions.join(", "))
+	}
 
-		let mut new_version = old_version.clone();
-		new_version.patch = 0;
-		new_version.minor = old_version.minor + 1;
+	pub fn set_package_version(&mut self, name: &str, version: &Version) -> anyhow::Result<()> {
+		match self
+			.manifests
+			.iter_mut()
+			.find(|m| m.package_name().as_deref() == Some(name))
+		{
+			Some(m) => m.set_version(version)?,
+			None => bail!("Package {} is not part of the workspace", name),
+		}
+		for m in self.manifests.iter_mut() {
+			m.set_dependency_version(name, version)?;
+		}
+		Ok(())
+	}
 
-		self.set_version(&new_version)?;
+	/// Applies `f` to the shared version, or to the version of every selected package.
+	pub fn update_versions(&mut self, f: impl Fn(&Version) -> Version) -> anyhow::Result<()> {
+		if self.packages.is_empty() {
+			let old_version = self.get_version()?;
+			return self.set_version(&f(&old_version));
+		}
+		for (p, old_version) in self.package_versions()? {
+			self.set_package_version(&p, &f(&old_version))?;
+		}
 		Ok(())
 	}
 
-	pub fn bump_major_version(&mut self) -> anyhow::Result<()> {
-		let old_version = self.get_version()?;
+	pub fn states(&self) -> Vec<ManifestState> {
+		self.manifests.iter().filter_map(|m| m.state()).collect()
+	}
+
+	pub fn manifests(&self) -> &[Manifest] {
+		&self.manifests
+	}
+
+	/// Paths of all manifests that were changed since they were loaded.
+	pub fn modified_paths(&self) -> Vec<PathBuf> {
+		self.manifests
+			.iter()
+			.filter(|m| m.is_modified())
+			.map(|m| m.path().to_owned())
+			.collect()
+	}
+
+	fn versioned(&self) -> impl Iterator<Item = &Manifest> {
+		self.manifests.iter().filter(|m| m.has_version())
+	}
+
+	/// The version shared by all manifests that have one.
+	pub fn get_version(&self) -> anyhow::Result<Version> {
+		let mut version: Option<(Version, &Path)> = None;
+		for m in self.versioned() {
+			let v = m.get_version()?;
+			match &version {
+				None => version = Some((v, m.path())),
+				Some((first, first_path)) if *first != v => bail!(
+					"Versions differ between {:?} ({}) and {:?} ({}), can not bump them consistently",
+					first_path,
+					first,
+					m.path(),
+					v
+				),
+				_ => {},
+			}
+		}
+		match version {
+			Some((v, _)) => Ok(v),
+			None => {
+				// report why the root manifest has no version
+				self.manifests[0].get_version()
+			},
+		}
+	}
+
+	pub fn get_pretty_version(&self) -> anyhow::Result<String> {
+		let v = self.get_version()?;
+		Ok(v.to_string())
+	}
+
+	/// Sets the version in all manifests that have one,
+	/// and updates the requirements of path dependencies between the packages.
+	pub fn set_version(&mut self, version: &Version) -> anyhow::Result<()> {
+		for m in self.manifests.iter_mut().filter(|m| m.has_version()) {
+			m.set_version(version)?;
+		}
 
-		let mut new_version = old_version.clone();
-		new_version.patch = 0;
-		new_version.minor = 0;
-		new_version.major = old_version.major + 1;
+		let names: Vec<String> = self
+			.manifests
+			.iter()
+			.filter(|m| m.has_version() || m.inherits_version())
+			.filter_map(|m| m.package_name())
+			.collect();
+		for m in self.manifests.iter_mut() {
+			for name in names.iter() {
+				m.set_dependency_version(name, version)?;
+			}
+		}
+		Ok(())
+	}
 
-		self.set_version(&new_version)?;
+	pub fn save(&mut self) -> anyhow::Result<()> {
+		for m in self.manifests.iter_mut().filter(|m| m.is_modified()) {
+			m.save()?;
+		}
 		Ok(())
 	}
 
+	/// Replaces the pre-release part of the version, an empty `suffix` removes it.
 	pub fn set_version_suffix(&mut self, suffix: &str) -> anyhow::Result<()> {
-		let old_version = self.get_version()?;
-		//		dbg!(&old_version);
+		let pre = match Prerelease::new(suffix) {
+			Ok(pre) => pre,
+			Err(e) => bail!("Invalid pre-release suffix {:?}: {}", suffix, e),
+		};
+		self.update_versions(|old_version| {
+			let mut new_version = old_version.clone();
+			new_version.pre = pre.clone();
+			new_version
+		})
+	}
+}
 
-		let mut new_version = old_version; //.clone();
-		new_version.pre = Prerelease::new(suffix).unwrap();
-		//		dbg!(&new_version);
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-		self.set_version(&new_version)?;
-		Ok(())
+	fn manifests(files: &[(&str, &str)]) -> Manifests {
+		let states: Vec<ManifestState> = files
+			.iter()
+			.map(|(path, contents)| ManifestState {
+				path:     PathBuf::from(path),
+				contents: contents.to_string(),
+				modified: false,
+			})
+			.collect();
+		Manifests::from_states(&states).unwrap()
+	}
+
+	fn contents(m: &Manifests, path: &str) -> String {
+		m.manifests()
+			.iter()
+			.find(|m| m.path() == Path::new(path))
+			.and_then(|m| m.contents())
+			.unwrap()
+	}
+
+	fn version(v: &str) -> Version {
+		Version::parse(v).unwrap()
+	}
+
+	#[test]
+	fn workspace_package_version_is_inherited() {
+		let mut m = manifests(&[
+			(
+				"Cargo.toml",
+				r#"[workspace]
+members = ["a", "b"]
+
+[workspace.package]
+version = "0.1.0-dev" # keep
+
+[workspace.dependencies]
+b = { path = "b", version = "0.1.0-dev" }
+"#,
+			),
+			(
+				"a/Cargo.toml",
+				"[package]\nname = \"a\"\nversion.workspace = true\n\n[dependencies]\nb.workspace = true\n",
+			),
+			(
+				"b/Cargo.toml",
+				"[package]\nname = \"b\"\nversion.workspace = true\n",
+			),
+		]);
+		assert_eq!(m.get_version().unwrap(), version("0.1.0-dev"));
+		assert!(m.manifests()[1].inherits_version());
+		m.set_version(&version("0.1.0-alpha")).unwrap();
+
+		let root = contents(&m, "Cargo.toml");
+		assert!(root.contains("version = \"0.1.0-alpha\" # keep\n"));
+		assert!(root.contains(r#"b = { path = "b", version = "0.1.0-alpha" }"#));
+		assert_eq!(m.modified_paths(), [PathBuf::from("Cargo.toml")]);
+	}
+
+	#[test]
+	fn per_member_versions() {
+		let mut m = manifests(&[
+			("Cargo.toml", "[workspace]\nmembers = [\"a\", \"b\"]\n"),
+			(
+				"a/Cargo.toml",
+				r#"[package]
+name = "a"
+version = "1.2.0"
+
+[dependencies]
+b = { path = "../b", version = "=0.2.0" }
+"#,
+			),
+			(
+				"b/Cargo.toml",
+				"[package]\nname = \"b\"\nversion = \"0.2.0\"\n",
+			),
+		]);
+		let err = m.get_version().unwrap_err().to_string();
+		assert!(err.starts_with("Versions differ"), "{}", err);
+
+		m.select_packages(&["b".to_string(), "a".to_string()])
+			.unwrap();
+		m.update_versions(|v| Version::new(v.major, v.minor + 1, 0))
+			.unwrap();
+		assert_eq!(m.get_pretty_versions().unwrap(), "b 0.3.0, a 1.3.0");
+		assert!(
+			contents(&m, "a/Cargo.toml").contains(r#"b = { path = "../b", version = "=0.3.0" }"#)
+		);
+		assert!(m.select_packages(&["c".to_string()]).is_err());
+	}
+
+	#[test]
+	fn target_dependencies() {
+		let mut m = manifests(&[(
+			"Cargo.toml",
+			r#"[package]
+name = "a"
+version = "0.1.0"
+
+[target.'cfg(unix)'.dependencies]
+a-sys = { path = "sys", version = "^0.1.0" }
+
+[target.'cfg(windows)'.build-dependencies]
+a-sys = { path = "sys", version = "~0.1.0" }
+a-registry = { version = "0.1.0" }
+
+[dev-dependencies]
+a-sys = { path = "sys", version = "*" }
+"#,
+		)]);
+		m.manifests[0]
+			.set_dependency_version("a-sys", &version("0.2.0+g4f012a5"))
+			.unwrap();
+		let toml = contents(&m, "Cargo.toml");
+		assert!(toml.contains(r#"a-sys = { path = "sys", version = "^0.2.0" }"#));
+		assert!(toml.contains(r#"a-sys = { path = "sys", version = "~0.2.0" }"#));
+		assert!(toml.contains(r#"a-registry = { version = "0.1.0" }"#));
+		assert!(toml.contains(r#"a-sys = { path = "sys", version = "*" }"#));
+	}
+
+	#[test]
+	fn compound_requirements_are_left_alone() {
+		let toml = r#"[package]
+name = "a"
+version = "0.1.0"
+
+[dependencies]
+a-sys = { path = "sys", version = ">=0.1, <0.3" }
+"#;
+		let mut m = manifests(&[("Cargo.toml", toml)]);
+		let changed = m.manifests[0]
+			.set_dependency_version("a-sys", &version("0.2.0"))
+			.unwrap();
+		assert!(!changed);
+		assert_eq!(contents(&m, "Cargo.toml"), toml);
+
+		let err = m.manifests[0]
+			.set_dependency_version("a-sys", &version("0.3.0"))
+			.unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Cargo.toml: dependency `a-sys` requires \">=0.1, <0.3\", 0.3.0 doesn't match, update it by hand"
+		);
 	}
 }
@@ -0,0 +1,72 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A preview of what a release would do, built by evaluating every step
+/// against a simulated state instead of touching the repository.
+#[derive(Debug, Default)]
+pub struct Plan {
+	path:    PathBuf,
+	entries: Vec<PlanEntry>,
+}
+
+#[derive(Debug)]
+pub struct PlanEntry {
+	index:   usize,
+	step:    String,
+	actions: Vec<String>,
+}
+
+impl PlanEntry {
+	/// Position of the step in the release pipeline, starting at 0.
+	pub fn index(&self) -> usize {
+		self.index
+	}
+
+	pub fn step(&self) -> &str {
+		&self.step
+	}
+
+	pub fn actions(&self) -> &[String] {
+		&self.actions
+	}
+}
+
+impl Plan {
+	pub fn new(path: &Path) -> Self {
+		Self {
+			path:    path.to_owned(),
+			entries: Vec::new(),
+		}
+	}
+
+	pub fn entries(&self) -> &[PlanEntry] {
+		&self.entries
+	}
+
+	pub(crate) fn begin_step(&mut self, index: usize, step: &str) {
+		self.entries.push(PlanEntry {
+			index,
+			step: step.to_string(),
+			actions: Vec::new(),
+		});
+	}
+
+	pub(crate) fn action(&mut self, action: &str) {
+		if let Some(e) = self.entries.last_mut() {
+			e.actions.push(action.to_string());
+		}
+	}
+}
+
+impl fmt::Display for Plan {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Release plan for {} (dry-run)", self.path.display())?;
+		for e in self.entries.iter() {
+			writeln!(f, "{:>3}. {}", e.index + 1, e.step)?;
+			for a in e.actions.iter() {
+				writeln!(f, "       - {}", a)?;
+			}
+		}
+		Ok(())
+	}
+}
@@ -1,53 +1,217 @@
+use std::cell::RefCell;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Instant;
 
-use anyhow::*;
+use anyhow::bail;
+use path_absolutize::*;
+use semver::{BuildMetadata, Prerelease, Version};
+use serde_json::json;
 
+use crate::build_template::BuildTemplate;
 use crate::cargo::Cargo;
-use crate::manifest::Manifest;
-use crate::Repository;
+use crate::changelog::{self, Changelog, CHANGELOG_FILE};
+use crate::config::Config;
+use crate::conventional::{self, ConventionalCommit};
+use crate::event::{self, Details, Event, Status, StatusSink};
+use crate::git_status::DirtyPolicy;
+use crate::journal::Journal;
+use crate::manifest::Manifests;
+use crate::plan::Plan;
+use crate::rollback::Rollback;
+use crate::signing::{SignatureFormat, Signing};
+use crate::step::{GitCommitMessage, Step, COMMIT_PLACEHOLDERS};
+use crate::tag_template::TagTemplate;
+use crate::template;
+use crate::vcs::{glob_match, Upstream, Vcs, VcsBackend};
 
-#[derive(Debug, Clone)]
-enum GitCommitMessage {
-	BumpVersionForRelease,
-	BumpVersionForDev,
-}
-#[derive(Debug, Clone)]
-enum Step {
-	GitEnsureClean, // fails if there are any changes
-	GitShowDirty,
-	CargoLoadManifest,
-	ManifestSetVersionSuffix,
-	ManifestSetVersionSuffixDev,
-	CargoSaveManifest,
-	CargoUpdateWorkspace,
-	ManifestPrintVersion,
-	GitCommitManifest(GitCommitMessage),
-	GitFetch,
-	GitRebase,
-	GitPush,
-	GitTag,
-	GitPushTag,
-	ManifestBumpLevel,
-}
+/// Branches releases are allowed from, unless `branches` is configured.
+const DEFAULT_BRANCHES: [&str; 2] = ["main", "master"];
 
-#[derive(Debug)]
-enum BumpLevel {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum BumpLevel {
 	Patch,
 	Minor,
 	Major,
 }
 
+impl BumpLevel {
+	fn bump(&self, old_version: &Version) -> Version {
+		let mut new_version = old_version.clone();
+		match self {
+			BumpLevel::Patch => new_version.patch = old_version.patch + 1,
+			BumpLevel::Minor => {
+				new_version.patch = 0;
+				new_version.minor = old_version.minor + 1;
+			},
+			BumpLevel::Major => {
+				new_version.patch = 0;
+				new_version.minor = 0;
+				new_version.major = old_version.major + 1;
+			},
+		}
+		new_version
+	}
+}
+
+impl fmt::Display for BumpLevel {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			BumpLevel::Patch => write!(f, "patch"),
+			BumpLevel::Minor => write!(f, "minor"),
+			BumpLevel::Major => write!(f, "major"),
+		}
+	}
+}
+
+impl std::str::FromStr for BumpLevel {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		match s {
+			"patch" => Ok(BumpLevel::Patch),
+			"minor" => Ok(BumpLevel::Minor),
+			"major" => Ok(BumpLevel::Major),
+			o => bail!("Invalid bump level: {}", &o),
+		}
+	}
+}
+
+/// What `Release::run` does, every command runs a part of the release pipeline.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+	/// The configured pipeline.
+	#[default]
+	Release,
+	/// Only sets the target version in the manifests, and the lockfile, without touching git.
+	SetVersion,
+	/// Only bumps the version by the bump level, without touching git.
+	Bump,
+	/// Tags HEAD with the manifest version, using the tag steps of the configured pipeline.
+	Tag,
+	/// Shows the version, branch, upstream, the last release tag, and dirty files.
+	Status,
+	/// Resets the branch to before the last release, and deletes its tags, only locally.
+	Undo,
+}
+
 #[derive(Debug)]
 pub struct Release {
-	bump_level:         BumpLevel,
-	pre_release_suffix: String,
-	allow_dirty:        bool,
-	skip_git:           bool,
-	skip_push:          bool,
-	skip_tag:           bool,
-	skip_all:           bool,
-	path:               PathBuf,
-	steps:              Vec<Step>,
+	bump_level:          BumpLevel,
+	auto_bump_level:     bool,
+	pre_release_suffix:  String,
+	pre_release_counter: bool,
+	stable:              bool,
+	target_version:      Option<Version>,
+	force:               bool,
+	command:             Command,
+	allow_dirty:         bool,
+	allow_any_branch:    bool,
+	allow_no_upstream:   bool,
+	allow_behind:        bool,
+	allow_diverged:      bool,
+	skip_git:            bool,
+	skip_push:           bool,
+	skip_tag:            bool,
+	skip_all:            bool,
+	dry_run:             bool,
+	resume:              bool,
+	no_rollback:         bool,
+	packages:            Vec<String>,
+	commit_per_package:  bool,
+	path:                PathBuf,
+	config:              Config,
+	steps:               Vec<Step>,
+	events:              Option<Sender<Event>>,
+	vcs:                 Vcs,
+	dirty_policy:        Option<DirtyPolicy>,
+	remote:              Option<String>,
+	target_branch:       Option<String>,
+	sign:                bool,
+	signing_key:         Option<String>,
+	signing_format:      Option<SignatureFormat>,
+	tag_template:        Option<TagTemplate>,
+	tag_build_metadata:  bool,
+	build_metadata:      Option<BuildTemplate>,
+	release_message:     Option<String>,
+	dev_message:         Option<String>,
+	commit_trailers:     Vec<String>,
+	backend:             RefCell<Option<Box<dyn VcsBackend>>>,
+	status:              StatusSink,
+}
+
+/// What the summary of a run reports.
+#[derive(Default)]
+struct Progress {
+	release_version: String,
+	version:         String,
+	tags:            Vec<String>,
+	completed:       usize,
+	total:           usize,
+}
+
+/// Hash and message of commits, newest first.
+type Commits = Vec<(String, String)>;
+
+/// Everything the steps hand over to each other while a release runs.
+#[derive(Default)]
+struct State {
+	manifests:         Option<Manifests>,
+	release_version:   String,
+	/// Only for independently versioned packages.
+	package_versions:  Vec<(String, String)>,
+	/// Written, but not committed yet.
+	changelog:         Option<PathBuf>,
+	/// The day the release started.
+	date:              String,
+	/// As of the last release commit.
+	previous_version:  String,
+	previous_packages: Vec<(String, String)>,
+	/// Once the version was bumped.
+	bump_level:        Option<BumpLevel>,
+}
+
+impl State {
+	fn new() -> Self {
+		Self {
+			date: changelog::today(),
+			..Default::default()
+		}
+	}
+}
+
+/// The version in a tag, from the first position the rest is a version, e.g. `1.2.3` of `foo-v1.2.3`.
+fn tag_version(tag: &str) -> Option<Version> {
+	tag.char_indices()
+		.filter(|(_, c)| c.is_ascii_digit())
+		.find_map(|(i, _)| Version::parse(&tag[i..]).ok())
+}
+
+/// The last tag rendered from `template` that isn't excluded, tags only the glob matches, e.g. `latest`, are skipped.
+fn last_tag(
+	repo: &mut dyn VcsBackend,
+	template: &TagTemplate,
+	package: Option<&str>,
+	exclude: &[String],
+) -> anyhow::Result<Option<String>> {
+	let pattern = template.pattern(package);
+	let mut exclude = exclude.to_vec();
+	while let Some(tag) = repo.last_tag(&pattern, &exclude)? {
+		if template.matches(&tag, package) {
+			return Ok(Some(tag));
+		}
+		exclude.push(tag);
+	}
+	Ok(None)
+}
+
+/// Name, and version of the independently versioned packages.
+fn package_versions(m: &Manifests) -> anyhow::Result<Vec<(String, String)>> {
+	Ok(m.package_versions()?
+		.into_iter()
+		.map(|(p, v)| (p, v.to_string()))
+		.collect())
 }
 
 impl Default for Release {
@@ -60,29 +224,47 @@ impl Release {
 	pub fn new() -> Self {
 		Self {
 			bump_level:             BumpLevel::Patch,
+			auto_bump_level:        false,
 			pre_release_suffix:     "alpha".to_string(),
+			pre_release_counter:    false,
+			stable:                 false,
+			target_version:         None,
+			force:                  false,
+			command:                Command::default(),
 			allow_dirty:            false,
+			allow_any_branch:       false,
+			allow_no_upstream:      false,
+			allow_behind:           false,
+			allow_diverged:         false,
 			skip_git:               false,
 			skip_push:              false,
 			skip_tag:               false,
 			skip_all:               false,
+			dry_run:                false,
+			resume:                 false,
+			no_rollback:            false,
+			packages:               Vec::new(),
+			commit_per_package:     false,
 			path:                   Path::new(".").to_path_buf(),
+			config:                 Config::default(),
 			#[rustfmt::skip]
 			steps:              [ /* :WIP: */
 									// pre release
+									Step::GitPreflight,
 									Step::GitEnsureClean,
 									Step::CargoLoadManifest,
 									Step::ManifestSetVersionSuffix,
 									Step::CargoSaveManifest,
 									Step::CargoUpdateWorkspace,
 									Step::ManifestPrintVersion,
+									Step::ChangelogUpdate,
 									Step::GitShowDirty,
 									Step::GitCommitManifest(GitCommitMessage::BumpVersionForRelease),
 									Step::GitFetch,
 									Step::GitRebase,
 									Step::GitPush,
-									Step::GitTag,
-									Step::GitPushTag,
+									Step::GitTag(None),
+									Step::GitPushTag(None),
 									// post release
 									Step::ManifestBumpLevel,
 									Step::ManifestSetVersionSuffixDev,
@@ -96,19 +278,73 @@ impl Release {
 									Step::GitPush,
 									Step::GitShowDirty,
 								].to_vec(),
+			events:             None,
+			vcs:                Vcs::default(),
+			dirty_policy:       None,
+			remote:             None,
+			target_branch:      None,
+			sign:               false,
+			signing_key:        None,
+			signing_format:     None,
+			tag_template:       None,
+			tag_build_metadata: false,
+			build_metadata:     None,
+			release_message:    None,
+			dev_message:        None,
+			commit_trailers:    Vec::new(),
+			backend:            RefCell::new(None),
+			status:             StatusSink::default(),
 		}
 	}
 
-	pub fn set_pre_release_suffix(&mut self, pre_release_suffix: &str) {
+	pub fn set_pre_release_suffix(&mut self, pre_release_suffix: &str) -> anyhow::Result<()> {
+		if pre_release_suffix.is_empty() {
+			bail!("Empty pre-release suffix, use a stable release instead");
+		}
+		if let Err(e) = Prerelease::new(pre_release_suffix) {
+			bail!("Invalid pre-release suffix {:?}: {}", pre_release_suffix, e);
+		}
 		self.pre_release_suffix = pre_release_suffix.to_string();
+		Ok(())
+	}
+
+	/// Releases without a pre-release suffix, e.g. `0.3.20`.
+	pub fn set_stable(&mut self, stable: bool) {
+		self.stable = stable;
+	}
+
+	/// Releases exactly `version`, instead of the current version with the pre-release suffix.
+	pub fn set_target_version(&mut self, version: &str) -> anyhow::Result<()> {
+		match Version::parse(version) {
+			Ok(version) => self.target_version = Some(version),
+			Err(e) => bail!("Invalid version {:?}: {}", version, e),
+		}
+		Ok(())
+	}
+
+	/// Sets a target version even if it isn't greater than the latest release tag.
+	pub fn set_force(&mut self, force: bool) {
+		self.force = force;
 	}
 
+	/// Runs only the part of the pipeline `command` needs, the whole release by default.
+	pub fn set_command(&mut self, command: Command) {
+		self.command = command;
+	}
+
+	/// Numbers pre-releases, e.g. `alpha.1`, `alpha.2`, then `beta.1`.
+	pub fn set_pre_release_counter(&mut self, pre_release_counter: bool) {
+		self.pre_release_counter = pre_release_counter;
+	}
+
+	/// `patch`, `minor`, `major`, or `auto` to derive it from the commits since the last release.
+	///
+	/// The release keeps the version of the manifest, the level bumps the dev version after it.
 	pub fn set_bump_level(&mut self, bump_level: &str) -> anyhow::Result<()> {
+		self.auto_bump_level = bump_level == "auto";
 		self.bump_level = match bump_level {
-			"patch" => BumpLevel::Patch,
-			"minor" => BumpLevel::Minor,
-			"major" => BumpLevel::Major,
-			o => bail!("Invalid bump level: {}", &o),
+			"auto" => BumpLevel::Patch,
+			o => o.parse()?,
 		};
 
 		Ok(())
@@ -118,6 +354,23 @@ impl Release {
 		self.allow_dirty = allow_dirty;
 	}
 
+	/// Releases from any branch, or a detached HEAD.
+	pub fn set_allow_any_branch(&mut self, allow_any_branch: bool) {
+		self.allow_any_branch = allow_any_branch;
+	}
+
+	pub fn set_allow_no_upstream(&mut self, allow_no_upstream: bool) {
+		self.allow_no_upstream = allow_no_upstream;
+	}
+
+	pub fn set_allow_behind(&mut self, allow_behind: bool) {
+		self.allow_behind = allow_behind;
+	}
+
+	pub fn set_allow_diverged(&mut self, allow_diverged: bool) {
+		self.allow_diverged = allow_diverged;
+	}
+
 	pub fn set_skip_git(&mut self, skip_git: bool) {
 		self.skip_git = skip_git;
 	}
@@ -134,19 +387,414 @@ impl Release {
 		self.skip_all = skip_all;
 	}
 
+	pub fn set_dry_run(&mut self, dry_run: bool) {
+		self.dry_run = dry_run;
+	}
+
+	pub fn set_resume(&mut self, resume: bool) {
+		self.resume = resume;
+	}
+
+	pub fn set_no_rollback(&mut self, no_rollback: bool) {
+		self.no_rollback = no_rollback;
+	}
+
+	/// Versions the given packages independently, instead of the whole workspace.
+	pub fn set_packages(&mut self, packages: &[String]) {
+		self.packages = packages.to_vec();
+	}
+
+	/// Creates one commit per package, only used with `set_packages`.
+	pub fn set_commit_per_package(&mut self, commit_per_package: bool) {
+		self.commit_per_package = commit_per_package;
+	}
+
 	pub fn set_path(&mut self, path: &str) {
 		self.path = Path::new(path).to_path_buf();
 	}
 
-	fn step_git_ensure_clean(&self) -> anyhow::Result<bool> {
-		let mut repo = Repository::new(&self.path);
+	/// The version control backend, `git-cli`, `git2`, or `gix`.
+	pub fn set_vcs(&mut self, vcs: &str) -> anyhow::Result<()> {
+		self.vcs = vcs.parse()?;
+		Ok(())
+	}
+
+	/// `tracked`, `strict`, or `ignore-submodules`, overrides the configured policy.
+	pub fn set_dirty_policy(&mut self, dirty_policy: &str) -> anyhow::Result<()> {
+		self.dirty_policy = Some(dirty_policy.parse()?);
+		Ok(())
+	}
+
+	/// The remote to fetch from, and push to, instead of the one the branch tracks.
+	pub fn set_remote(&mut self, remote: &str) {
+		self.remote = Some(remote.to_string());
+	}
+
+	/// The branch on the remote to rebase onto, and push to, instead of the one the branch tracks.
+	pub fn set_target_branch(&mut self, target_branch: &str) {
+		self.target_branch = Some(target_branch.to_string());
+	}
+
+	/// Signs the release commits, and tags.
+	pub fn set_sign(&mut self, sign: bool) {
+		self.sign = sign;
+	}
+
+	/// A gpg key id, or the path of an ssh key, instead of `user.signingKey`.
+	pub fn set_signing_key(&mut self, signing_key: &str) {
+		self.signing_key = Some(signing_key.to_string());
+	}
+
+	/// `openpgp`, or `ssh`, instead of `gpg.format`.
+	pub fn set_signing_format(&mut self, signing_format: &str) -> anyhow::Result<()> {
+		self.signing_format = Some(signing_format.parse()?);
+		Ok(())
+	}
+
+	/// Tag name for `GitTag` steps without one, e.g. `v{version}`, replaces `tag_template` from the config.
+	pub fn set_tag_template(&mut self, tag_template: &str) -> anyhow::Result<()> {
+		self.tag_template = Some(TagTemplate::new(tag_template)?);
+		Ok(())
+	}
+
+	/// Keeps the build metadata in `{version}` of tag names, e.g. `v1.2.3-dev+g4f012a5`.
+	pub fn set_tag_build_metadata(&mut self, tag_build_metadata: bool) {
+		self.tag_build_metadata = tag_build_metadata;
+	}
+
+	/// Build metadata for every version set, e.g. `g{short_hash}.{date}`, replaces `build_metadata` from the config.
+	pub fn set_build_metadata(&mut self, template: &str) -> anyhow::Result<()> {
+		self.build_metadata = Some(BuildTemplate::new(template)?);
+		Ok(())
+	}
+
+	/// Message of the release commit, e.g. `chore: release {new_version}`, replaces `release_commit_message` from the config.
+	pub fn set_release_commit_message(&mut self, template: &str) -> anyhow::Result<()> {
+		template::check(template, &COMMIT_PLACEHOLDERS, "commit message")?;
+		self.release_message = Some(template.to_string());
+		Ok(())
+	}
+
+	/// Message of the commit back to dev, replaces `dev_commit_message` from the config.
+	pub fn set_dev_commit_message(&mut self, template: &str) -> anyhow::Result<()> {
+		template::check(template, &COMMIT_PLACEHOLDERS, "commit message")?;
+		self.dev_message = Some(template.to_string());
+		Ok(())
+	}
+
+	/// Appends `trailer` to every commit message, after the ones from the config.
+	pub fn add_commit_trailer(&mut self, trailer: &str) -> anyhow::Result<()> {
+		template::check(trailer, &COMMIT_PLACEHOLDERS, "trailer")?;
+		self.commit_trailers.push(trailer.to_string());
+		Ok(())
+	}
+
+	/// Uses `backend` instead of opening the one selected with `set_vcs`.
+	pub fn set_vcs_backend(&mut self, backend: Box<dyn VcsBackend>) {
+		self.backend = RefCell::new(Some(backend));
+	}
+
+	/// Sends an `Event` for every step, and a final summary, while the release runs.
+	pub fn set_event_sender(&mut self, events: Sender<Event>) {
+		self.events = Some(events);
+	}
+
+	/// Prints the human readable status to stderr instead of stdout.
+	pub fn set_status_to_stderr(&mut self, status_to_stderr: bool) {
+		self.status = StatusSink::new(status_to_stderr);
+	}
+
+	/// Loads `omr-bumper.toml`, or the metadata table in `Cargo.toml`, from the path.
+	///
+	/// Call after `set_path`.
+	pub fn load_config(&mut self) -> anyhow::Result<()> {
+		self.config = Config::load(&self.path)?;
+		if let Some(steps) = self.config.steps() {
+			status!(self.status, "Using steps from {}", self.config.source());
+			self.steps = steps.clone();
+		}
+		Ok(())
+	}
+
+	fn dirty_policy(&self) -> DirtyPolicy {
+		self.dirty_policy
+			.or(self.config.dirty_policy())
+			.unwrap_or_default()
+	}
+
+	/// How to sign, `None` if signing is disabled.
+	fn signing(&self) -> Option<Signing> {
+		if !(self.sign || self.config.sign()) {
+			return None;
+		}
+		Some(Signing {
+			format:  self.signing_format.or(self.config.signing_format()),
+			key:     self
+				.signing_key
+				.clone()
+				.or(self.config.signing_key().map(|k| k.to_string())),
+			program: None,
+		})
+	}
+
+	/// Where to fetch from, and push to, `None` if neither configured, nor tracked by the current branch.
+	///
+	/// Remote, and branch come from the settings, then the config, then the tracking config of the branch.
+	fn upstream(&self, repo: &mut dyn VcsBackend) -> anyhow::Result<Option<Upstream>> {
+		let tracking = repo.upstream()?;
+		let remote = self
+			.remote
+			.as_deref()
+			.or(self.config.remote())
+			.or(tracking.as_ref().map(|t| t.remote.as_str()));
+		let Some(remote) = remote else {
+			return Ok(None);
+		};
+		let branch = match self
+			.target_branch
+			.as_deref()
+			.or(self.config.target_branch())
+			.or(tracking.as_ref().map(|t| t.branch.as_str()))
+		{
+			Some(branch) => branch.to_string(),
+			None => match repo.status()?.branch.head {
+				Some(head) => head,
+				None => return Ok(None),
+			},
+		};
+		Ok(Some(Upstream::new(remote, &branch)))
+	}
+
+	fn require_upstream(&self, repo: &mut dyn VcsBackend) -> anyhow::Result<Upstream> {
+		match self.upstream(repo)? {
+			Some(upstream) => Ok(upstream),
+			None => bail!(
+				"No upstream. Set one with `git branch --set-upstream-to`, or configure `remote`"
+			),
+		}
+	}
+
+	/// The steps of the command, without the ones disabled by the `skip_*` settings.
+	///
+	/// With a target version it replaces the suffix.
+	fn pipeline(&self) -> Vec<Step> {
+		let steps = match self.command {
+			Command::Release => self.steps.clone(),
+			Command::SetVersion => {
+				return vec![
+					Step::CargoLoadManifest,
+					Step::ManifestSetVersion,
+					Step::CargoSaveManifest,
+					Step::CargoUpdateWorkspace,
+					Step::ManifestPrintVersion,
+				]
+			},
+			Command::Bump => {
+				return vec![
+					Step::CargoLoadManifest,
+					Step::ManifestBumpLevel,
+					Step::CargoSaveManifest,
+					Step::CargoUpdateWorkspace,
+					Step::ManifestPrintVersion,
+				]
+			},
+			Command::Tag => {
+				let mut steps = vec![
+					Step::GitPreflight,
+					Step::GitEnsureClean,
+					Step::CargoLoadManifest,
+					Step::ManifestPrintVersion,
+				];
+				let tags: Vec<Step> = self.steps.iter().filter(|s| s.is_tag()).cloned().collect();
+				if tags.is_empty() {
+					steps.extend([Step::GitTag(None), Step::GitPushTag(None)]);
+				}
+				steps.extend(tags);
+				steps
+			},
+			Command::Status => {
+				return vec![
+					Step::CargoLoadManifest,
+					Step::GitShowStatus,
+					Step::GitShowDirty,
+				]
+			},
+			Command::Undo => {
+				return vec![
+					Step::GitEnsureClean,
+					Step::GitUndoRelease,
+					Step::CargoLoadManifest,
+					Step::GitShowStatus,
+				]
+			},
+		};
+		let skip_git = self.skip_git || self.skip_all;
+		let skip_push = self.skip_push || self.skip_all;
+		let skip_tag = self.skip_tag || self.skip_all;
+		steps
+			.iter()
+			.filter(|s| !(skip_git && s.is_git()))
+			.filter(|s| !(skip_push && s.is_push()))
+			.filter(|s| !(skip_tag && s.is_tag()))
+			.map(|s| match s {
+				Step::ManifestSetVersionSuffix if self.target_version.is_some() => {
+					Step::ManifestSetVersion
+				},
+				s => s.clone(),
+			})
+			.collect()
+	}
+
+	/// The template of a `GitTag` step, its own, then the settings, then the config.
+	///
+	/// Independently versioned packages default to `{package}-v{version}`.
+	fn tag_template(&self, name: &Option<String>, packages: bool) -> anyhow::Result<TagTemplate> {
+		let template = match (
+			name,
+			self.tag_template.as_ref().or(self.config.tag_template()),
+		) {
+			(Some(name), _) => TagTemplate::new(name)?,
+			(None, Some(template)) => template.clone(),
+			(None, None) if packages => TagTemplate::new("{package}-v{version}")?,
+			(None, None) => TagTemplate::new("{version}")?,
+		}
+		.with_build_metadata(self.tag_build_metadata || self.config.tag_build_metadata());
+		if packages && !template.has_package() {
+			bail!(
+				"Tag template `{}` needs `{{package}}`, every package gets a tag of its own",
+				template
+			);
+		}
+		Ok(template)
+	}
+
+	/// One tag for the release, or one per package, e.g. `foo-v1.2.3`.
+	fn tag_names(&self, name: &Option<String>, state: &State) -> anyhow::Result<Vec<String>> {
+		let template = self.tag_template(name, !state.package_versions.is_empty())?;
+		if state.package_versions.is_empty() {
+			let version = Version::parse(&state.release_version)?;
+			return Ok(vec![template.render(&version, None, &state.date)]);
+		}
+
+		let mut tags = Vec::new();
+		for (p, v) in state.package_versions.iter() {
+			tags.push(template.render(&Version::parse(v)?, Some(p), &state.date));
+		}
+		Ok(tags)
+	}
+
+	/// Checks the branch, and its upstream, returns what was found.
+	///
+	/// Ahead, and behind are as of the last fetch.
+	fn step_git_preflight(
+		&self,
+		repo: &mut dyn VcsBackend,
+		details: &mut Details,
+	) -> anyhow::Result<Vec<String>> {
+		let mut report = Vec::new();
+		let branch = repo.status()?.branch;
+		details.set("branch", branch.head.as_deref());
+		details.set("upstream", branch.upstream.as_deref());
+
+		let allowed: Vec<&str> = match self.config.branches() {
+			Some(branches) => branches.iter().map(|b| b.as_str()).collect(),
+			None => DEFAULT_BRANCHES.to_vec(),
+		};
+		let name = match &branch.head {
+			Some(name) if allowed.iter().any(|a| glob_match(a, name)) => name.clone(),
+			_ if self.allow_any_branch => {
+				let name = branch.head.clone().unwrap_or("HEAD".to_string());
+				report.push(format!(
+					"Releasing from {} because of --allow-any-branch",
+					name
+				));
+				name
+			},
+			Some(name) => bail!(
+				"Branch {} is not allowed for releases (allowed: {}). Configure `branches`, or use --allow-any-branch",
+				name,
+				allowed.join(", ")
+			),
+			None => bail!(
+				"HEAD is detached, releases are made from a branch. Check out one, or use --allow-any-branch"
+			),
+		};
+		report.push(format!("Branch: {}", name));
+
+		if !self.pipeline().iter().any(|s| s.is_push()) {
+			report.push("Not pushing, upstream not checked".to_string());
+			return Ok(report);
+		}
+		let upstream = match self.upstream(repo)? {
+			Some(upstream) => upstream,
+			None if self.allow_no_upstream => {
+				report.push("No upstream, continuing because of --allow-no-upstream".to_string());
+				return Ok(report);
+			},
+			None => bail!(
+				"Branch {} has no upstream. Set one with `git branch --set-upstream-to`, configure `remote`, or use --allow-no-upstream",
+				name
+			),
+		};
+		report.push(format!("Upstream: {}", upstream));
+		if branch.upstream != Some(upstream.to_string()) {
+			report.push(format!(
+				"Branch {} doesn't track {}, ahead, and behind not checked",
+				name, upstream
+			));
+			return Ok(report);
+		}
+		details.set("ahead", branch.ahead);
+		details.set("behind", branch.behind);
+
+		if branch.ahead > 0 && branch.behind > 0 {
+			if !self.allow_diverged {
+				bail!(
+					"Branch {} has diverged from {} ({} ahead, {} behind). Rebase it first, or use --allow-diverged",
+					name,
+					upstream,
+					branch.ahead,
+					branch.behind
+				);
+			}
+			report.push(format!(
+				"Diverged from {} ({} ahead, {} behind), continuing because of --allow-diverged",
+				upstream, branch.ahead, branch.behind
+			));
+		} else if branch.behind > 0 {
+			if !self.allow_behind {
+				bail!(
+					"Branch {} is {} commit(s) behind {}. Pull first, or use --allow-behind",
+					name,
+					branch.behind,
+					upstream
+				);
+			}
+			report.push(format!(
+				"{} commit(s) behind {}, continuing because of --allow-behind",
+				branch.behind, upstream
+			));
+		} else if branch.ahead > 0 {
+			report.push(format!(
+				"{} commit(s) ahead of {}, they are released too",
+				branch.ahead, upstream
+			));
+		}
+		Ok(report)
+	}
 
-		println!("Checking if repository is clean...");
+	fn step_git_ensure_clean(
+		&self,
+		repo: &mut dyn VcsBackend,
+		details: &mut Details,
+	) -> anyhow::Result<bool> {
+		status!(self.status, "Checking if repository is clean...");
 
 		// First check if Cargo.toml and Cargo.lock exist in the repository
 		let manifest_path = self.path.join("Cargo.toml");
 		if !manifest_path.exists() {
-			println!(
+			status!(
+				self.status,
 				"ERROR: Cargo.toml not found at: {}",
 				manifest_path.display()
 			);
@@ -155,172 +803,2682 @@ impl Release {
 
 		let lock_path = self.path.join("Cargo.lock");
 		if !lock_path.exists() {
-			println!("ERROR: Cargo.lock not found at: {}", lock_path.display());
-			println!("Run 'cargo build' in the repository to generate it first.");
+			status!(
+				self.status,
+				"ERROR: Cargo.lock not found at: {}",
+				lock_path.display()
+			);
+			status!(
+				self.status,
+				"Run 'cargo build' in the repository to generate it first."
+			);
 			return Ok(false);
 		}
 
-		let dirty = repo.get_dirty()?;
+		self.check_dirty(repo, details)
+	}
+
+	fn step_git_show_dirty(
+		&self,
+		repo: &mut dyn VcsBackend,
+		details: &mut Details,
+	) -> anyhow::Result<()> {
+		status!(self.status, "Checking if repository is clean...");
+		self.check_dirty(repo, details)?;
+		Ok(())
+	}
+
+	/// Reports the dirty files according to the dirty policy, returns `true` if there are none.
+	fn check_dirty(
+		&self,
+		repo: &mut dyn VcsBackend,
+		details: &mut Details,
+	) -> anyhow::Result<bool> {
+		let policy = self.dirty_policy();
+		let status = repo.status()?;
+		for u in status.untracked() {
+			if !u.is_dirty(policy) {
+				status!(
+					self.status,
+					"Untracked file (not considered dirty): {}",
+					&u.path
+				);
+			}
+		}
 
+		let dirty = status.dirty(policy);
+		let paths: Vec<&str> = dirty.iter().map(|d| d.path.as_str()).collect();
+		details.set("dirty", paths);
 		if !dirty.is_empty() {
-			println!("Dirty files:");
+			status!(self.status, "Dirty files ({} policy):", policy);
 			for d in dirty.iter() {
-				println!("{}", d);
+				status!(self.status, "{}", d);
 			}
 			//bail!("Repository is dirty");
 			return Ok(false);
 		}
-		println!("Repository is clean (enough)");
+		status!(self.status, "Repository is clean (enough)");
 		Ok(true)
 	}
-	fn step_git_show_dirty(&self) -> anyhow::Result<()> {
-		let mut repo = Repository::new(&self.path);
 
-		println!("Checking if repository is clean...");
+	fn commit_files(
+		&self,
+		repo: &mut dyn VcsBackend,
+		state: &State,
+	) -> anyhow::Result<Vec<String>> {
+		let mut files = Vec::new();
+		if let Some(m) = &state.manifests {
+			for path in m.modified_paths() {
+				files.push(self.relative_path(&path)?);
+			}
+		}
+		if let Some(changelog) = &state.changelog {
+			files.push(self.relative_path(changelog)?);
+		}
+		if !repo.check_ignore("Cargo.lock")? {
+			files.push("Cargo.lock".to_owned());
+		} else {
+			status!(self.status, "Not commiting ignored Cargo.lock");
+		}
+		Ok(files)
+	}
 
-		let dirty = repo.get_dirty()?;
+	/// Files and message of every commit for a `GitCommitManifest` step.
+	///
+	/// With `commit_per_package` every package gets a commit with its manifest,
+	/// the last one also takes all remaining files.
+	fn commits(
+		&self,
+		repo: &mut dyn VcsBackend,
+		state: &State,
+		m: &GitCommitMessage,
+	) -> anyhow::Result<Vec<(Vec<String>, String)>> {
+		let files = self.commit_files(repo, state)?;
+		let manifests = match &state.manifests {
+			Some(manifests) if self.commit_per_package && !state.package_versions.is_empty() => {
+				manifests
+			},
+			_ => return Ok(vec![(files, self.commit_message(m, state, None)?)]),
+		};
 
-		if !dirty.is_empty() {
-			println!("Dirty files:");
-			for d in dirty.iter() {
-				println!("{}", d);
-			}
-			//bail!("Repository is dirty");
-			return Ok(());
+		let mut remaining = files;
+		let mut commits = Vec::new();
+		for (p, v) in state.package_versions.iter() {
+			let path = self.relative_path(&manifests.package_manifest_path(p)?)?;
+			remaining.retain(|f| *f != path);
+			commits.push((vec![path], self.commit_message(m, state, Some((p, v)))?));
 		}
-		println!("Repository is clean (enough)");
-		Ok(())
+		if let Some((files, _)) = commits.last_mut() {
+			files.append(&mut remaining);
+		}
+		Ok(commits)
 	}
 
-	pub fn run(&self) -> anyhow::Result<()> {
-		let mut manifest = None;
-		let mut release_version = "".to_string();
-		for s in &self.steps {
-			eprintln!("Step: {:?}", &s);
-			match s {
-				Step::GitEnsureClean => {
-					if !self.step_git_ensure_clean()? {
-						if !self.allow_dirty {
-							println!("Not clean! STOPPING!");
-							return Ok(());
-						} else {
-							println!("Not clean! I hope you know what you do!");
-						}
-					}
-				},
-				Step::GitShowDirty => {
-					self.step_git_show_dirty()?;
-				},
-				Step::CargoLoadManifest => {
-					let mut new_manifest = Manifest::new(&self.path.join("Cargo.toml"));
-					new_manifest.load()?;
-					manifest = Some(new_manifest);
-					println!("Loaded manifest");
-				},
-				Step::CargoSaveManifest => {
-					if let Some(m) = &mut manifest {
-						m.save()?;
-					} else {
-						bail!("Tried to save manifest without manifest");
-					}
-				},
-				Step::ManifestSetVersionSuffix => {
-					if let Some(m) = &mut manifest {
-						m.set_version_suffix(&self.pre_release_suffix)?;
-					} else {
-						bail!("Tried to set suffix without manifest");
-					}
-				},
-				Step::ManifestSetVersionSuffixDev => {
-					if let Some(m) = &mut manifest {
-						m.set_version_suffix("dev")?;
-					} else {
-						bail!("Tried to set dev suffix without manifest");
-					}
-				},
-				Step::ManifestBumpLevel => {
-					if let Some(m) = &mut manifest {
-						match self.bump_level {
-							BumpLevel::Patch => m.bump_patch_version()?,
-							BumpLevel::Minor => m.bump_minor_version()?,
-							BumpLevel::Major => m.bump_major_version()?,
-						};
-					} else {
-						bail!("Tried to bump level without manifest");
-					}
-				},
-				Step::CargoUpdateWorkspace => {
-					let mut cargo = Cargo::new(&self.path);
-					cargo.open()?;
-					cargo.update_workspace()?;
-				},
-				Step::ManifestPrintVersion => {
-					if let Some(m) = &mut manifest {
-						release_version = m.get_pretty_version()?;
-						println!("Release version: {}", &release_version);
-					} else {
-						bail!("Tried to print version without manifest");
-					}
-				},
-				Step::GitCommitManifest(m) => {
-					let mut repo = Repository::new(&self.path);
+	/// The template of a commit message, the settings, then the config, then the default.
+	fn commit_template(&self, m: &GitCommitMessage) -> &str {
+		let configured = match m {
+			GitCommitMessage::BumpVersionForRelease => &self.release_message,
+			GitCommitMessage::BumpVersionForDev => &self.dev_message,
+		};
+		configured
+			.as_deref()
+			.or(self.config.commit_message(m))
+			.unwrap_or(m.default_template())
+	}
 
-					let mut files = Vec::new();
-					files.push("Cargo.toml".to_owned());
-					if !repo.check_ignore("Cargo.lock")? {
-						files.push("Cargo.lock".to_owned());
-					} else {
-						println!("Not commiting ignored Cargo.lock");
-					}
+	/// Glob matching the first line of every message rendered from the template.
+	fn commit_glob(&self, m: &GitCommitMessage) -> anyhow::Result<String> {
+		let template = self.commit_template(m);
+		let first_line = template.lines().next().unwrap_or_default();
+		template::expand(first_line, "commit message", |_| "*".to_string())
+	}
 
-					//let files = vec!["Cargo.toml".to_owned(), "Cargo.lock".to_owned()];
-					let msg = match m {
-						GitCommitMessage::BumpVersionForRelease => {
-							format!(
-								": Bump version for {} release - {}",
-								&self.pre_release_suffix, &release_version
-							)
-						},
-						GitCommitMessage::BumpVersionForDev => {
-							format!(
-								": Bump version back to dev release, and bump patch level - {}",
-								&release_version
-							)
-						},
-						#[allow(unreachable_patterns)]
-						o => format!(":TODO: {:?}", &o),
-					};
+	/// Version, branch, upstream, and the last release, for `status`.
+	fn status_report(
+		&self,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+		details: &mut Details,
+	) -> anyhow::Result<Vec<String>> {
+		let mut report = Vec::new();
+		if let Some(m) = &state.manifests {
+			report.push(format!("Version: {}", m.get_pretty_versions()?));
+		}
 
-					repo.commit(&files, &msg)?;
-				},
-				Step::GitFetch => {
-					let mut repo = Repository::new(&self.path);
-					if repo.fetch()? > 0 {
-						bail!("Fetch was not empty. Please resolve manually!")
-					};
-				},
-				Step::GitRebase => {
-					let mut repo = Repository::new(&self.path);
-					repo.rebase()?;
-				},
-				Step::GitPush => {
-					let mut repo = Repository::new(&self.path);
-					repo.push()?;
-				},
-				Step::GitTag => {
-					let mut repo = Repository::new(&self.path);
-					let tag_msg = format!(". Tag {}", &release_version);
-					repo.tag(&release_version, &tag_msg)?;
-				},
-				Step::GitPushTag => {
-					let mut repo = Repository::new(&self.path);
-					repo.push_tag(&release_version)?;
+		let branch = repo.status()?.branch;
+		details.set("branch", branch.head.as_deref());
+		details.set("upstream", branch.upstream.as_deref());
+		match &branch.head {
+			Some(name) => report.push(format!("Branch: {}", name)),
+			None => report.push("HEAD is detached".to_string()),
+		}
+		match &branch.upstream {
+			Some(upstream) => {
+				details.set("ahead", branch.ahead);
+				details.set("behind", branch.behind);
+				report.push(format!(
+					"Upstream: {}, {} ahead, {} behind, as of the last fetch",
+					upstream, branch.ahead, branch.behind
+				));
+			},
+			None => report.push("No upstream".to_string()),
+		}
+
+		let packages: Vec<Option<&str>> = match self.packages.is_empty() {
+			true => vec![None],
+			false => self.packages.iter().map(|p| Some(p.as_str())).collect(),
+		};
+		for package in packages {
+			let pattern = self.tag_pattern(package)?;
+			let of = package.map(|p| format!(" of {}", p)).unwrap_or_default();
+			match self.last_release_tag(package, &[], repo)? {
+				Some(tag) => {
+					let since = repo.commit_messages(Some(&tag))?.len();
+					report.push(format!(
+						"Last release{}: {}, {} commit(s) since",
+						of, tag, since
+					));
+					details.push("last_tags", tag);
 				},
-				#[allow(unreachable_patterns)]
-				s => eprintln!("Step {:?} not handled yet", &s),
+				None => report.push(format!(
+					"Last release{}: none, no tag matches {}",
+					of, pattern
+				)),
 			}
 		}
 
-		Ok(())
+		// other pipelines have a journal of their own
+		let journal = Journal::new(&repo.git_dir()?);
+		if journal.exists() && self.pipeline().iter().all(|s| s.is_read_only()) {
+			report.push(format!(
+				"Unfinished release, see {:?}. Use --resume to continue",
+				journal.path()
+			));
+		}
+		Ok(report)
+	}
+
+	/// The tags of the last release, the commit before it, and how many commits it made.
+	///
+	/// Fails unless HEAD is the release, or the commits back to dev after it.
+	fn last_release(
+		&self,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<(Vec<String>, String, usize)> {
+		let packages = !self.packages.is_empty();
+		let mut templates = Vec::new();
+		for s in self.steps.iter() {
+			if let Step::GitTag(name) = s {
+				templates.push(self.tag_template(name, packages)?);
+			}
+		}
+		let Some(first) = templates.first() else {
+			bail!("Releases aren't tagged, there is no `GitTag` step, nothing to undo");
+		};
+		let pattern = first.pattern(None);
+		let Some(tag) = last_tag(repo, first, None, &[])? else {
+			bail!("No release tag matches {}, nothing to undo", pattern);
+		};
+		let after = repo.commit_messages(Some(&tag))?;
+
+		// the other tags of the release are on the same commit
+		let mut tags = vec![tag.clone()];
+		for template in templates.iter() {
+			while let Some(t) = last_tag(repo, template, None, &tags)? {
+				if repo.commit_messages(Some(&t))?.len() != after.len() {
+					break;
+				}
+				tags.push(t);
+			}
+		}
+
+		let per_package = match self.commit_per_package && packages {
+			true => tags.iter().filter(|t| glob_match(&pattern, t)).count(),
+			false => 1,
+		};
+		let commits = |m: GitCommitMessage| {
+			let steps = self
+				.steps
+				.iter()
+				.filter(|s| **s == Step::GitCommitManifest(m.clone()));
+			steps.count() * per_package
+		};
+		let release_commits = commits(GitCommitMessage::BumpVersionForRelease);
+		let dev_commits = commits(GitCommitMessage::BumpVersionForDev);
+		let first_line = |m: &str| m.lines().next().unwrap_or_default().to_string();
+
+		let dev = self.commit_glob(&GitCommitMessage::BumpVersionForDev)?;
+		if after.len() > dev_commits {
+			bail!(
+				"HEAD is {} commit(s) past {}, the release isn't the last thing on the branch, nothing undone",
+				after.len(),
+				tag
+			);
+		}
+		if let Some((id, m)) = after
+			.iter()
+			.find(|(_, m)| !glob_match(&dev, &first_line(m)))
+		{
+			bail!(
+				"Commit {} after {} isn't a commit back to dev, nothing undone: {}",
+				id,
+				tag,
+				first_line(m)
+			);
+		}
+
+		// peeled explicitly, gix doesn't go from an annotated tag to the parents of its commit
+		let base = format!("{}^{{commit}}~{}", tag, release_commits);
+		let release = self.commit_glob(&GitCommitMessage::BumpVersionForRelease)?;
+		let removed = repo.commit_messages(Some(&base))?;
+		let is_release = removed.len() == after.len() + release_commits
+			&& removed[after.len()..]
+				.iter()
+				.all(|(_, m)| glob_match(&release, &first_line(m)));
+		if !is_release {
+			bail!("Tag {} isn't on a release commit, nothing undone", tag);
+		}
+		Ok((tags, base, removed.len()))
+	}
+
+	/// The message of a `GitCommitManifest` step, for all packages, or one of them.
+	fn commit_message(
+		&self,
+		m: &GitCommitMessage,
+		state: &State,
+		package: Option<(&str, &str)>,
+	) -> anyhow::Result<String> {
+		let template = self.commit_template(m);
+
+		let (old_version, new_version, packages) = match package {
+			Some((p, v)) => {
+				let old_version = state
+					.previous_packages
+					.iter()
+					.find(|(name, _)| name == p)
+					.map(|(_, v)| v.as_str())
+					.unwrap_or(&state.previous_version);
+				// like the pretty versions of all packages, e.g. `foo 1.2.3`
+				(
+					format!("{} {}", p, old_version),
+					format!("{} {}", p, v),
+					p.to_string(),
+				)
+			},
+			None => (
+				state.previous_version.clone(),
+				state.release_version.clone(),
+				self.package_names(state).join(", "),
+			),
+		};
+		let bump_level = match (state.bump_level, self.auto_bump_level) {
+			(Some(level), _) => level.to_string(),
+			(None, true) => "auto".to_string(),
+			(None, false) => self.bump_level.to_string(),
+		};
+		let render = |t: &str| {
+			template::expand(t, "commit message", |p| match p {
+				"old_version" => old_version.clone(),
+				"new_version" => new_version.clone(),
+				"bump_level" => bump_level.clone(),
+				"suffix" => self.suffix_name().to_string(),
+				"packages" => packages.clone(),
+				_ => String::new(),
+			})
+		};
+
+		let mut message = render(template)?;
+		let trailers = self
+			.config
+			.commit_trailers()
+			.iter()
+			.chain(self.commit_trailers.iter());
+		for (i, trailer) in trailers.enumerate() {
+			message.push_str(if i == 0 { "\n\n" } else { "\n" });
+			message.push_str(&render(trailer)?);
+		}
+		Ok(message)
+	}
+
+	/// The independently versioned packages, or every package with the workspace version.
+	fn package_names(&self, state: &State) -> Vec<String> {
+		if !state.package_versions.is_empty() {
+			return state
+				.package_versions
+				.iter()
+				.map(|(p, _)| p.clone())
+				.collect();
+		}
+		match &state.manifests {
+			Some(m) => m
+				.manifests()
+				.iter()
+				.filter(|m| m.has_version() || m.inherits_version())
+				.filter_map(|m| m.package_name())
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	fn tag_message(&self, tag: &str) -> String {
+		format!(". Tag {}", &tag)
+	}
+
+	fn manifest_path(&self) -> PathBuf {
+		self.path.join("Cargo.toml")
+	}
+
+	/// All manifests the release might change, and the changelog once it was written.
+	fn tracked_files(&self, state: &State) -> Vec<PathBuf> {
+		let mut files = match &state.manifests {
+			Some(m) => m.manifests().iter().map(|m| m.path().to_owned()).collect(),
+			None => vec![self.manifest_path()],
+		};
+		files.extend(state.changelog.clone());
+		files
+	}
+
+	fn changelog(&self) -> Changelog {
+		let path = self.config.changelog_path().unwrap_or(CHANGELOG_FILE);
+		Changelog::new(&self.path.join(path), self.config.changelog_groups())
+	}
+
+	/// `path` relative to the repository path, as expected by git.
+	fn relative_path(&self, path: &Path) -> anyhow::Result<String> {
+		let root = self.path.absolutize()?;
+		let path = path.absolutize()?;
+		let relative = path.strip_prefix(&root).unwrap_or(&path);
+		Ok(relative.display().to_string())
+	}
+
+	fn load_manifests(&self) -> anyhow::Result<Manifests> {
+		let mut cargo = Cargo::new(&self.path);
+		cargo.open()?;
+		let members = cargo.member_manifests()?;
+		let mut manifests = Manifests::load(&self.manifest_path(), &members)?;
+		self.select_packages(&cargo, &mut manifests)?;
+		Ok(manifests)
+	}
+
+	/// Selects the packages to version independently, in dependency order.
+	fn select_packages(&self, cargo: &Cargo, manifests: &mut Manifests) -> anyhow::Result<()> {
+		if self.packages.is_empty() {
+			return Ok(());
+		}
+		let order = cargo.members_in_dependency_order()?;
+		for p in self.packages.iter() {
+			if !order.contains(p) {
+				bail!("Package {} is not a member of the workspace", p);
+			}
+		}
+		let packages: Vec<String> = order
+			.into_iter()
+			.filter(|p| self.packages.contains(p))
+			.collect();
+		manifests.select_packages(&packages)
+	}
+
+	fn step_names(&self) -> Vec<String> {
+		self.pipeline().iter().map(|s| s.to_string()).collect()
+	}
+
+	/// Restores the state of an unfinished release from its journal,
+	/// returns the index of the first incomplete step.
+	fn restore_state(
+		&self,
+		repo: &mut dyn VcsBackend,
+		journal: &mut Journal,
+	) -> anyhow::Result<(usize, State)> {
+		if !journal.exists() {
+			bail!(
+				"No unfinished release found at {:?}. Nothing to resume",
+				journal.path()
+			);
+		}
+		journal.load()?;
+
+		if journal.steps() != self.step_names() {
+			bail!("Steps changed since the journal was written. Refusing to resume");
+		}
+		let head = repo.head()?;
+		if journal.head() != head {
+			bail!(
+				"HEAD changed since the journal was written ({} -> {}). Refusing to resume",
+				journal.head(),
+				&head
+			);
+		}
+		for (path, contents) in journal.files() {
+			if std::fs::read_to_string(path)? != *contents {
+				bail!(
+					"{} changed since the journal was written. Refusing to resume",
+					path.display()
+				);
+			}
+		}
+
+		let mut state = State {
+			release_version: journal.release_version().to_string(),
+			..Default::default()
+		};
+		state.package_versions = journal.package_versions().to_vec();
+		state.changelog = journal.changelog().map(|c| c.to_owned());
+		state.date = journal.date().to_string();
+		state.previous_version = journal.previous_version().to_string();
+		state.previous_packages = journal.previous_package_versions().to_vec();
+		state.bump_level = match journal.bump_level() {
+			Some(level) => Some(level.parse()?),
+			None => None,
+		};
+		if !journal.manifests().is_empty() {
+			let mut manifests = Manifests::from_states(journal.manifests())?;
+			let mut cargo = Cargo::new(&self.path);
+			cargo.open()?;
+			self.select_packages(&cargo, &mut manifests)?;
+			state.manifests = Some(manifests);
+		}
+
+		Ok((journal.completed(), state))
+	}
+
+	fn update_journal(
+		&self,
+		repo: &mut dyn VcsBackend,
+		journal: &mut Journal,
+		completed: usize,
+		state: &State,
+	) -> anyhow::Result<()> {
+		// the journal stays as it was saved last if anything fails
+		let head = repo.head()?;
+		let mut files = Vec::new();
+		for path in self.tracked_files(state) {
+			let contents = std::fs::read_to_string(&path)?;
+			files.push((path, contents));
+		}
+		journal.set_completed(completed);
+		journal.set_release_version(&state.release_version);
+		journal.set_package_versions(state.package_versions.clone());
+		journal.set_changelog(state.changelog.clone());
+		journal.set_date(&state.date);
+		journal.set_previous_version(&state.previous_version);
+		journal.set_previous_package_versions(state.previous_packages.clone());
+		journal.set_bump_level(state.bump_level.map(|l| l.to_string()));
+		journal.set_head(&head);
+		journal.set_files(files);
+		journal.set_manifests(match &state.manifests {
+			Some(m) => m.states(),
+			None => Vec::new(),
+		});
+		journal.save()
+	}
+
+	pub fn run(&self) -> anyhow::Result<()> {
+		let start = Instant::now();
+		let mut progress = Progress::default();
+		let result = self.run_pipeline(&mut progress);
+		let (status, error) = match &result {
+			Ok(status) => (*status, None),
+			Err(e) => (Status::Failed, Some(format!("{:#}", e))),
+		};
+		self.emit(Event::Summary {
+			status,
+			release_version: progress.release_version,
+			version: progress.version,
+			tags: progress.tags,
+			completed_steps: progress.completed,
+			total_steps: progress.total,
+			duration_ms: event::millis(start.elapsed()),
+			error,
+		});
+		result.map(|_| ())
+	}
+
+	fn run_pipeline(&self, progress: &mut Progress) -> anyhow::Result<Status> {
+		if self.dry_run {
+			let plan = self.plan()?;
+			progress.total = plan.entries().len();
+			for e in plan.entries() {
+				self.emit(Event::StepPlanned {
+					index:   e.index(),
+					step:    e.step().to_string(),
+					actions: e.actions().to_vec(),
+				});
+			}
+			status!(self.status, "{}", plan);
+			return Ok(Status::DryRun);
+		}
+
+		self.with_vcs(|repo| self.run_steps(repo, progress))
+	}
+
+	/// Runs `f` with the backend from `set_vcs_backend`, or a newly opened one.
+	fn with_vcs<T>(
+		&self,
+		f: impl FnOnce(&mut dyn VcsBackend) -> anyhow::Result<T>,
+	) -> anyhow::Result<T> {
+		match self.backend.borrow_mut().as_mut() {
+			Some(backend) => f(backend.as_mut()),
+			None => f(self.vcs.open_with_status(&self.path, self.status)?.as_mut()),
+		}
+	}
+
+	fn run_steps(
+		&self,
+		repo: &mut dyn VcsBackend,
+		progress: &mut Progress,
+	) -> anyhow::Result<Status> {
+		let steps = self.pipeline();
+		progress.total = steps.len();
+		let mut journal = Journal::new(&repo.git_dir()?);
+		// e.g. `status`, it must not touch the journal of an unfinished release
+		let journaled = !steps.iter().all(|s| s.is_read_only());
+
+		let (first, mut state) = if !journaled {
+			(0, State::new())
+		} else if self.resume {
+			let (first, state) = self.restore_state(repo, &mut journal)?;
+			status!(
+				self.status,
+				"Resuming release {} at step {}/{}",
+				&state.release_version,
+				first + 1,
+				steps.len()
+			);
+			(first, state)
+		} else {
+			if journal.exists() {
+				bail!(
+					"Found journal of an unfinished release at {:?}. Use --resume to continue, or remove it to start over",
+					journal.path()
+				);
+			}
+			journal.set_steps(self.step_names());
+			(0, State::new())
+		};
+		progress.completed = first;
+		progress.version = state.release_version.clone();
+
+		let mut rollback = Rollback::new(self.status);
+		for (i, s) in steps.iter().enumerate() {
+			if i < first {
+				self.emit_skipped(i, s, "completed before --resume");
+				continue;
+			}
+			status!(self.status, "Step: {}", &s);
+			self.emit(Event::StepStarted {
+				index: i,
+				step:  s.to_string(),
+			});
+			let started = Instant::now();
+			let mut details = Details::default();
+			repo.take_output();
+			let mut not_reversible = None;
+			let result = self
+				.prepare_rollback(s, &state, repo, &mut rollback)
+				.and_then(|r| {
+					not_reversible = r;
+					self.run_step(s, &mut state, repo, &mut details)
+				});
+			for (command, stdout) in repo.take_output() {
+				details.push("git", json!({ "command": command, "stdout": stdout }));
+			}
+			progress.version = state.release_version.clone();
+			match result {
+				Ok(true) => {
+					self.emit(Event::StepSucceeded {
+						index:       i,
+						step:        s.to_string(),
+						duration_ms: event::millis(started.elapsed()),
+						details:     details.take(),
+					});
+				},
+				Ok(false) => {
+					details.set("stop", true);
+					self.emit(Event::StepSucceeded {
+						index:       i,
+						step:        s.to_string(),
+						duration_ms: event::millis(started.elapsed()),
+						details:     details.take(),
+					});
+					progress.completed = i + 1;
+					for (j, s) in steps.iter().enumerate().skip(i + 1) {
+						self.emit_skipped(j, s, "release stopped");
+					}
+					if journaled {
+						journal.remove()?;
+					}
+					return Ok(Status::Stopped);
+				},
+				Err(e) => {
+					self.emit(Event::StepFailed {
+						index:       i,
+						step:        s.to_string(),
+						duration_ms: event::millis(started.elapsed()),
+						error:       format!("{:#}", e),
+						details:     details.take(),
+					});
+					self.roll_back(repo, &mut rollback, &journal, journaled)?;
+					return Err(e);
+				},
+			}
+			match s {
+				Step::GitTag(name) => {
+					progress.tags.extend(self.tag_names(name, &state)?);
+					progress.release_version = state.release_version.clone();
+				},
+				Step::GitCommitManifest(GitCommitMessage::BumpVersionForRelease) => {
+					progress.release_version = state.release_version.clone();
+				},
+				_ => {},
+			}
+			progress.completed = i + 1;
+			let saved = match journaled {
+				true => self.update_journal(repo, &mut journal, i + 1, &state),
+				false => Ok(()),
+			};
+			// even if the journal wasn't saved, a resume repeats the step then
+			let completed =
+				self.complete_rollback(s, &state, not_reversible, &journal, &mut rollback);
+			if let Err(e) = saved.and(completed) {
+				self.roll_back(repo, &mut rollback, &journal, journaled)?;
+				return Err(e);
+			}
+		}
+
+		if journaled {
+			journal.remove()?;
+		}
+		Ok(Status::Success)
+	}
+
+	/// Undoes what the release did locally, unless `no_rollback` is set.
+	fn roll_back(
+		&self,
+		repo: &mut dyn VcsBackend,
+		rollback: &mut Rollback,
+		journal: &Journal,
+		journaled: bool,
+	) -> anyhow::Result<()> {
+		if self.no_rollback {
+			status!(self.status, "Not rolling back because of --no-rollback");
+			return Ok(());
+		}
+		match rollback.run(repo) {
+			Ok(true) if journaled => journal.remove()?,
+			Ok(true) => {},
+			Ok(false) => {},
+			Err(re) => status!(self.status, "Error: {}", re),
+		}
+		Ok(())
+	}
+
+	fn emit(&self, event: Event) {
+		if let Some(events) = &self.events {
+			// nobody listening anymore is fine
+			let _ = events.send(event);
+		}
+	}
+
+	fn emit_skipped(&self, index: usize, step: &Step, reason: &str) {
+		self.emit(Event::StepSkipped {
+			index,
+			step: step.to_string(),
+			reason: reason.to_string(),
+		});
+	}
+
+	/// Registers how to undo `step`, before it runs.
+	///
+	/// Returns what can't be undone once the step succeeded, e.g. a push.
+	fn prepare_rollback(
+		&self,
+		step: &Step,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+		rollback: &mut Rollback,
+	) -> anyhow::Result<Option<String>> {
+		match step {
+			Step::CargoSaveManifest => {
+				for path in self.tracked_files(state) {
+					rollback.restore_file(&path)?;
+				}
+			},
+			Step::CargoUpdateWorkspace => rollback.restore_file(&self.path.join("Cargo.lock"))?,
+			Step::ChangelogUpdate => rollback.restore_file(self.changelog().path())?,
+			Step::GitCommitManifest(_) => rollback.reset_head(&repo.head()?),
+			Step::GitRebase => {
+				rollback.reset_head(&repo.head()?);
+				rollback.abort_rebase();
+			},
+			Step::GitPush => {
+				let upstream = self.require_upstream(repo)?;
+				return Ok(Some(format!("pushed to {}", upstream)));
+			},
+			Step::GitPushTag(name) => {
+				let upstream = self.require_upstream(repo)?;
+				return Ok(Some(format!(
+					"pushed tag {} to {}",
+					self.tag_names(name, state)?.join(", "),
+					upstream.remote
+				)));
+			},
+			_ => {},
+		}
+		Ok(None)
+	}
+
+	/// Registers how to undo `step`, for steps that can only be undone once they succeeded,
+	/// and what `prepare_rollback` found can't be undone.
+	fn complete_rollback(
+		&self,
+		step: &Step,
+		state: &State,
+		not_reversible: Option<String>,
+		journal: &Journal,
+		rollback: &mut Rollback,
+	) -> anyhow::Result<()> {
+		if let Step::GitTag(name) = step {
+			for tag in self.tag_names(name, state)? {
+				rollback.delete_tag(&tag);
+			}
+		}
+		if let Some(description) = not_reversible {
+			rollback.not_reversible(&description, journal);
+		}
+		Ok(())
+	}
+
+	/// Executes a single step, returns `false` if the release should stop.
+	fn run_step(
+		&self,
+		step: &Step,
+		state: &mut State,
+		repo: &mut dyn VcsBackend,
+		details: &mut Details,
+	) -> anyhow::Result<bool> {
+		match step {
+			Step::GitPreflight => {
+				status!(self.status, "Checking branch, and upstream...");
+				for line in self.step_git_preflight(repo, details)? {
+					status!(self.status, "{}", line);
+					details.push("report", line);
+				}
+			},
+			Step::GitEnsureClean => {
+				let clean = self.step_git_ensure_clean(repo, details)?;
+				details.set("clean", clean);
+				if !clean {
+					if !self.allow_dirty {
+						status!(self.status, "Not clean! STOPPING!");
+						return Ok(false);
+					} else {
+						status!(self.status, "Not clean! I hope you know what you do!");
+					}
+				}
+			},
+			Step::GitShowDirty => {
+				self.step_git_show_dirty(repo, details)?;
+			},
+			Step::GitShowStatus => {
+				for line in self.status_report(state, repo, details)? {
+					status!(self.status, "{}", line);
+					details.push("report", line);
+				}
+			},
+			Step::GitUndoRelease => {
+				let changed = repo.status()?.dirty(DirtyPolicy::Tracked).len();
+				if changed > 0 {
+					bail!(
+						"Undo resets the working tree, {} file(s) have changes. Commit, or stash them first",
+						changed
+					);
+				}
+				let (tags, base, commits) = self.last_release(repo)?;
+				repo.reset_hard(&base)?;
+				let head = repo.head()?;
+				status!(
+					self.status,
+					"Reset to {}, before {}, removed {} commit(s)",
+					head,
+					tags[0],
+					commits
+				);
+				details.set("head", head.as_str());
+				details.set("removed_commits", commits);
+				for tag in tags {
+					repo.delete_tag(&tag)?;
+					status!(self.status, "Deleted tag {}", tag);
+					details.push("tags", tag);
+				}
+				if repo.status()?.branch.behind > 0 {
+					status!(
+						self.status,
+						"The release was pushed already, the remote keeps it, and its tags"
+					);
+				}
+			},
+			Step::CargoLoadManifest => {
+				let manifests = self.load_manifests()?;
+				status!(
+					self.status,
+					"Loaded {} manifest(s)",
+					manifests.manifests().len()
+				);
+				for m in manifests.manifests() {
+					details.push("manifests", m.path().display().to_string());
+				}
+				details.set("version", manifests.get_pretty_versions()?);
+				state.previous_version = manifests.get_pretty_versions()?;
+				state.previous_packages = package_versions(&manifests)?;
+				state.manifests = Some(manifests);
+			},
+			Step::CargoSaveManifest => {
+				if let Some(m) = &mut state.manifests {
+					for path in m.modified_paths() {
+						details.push("files", path.display().to_string());
+					}
+					m.save()?;
+				} else {
+					bail!("Tried to save manifest without manifest");
+				}
+			},
+			Step::ManifestSetVersionSuffix => {
+				if let Some(mut m) = state.manifests.take() {
+					let r = self
+						.set_pre_release(&mut m, state, repo)
+						.and_then(|()| self.apply_build_metadata(&mut m, state, repo));
+					details.set("version", m.get_pretty_versions()?);
+					state.manifests = Some(m);
+					r?;
+				} else {
+					bail!("Tried to set suffix without manifest");
+				}
+			},
+			Step::ManifestSetVersionSuffixDev => {
+				if let Some(mut m) = state.manifests.take() {
+					let r = m
+						.set_version_suffix("dev")
+						.and_then(|()| self.apply_build_metadata(&mut m, state, repo));
+					details.set("version", m.get_pretty_versions()?);
+					state.manifests = Some(m);
+					r?;
+				} else {
+					bail!("Tried to set dev suffix without manifest");
+				}
+			},
+			Step::ManifestSetVersion => {
+				if let Some(mut m) = state.manifests.take() {
+					let r = self
+						.apply_target_version(&mut m, repo)
+						.and_then(|()| self.apply_build_metadata(&mut m, state, repo));
+					details.set("version", m.get_pretty_versions()?);
+					state.manifests = Some(m);
+					r?;
+				} else {
+					bail!("Tried to set version without manifest");
+				}
+			},
+			Step::ManifestBumpLevel => {
+				if let Some(mut m) = state.manifests.take() {
+					let r = self.bump_manifest(&mut m, state, repo);
+					details.set("version", m.get_pretty_versions()?);
+					state.manifests = Some(m);
+					let (level, report) = r?;
+					state.bump_level = Some(level);
+					details.set("bump_level", level.to_string());
+					for line in report {
+						status!(self.status, "{}", line);
+						details.push("report", line);
+					}
+				} else {
+					bail!("Tried to bump level without manifest");
+				}
+			},
+			Step::CargoUpdateWorkspace => {
+				let mut cargo = Cargo::new(&self.path);
+				cargo.open()?;
+				cargo.update_workspace(self.status)?;
+			},
+			Step::ManifestPrintVersion => {
+				if let Some(m) = &mut state.manifests {
+					state.release_version = m.get_pretty_versions()?;
+					state.package_versions = package_versions(m)?;
+					status!(self.status, "Release version: {}", &state.release_version);
+					details.set("version", state.release_version.as_str());
+					for (p, v) in state.package_versions.iter() {
+						details.push("package_versions", json!({ "package": p, "version": v }));
+					}
+				} else {
+					bail!("Tried to print version without manifest");
+				}
+			},
+			Step::ChangelogUpdate => {
+				let changelog = self.changelog();
+				if self.config.changelog() || changelog.path().exists() {
+					let (_, commits) = self.commits_since_last_release(None, state, repo)?;
+					changelog.update(&state.release_version, &state.date, &commits)?;
+					status!(self.status, "Updated {}", changelog.path().display());
+					details.set("file", changelog.path().display().to_string());
+					state.changelog = Some(changelog.path().to_owned());
+				} else {
+					status!(self.status, "No {}, skipping", changelog.path().display());
+				}
+			},
+			Step::GitCommitManifest(m) => {
+				for (files, msg) in self.commits(repo, state, m)? {
+					repo.commit(&files, &msg, self.signing().as_ref())?;
+					details.push("commits", json!({ "files": files, "message": msg }));
+				}
+				state.changelog = None;
+				state.previous_version = state.release_version.clone();
+				state.previous_packages = state.package_versions.clone();
+			},
+			Step::GitFetch => {
+				let upstream = self.require_upstream(repo)?;
+				details.set("upstream", upstream.to_string());
+				if repo.fetch(&upstream)? > 0 {
+					bail!("Fetch was not empty. Please resolve manually!")
+				};
+			},
+			Step::GitRebase => {
+				let upstream = self.require_upstream(repo)?;
+				details.set("upstream", upstream.to_string());
+				repo.rebase(&upstream, self.signing().as_ref())?;
+			},
+			Step::GitPush => {
+				let upstream = self.require_upstream(repo)?;
+				details.set("upstream", upstream.to_string());
+				repo.push(&upstream)?;
+			},
+			Step::GitTag(name) => {
+				// the tags aren't rolled back yet, the step didn't complete
+				let mut created = Vec::new();
+				for tag in self.tag_names(name, state)? {
+					if let Err(e) = self.create_tag(repo, &tag, &mut created, details) {
+						for tag in created.iter().rev() {
+							repo.delete_tag(tag)?;
+						}
+						return Err(e);
+					}
+				}
+			},
+			Step::GitPushTag(name) => {
+				let upstream = self.require_upstream(repo)?;
+				for tag in self.tag_names(name, state)? {
+					repo.push_tag(&upstream.remote, &tag)?;
+					details.push("tags", tag);
+				}
+			},
+		}
+
+		Ok(true)
+	}
+
+	/// Creates, and verifies the tag, adds it to `created` as soon as it exists.
+	fn create_tag(
+		&self,
+		repo: &mut dyn VcsBackend,
+		tag: &str,
+		created: &mut Vec<String>,
+		details: &mut Details,
+	) -> anyhow::Result<()> {
+		let signing = self.signing();
+		repo.tag(tag, &self.tag_message(tag), signing.as_ref())?;
+		created.push(tag.to_string());
+		if let Some(signing) = &signing {
+			repo.verify_tag(tag, signing)?;
+			status!(self.status, "Verified signature of tag {}", tag);
+			details.push("verified", tag);
+		}
+		details.push("tags", tag);
+		Ok(())
+	}
+
+	/// Bumps the version, returns the highest level, and a report of how the bump level was chosen.
+	fn bump_manifest(
+		&self,
+		m: &mut Manifests,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<(BumpLevel, Vec<String>)> {
+		if !self.auto_bump_level {
+			m.update_versions(|v| self.bump_level.bump(v))?;
+			return Ok((self.bump_level, Vec::new()));
+		}
+
+		let mut report = Vec::new();
+		let mut highest = BumpLevel::Patch;
+		if state.package_versions.is_empty() {
+			let version = m.get_version()?;
+			let level = self.auto_bump_level_for(None, &version, state, repo, &mut report)?;
+			m.set_version(&level.bump(&version))?;
+			highest = level;
+		} else {
+			for (p, version) in m.package_versions()? {
+				let level =
+					self.auto_bump_level_for(Some(&p), &version, state, repo, &mut report)?;
+				m.set_package_version(&p, &level.bump(&version))?;
+				highest = highest.max(level);
+			}
+		}
+		Ok((highest, report))
+	}
+
+	/// Derives the bump level from the conventional commits since the last release tag.
+	fn auto_bump_level_for(
+		&self,
+		package: Option<&str>,
+		version: &Version,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+		report: &mut Vec<String>,
+	) -> anyhow::Result<BumpLevel> {
+		let (since, commits) = self.commits_since_last_release(package, state, repo)?;
+		let commits: Vec<ConventionalCommit> = commits
+			.iter()
+			.filter_map(|(hash, message)| ConventionalCommit::parse(hash, message))
+			.collect();
+		let (level, drivers) = conventional::bump_level(&commits, version);
+
+		let prefix = match package {
+			Some(p) => format!("{}: ", p),
+			None => String::new(),
+		};
+		report.push(format!(
+			"{}{} conventional commit(s) since {}",
+			prefix,
+			commits.len(),
+			since.as_deref().unwrap_or("the first commit")
+		));
+		for c in drivers.iter() {
+			report.push(format!("{}  {} {}", prefix, c.short_hash(), c.header()));
+		}
+		report.push(format!("{}Bump level: {:?}", prefix, level));
+		Ok(level)
+	}
+
+	/// The last release tag, and hash and message of every commit since.
+	fn commits_since_last_release(
+		&self,
+		package: Option<&str>,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<(Option<String>, Commits)> {
+		let since = self.last_release_tag(package, &self.release_tags(state)?, repo)?;
+		let commits = repo.commit_messages(since.as_deref())?;
+		Ok((since, commits))
+	}
+
+	/// The template of the first `GitTag` step, it names the release tags.
+	fn release_tag_template(&self, packages: bool) -> anyhow::Result<TagTemplate> {
+		let name = self.steps.iter().find_map(|s| match s {
+			Step::GitTag(name) => Some(name.clone()),
+			_ => None,
+		});
+		self.tag_template(&name.flatten(), packages)
+	}
+
+	/// The last release tag of `package`, or of the release, that isn't excluded.
+	fn last_release_tag(
+		&self,
+		package: Option<&str>,
+		exclude: &[String],
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<Option<String>> {
+		let template = self.release_tag_template(package.is_some())?;
+		last_tag(repo, &template, package, exclude)
+	}
+
+	/// Glob matching the release tags, based on the template of the first `GitTag` step.
+	fn tag_pattern(&self, package: Option<&str>) -> anyhow::Result<String> {
+		Ok(self
+			.release_tag_template(package.is_some())?
+			.pattern(package))
+	}
+
+	/// The pre-release suffix, or `stable`, of the target version if there is one.
+	fn suffix_name(&self) -> &str {
+		match (&self.target_version, self.stable) {
+			(Some(target), _) if !target.pre.is_empty() => target.pre.as_str(),
+			(Some(_), _) | (None, true) => "stable",
+			(None, false) => &self.pre_release_suffix,
+		}
+	}
+
+	/// Replaces the pre-release part of the version with the suffix, numbered with `pre_release_counter`.
+	///
+	/// Stable releases remove it, unless the version was released already.
+	fn set_pre_release(
+		&self,
+		m: &mut Manifests,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<()> {
+		if self.stable {
+			m.set_version_suffix("")?;
+			return self.check_not_released(m, state, repo);
+		}
+		if !(self.pre_release_counter || self.config.pre_release_counter()) {
+			return m.set_version_suffix(&self.pre_release_suffix);
+		}
+
+		// the selected packages, the release version isn't known yet
+		let package_versions = m.package_versions()?;
+		let template = self.release_tag_template(!package_versions.is_empty())?;
+		if package_versions.is_empty() {
+			let tags = repo.tag_names(&template.pattern(None))?;
+			let version = m.get_version()?;
+			m.set_version(&self.next_pre_release(&version, None, &template, &tags, state)?)?;
+			return Ok(());
+		}
+		for (p, version) in package_versions {
+			let tags = repo.tag_names(&template.pattern(Some(&p)))?;
+			let next = self.next_pre_release(&version, Some(&p), &template, &tags, state)?;
+			m.set_package_version(&p, &next)?;
+		}
+		Ok(())
+	}
+
+	/// Replaces the build metadata of every version with the rendered `build_metadata`, if there is one.
+	fn apply_build_metadata(
+		&self,
+		m: &mut Manifests,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<()> {
+		let Some(template) = self
+			.build_metadata
+			.as_ref()
+			.or(self.config.build_metadata())
+		else {
+			return Ok(());
+		};
+		let hash = repo.head()?;
+		let build = template.render(&hash, &state.date, |name| std::env::var(name).ok())?;
+		m.update_versions(|old_version| {
+			let mut new_version = old_version.clone();
+			new_version.build = build.clone();
+			new_version
+		})
+	}
+
+	/// Sets the target version, for all packages, or every independently versioned one.
+	fn apply_target_version(
+		&self,
+		m: &mut Manifests,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<()> {
+		let Some(target) = &self.target_version else {
+			bail!("No version to set, use --release-version");
+		};
+		let package_versions = m.package_versions()?;
+		if package_versions.is_empty() {
+			self.check_target_version(target, None, repo)?;
+			return m.set_version(target);
+		}
+		for (p, _) in package_versions {
+			self.check_target_version(target, Some(&p), repo)?;
+			m.set_package_version(&p, target)?;
+		}
+		Ok(())
+	}
+
+	/// Fails unless `target` is greater than the version of every release tag, or `force` is set.
+	fn check_target_version(
+		&self,
+		target: &Version,
+		package: Option<&str>,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<()> {
+		if self.force {
+			return Ok(());
+		}
+		let template = self.release_tag_template(package.is_some())?;
+		let tags = repo.tag_names(&template.pattern(package))?;
+		let latest = tags
+			.iter()
+			.filter(|t| template.matches(t, package))
+			.filter_map(|t| Some((tag_version(t)?, t)))
+			.max_by(|(a, _), (b, _)| a.cmp(b));
+		if let Some((latest, tag)) = latest {
+			if *target <= latest {
+				bail!(
+					"Version {} is not greater than {} of tag {}. Use --force to set it anyway",
+					target,
+					latest,
+					tag
+				);
+			}
+		}
+		Ok(())
+	}
+
+	/// Fails if a release tag for the version, or any of the package versions exists.
+	fn check_not_released(
+		&self,
+		m: &Manifests,
+		state: &State,
+		repo: &mut dyn VcsBackend,
+	) -> anyhow::Result<()> {
+		let package_versions = m.package_versions()?;
+		let template = self.release_tag_template(!package_versions.is_empty())?;
+		let mut releases = Vec::new();
+		if package_versions.is_empty() {
+			let version = m.get_version()?;
+			releases.push((template.render(&version, None, &state.date), version));
+		}
+		for (p, version) in package_versions {
+			releases.push((template.render(&version, Some(&p), &state.date), version));
+		}
+		for (tag, version) in releases {
+			if repo.tag_names(&tag)?.contains(&tag) {
+				bail!(
+					"{} was released already, tag {} exists. Bump the version first",
+					version,
+					tag
+				);
+			}
+		}
+		Ok(())
+	}
+
+	/// `version` with the next unused pre-release number, e.g. `alpha.3` after `alpha.2`.
+	///
+	/// Numbers come from the version itself, and from the `tags` of the same version, and suffix.
+	fn next_pre_release(
+		&self,
+		version: &Version,
+		package: Option<&str>,
+		template: &TagTemplate,
+		tags: &[String],
+		state: &State,
+	) -> anyhow::Result<Version> {
+		let suffix = &self.pre_release_suffix;
+		let with_counter = |n: u64| -> anyhow::Result<Version> {
+			// build metadata of the old version doesn't belong to the new one, or its tags
+			let mut v = version.clone();
+			v.build = BuildMetadata::EMPTY;
+			v.pre = match Prerelease::new(&format!("{}.{}", suffix, n)) {
+				Ok(pre) => pre,
+				Err(e) => bail!("Invalid pre-release suffix {:?}: {}", suffix, e),
+			};
+			Ok(v)
+		};
+
+		// `alpha` counts as `alpha.0`, another suffix starts over
+		let from_version = match version.pre.as_str().strip_prefix(suffix.as_str()) {
+			Some("") => Some(0),
+			Some(n) => n.strip_prefix('.').and_then(|n| n.parse::<u64>().ok()),
+			None => None,
+		};
+
+		// the tags differ only where the number is
+		let first = template.render(&with_counter(1)?, package, &state.date);
+		let second = template.render(&with_counter(2)?, package, &state.date);
+		let prefix = first
+			.bytes()
+			.zip(second.bytes())
+			.take_while(|(a, b)| a == b)
+			.count();
+		let postfix = first[prefix..]
+			.bytes()
+			.rev()
+			.zip(second[prefix..].bytes().rev())
+			.take_while(|(a, b)| a == b)
+			.count();
+		let from_tags = match first != second {
+			true => tags
+				.iter()
+				.filter_map(|t| {
+					let n = t
+						.strip_prefix(&first[..prefix])?
+						.strip_suffix(&first[first.len() - postfix..])?;
+					// tags with build metadata, e.g. `alpha.2+g4f012a5`
+					let n = n.split_once('+').map_or(n, |(n, _)| n);
+					n.parse::<u64>().ok()
+				})
+				.max(),
+			false => None,
+		};
+
+		let last = from_version.max(from_tags).unwrap_or(0);
+		with_counter(last + 1)
+	}
+
+	/// Tags created by this release, they are not the *last* release.
+	fn release_tags(&self, state: &State) -> anyhow::Result<Vec<String>> {
+		let mut tags = Vec::new();
+		for s in self.pipeline().iter() {
+			if let Step::GitTag(name) = s {
+				tags.extend(self.tag_names(name, state)?);
+			}
+		}
+		Ok(tags)
+	}
+
+	/// Evaluates all steps against a simulated state without writing to the
+	/// manifest, the lockfile, or git.
+	pub fn plan(&self) -> anyhow::Result<Plan> {
+		let mut plan = Plan::new(&self.path);
+		self.with_vcs(|repo| {
+			let (first, mut state) = if self.resume {
+				let mut journal = Journal::new(&repo.git_dir()?);
+				self.restore_state(repo, &mut journal)?
+			} else {
+				(0, State::new())
+			};
+			for (i, s) in self.pipeline().iter().enumerate().skip(first) {
+				plan.begin_step(i, &s.to_string());
+				self.plan_step(s, &mut state, repo, &mut plan)?;
+			}
+			Ok(())
+		})?;
+
+		Ok(plan)
+	}
+
+	fn plan_step(
+		&self,
+		step: &Step,
+		state: &mut State,
+		repo: &mut dyn VcsBackend,
+		plan: &mut Plan,
+	) -> anyhow::Result<()> {
+		match step {
+			Step::GitPreflight => match self.step_git_preflight(repo, &mut Details::default()) {
+				Ok(report) => {
+					for line in report {
+						plan.action(&line);
+					}
+				},
+				Err(e) => plan.action(&format!("{}, release would FAIL here", e)),
+			},
+			Step::GitEnsureClean => {
+				if self.step_git_ensure_clean(repo, &mut Details::default())? {
+					plan.action("repository is clean");
+				} else if !self.allow_dirty {
+					plan.action("repository is not clean, release would STOP here");
+				} else {
+					plan.action("repository is not clean, continuing because of --allow-dirty");
+				}
+			},
+			Step::GitShowDirty => {
+				plan.action("show dirty files");
+			},
+			Step::GitShowStatus => {
+				for line in self.status_report(state, repo, &mut Details::default())? {
+					plan.action(&line);
+				}
+			},
+			Step::GitUndoRelease => match self.last_release(repo) {
+				Ok((tags, _, commits)) => {
+					plan.action(&format!(
+						"reset to before {}, removing {} commit(s)",
+						tags[0], commits
+					));
+					for tag in tags {
+						plan.action(&format!("delete tag {}", tag));
+					}
+				},
+				Err(e) => plan.action(&format!("{}, undo would FAIL here", e)),
+			},
+			Step::CargoLoadManifest => {
+				let manifests = self.load_manifests()?;
+				for m in manifests.manifests() {
+					let version = if m.has_version() {
+						format!("version {}", m.get_pretty_version()?)
+					} else if m.inherits_version() {
+						"version inherited from workspace".to_string()
+					} else {
+						"no version".to_string()
+					};
+					plan.action(&format!("load {} ({})", m.path().display(), version));
+				}
+				plan.action(&format!("version: {}", manifests.get_pretty_versions()?));
+				state.previous_version = manifests.get_pretty_versions()?;
+				state.previous_packages = package_versions(&manifests)?;
+				state.manifests = Some(manifests);
+			},
+			Step::CargoSaveManifest => {
+				if let Some(m) = &state.manifests {
+					for path in m.modified_paths() {
+						plan.action(&format!("write {}", path.display()));
+					}
+					plan.action(&format!("version: {}", m.get_pretty_versions()?));
+				} else {
+					bail!("Tried to save manifest without manifest");
+				}
+			},
+			Step::ManifestSetVersionSuffix | Step::ManifestSetVersionSuffixDev => {
+				let suffix = match step {
+					Step::ManifestSetVersionSuffixDev => "dev",
+					_ => self.suffix_name(),
+				};
+				if let Some(mut m) = state.manifests.take() {
+					let old_version = m.get_pretty_versions()?;
+					let r = match step {
+						Step::ManifestSetVersionSuffixDev => m.set_version_suffix(suffix),
+						_ => self.set_pre_release(&mut m, state, repo),
+					}
+					.and_then(|()| self.apply_build_metadata(&mut m, state, repo));
+					let new_version = m.get_pretty_versions()?;
+					state.manifests = Some(m);
+					r?;
+					plan.action(&format!(
+						"set version suffix {}: {} -> {}",
+						suffix, old_version, new_version
+					));
+				} else {
+					bail!("Tried to set suffix without manifest");
+				}
+			},
+			Step::ManifestSetVersion => {
+				if let Some(mut m) = state.manifests.take() {
+					let old_version = m.get_pretty_versions()?;
+					let r = self
+						.apply_target_version(&mut m, repo)
+						.and_then(|()| self.apply_build_metadata(&mut m, state, repo));
+					let new_version = m.get_pretty_versions()?;
+					state.manifests = Some(m);
+					r?;
+					plan.action(&format!("set version: {} -> {}", old_version, new_version));
+				} else {
+					bail!("Tried to set version without manifest");
+				}
+			},
+			Step::ManifestBumpLevel => {
+				if let Some(mut m) = state.manifests.take() {
+					let old_version = m.get_pretty_versions()?;
+					let r = self.bump_manifest(&mut m, state, repo);
+					let (level, report) = r?;
+					state.bump_level = Some(level);
+					for line in report {
+						plan.action(&line);
+					}
+					plan.action(&format!(
+						"bump {} version: {} -> {}",
+						level,
+						old_version,
+						m.get_pretty_versions()?
+					));
+					state.manifests = Some(m);
+				} else {
+					bail!("Tried to bump level without manifest");
+				}
+			},
+			Step::CargoUpdateWorkspace => {
+				plan.action("update Cargo.lock for the workspace");
+			},
+			Step::ManifestPrintVersion => {
+				if let Some(m) = &state.manifests {
+					state.release_version = m.get_pretty_versions()?;
+					state.package_versions = package_versions(m)?;
+					plan.action(&format!("release version: {}", &state.release_version));
+				} else {
+					bail!("Tried to print version without manifest");
+				}
+			},
+			Step::ChangelogUpdate => {
+				let changelog = self.changelog();
+				if self.config.changelog() || changelog.path().exists() {
+					let (since, commits) = self.commits_since_last_release(None, state, repo)?;
+					let entries: usize = changelog
+						.sections(&commits)
+						.iter()
+						.map(|(_, e)| e.len())
+						.sum();
+					plan.action(&format!(
+						"write {}: section [{}] with {} entries from {} commit(s) since {}",
+						changelog.path().display(),
+						&state.release_version,
+						entries,
+						commits.len(),
+						since.as_deref().unwrap_or("the first commit")
+					));
+					state.changelog = Some(changelog.path().to_owned());
+				} else {
+					plan.action(&format!("no {}, skipping", changelog.path().display()));
+				}
+			},
+			Step::GitCommitManifest(m) => {
+				for (files, msg) in self.commits(repo, state, m)? {
+					plan.action(&format!(
+						"commit {} with message {:?}",
+						files.join(", "),
+						msg
+					));
+				}
+				state.changelog = None;
+				state.previous_version = state.release_version.clone();
+				state.previous_packages = state.package_versions.clone();
+			},
+			Step::GitFetch | Step::GitRebase | Step::GitPush => {
+				let upstream = match self.upstream(repo)? {
+					Some(upstream) => upstream.to_string(),
+					None => "<no upstream configured>".to_string(),
+				};
+				let action = match step {
+					Step::GitFetch => "fetch from",
+					Step::GitRebase => "rebase onto",
+					_ => "push to",
+				};
+				plan.action(&format!("{} {}", action, upstream));
+			},
+			Step::GitTag(name) => {
+				for tag in self.tag_names(name, state)? {
+					let tag_msg = self.tag_message(&tag);
+					plan.action(&format!("create tag {} with message {:?}", &tag, tag_msg));
+				}
+			},
+			Step::GitPushTag(name) => {
+				let remote = match self.upstream(repo)? {
+					Some(upstream) => upstream.remote,
+					None => "<no remote configured>".to_string(),
+				};
+				for tag in self.tag_names(name, state)? {
+					plan.action(&format!("push tag {} to {}", &tag, &remote));
+				}
+			},
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use super::*;
+	use crate::git_status::{EntryKind, FileState, StatusEntry, SubmoduleState};
+	use crate::vcs_fake::{Call, FakeCommit, FakeVcs};
+
+	const MANIFEST: &str = r#"[package]
+name = "test-project"
+version = "0.1.0-dev" # keep
+edition = "2021"
+"#;
+
+	const LOCKFILE: &str = r#"# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "test-project"
+version = "0.1.0-dev"
+"#;
+
+	const RELEASE_MESSAGE: &str = ": Bump version for alpha release - 0.1.0-alpha";
+	const DEV_MESSAGE: &str =
+		": Bump version back to dev release, and bump patch level - 0.1.1-dev";
+
+	/// A project at version `0.1.0-dev`, and a fake repository for it.
+	fn setup() -> (tempfile::TempDir, FakeVcs) {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::create_dir(dir.path().join("src")).unwrap();
+		std::fs::create_dir(dir.path().join(".git")).unwrap();
+		std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+		std::fs::write(dir.path().join("Cargo.toml"), MANIFEST).unwrap();
+		std::fs::write(dir.path().join("Cargo.lock"), LOCKFILE).unwrap();
+		let fake = FakeVcs::new(&dir.path().join(".git"));
+		(dir, fake)
+	}
+
+	/// A workspace with the packages `a`, and `b`, versioned independently.
+	fn setup_workspace() -> (tempfile::TempDir, FakeVcs) {
+		let (dir, fake) = setup();
+		std::fs::write(
+			dir.path().join("Cargo.toml"),
+			"[workspace]\nmembers = [\"a\", \"b\"]\nresolver = \"2\"\n",
+		)
+		.unwrap();
+		for p in ["a", "b"] {
+			std::fs::create_dir_all(dir.path().join(p).join("src")).unwrap();
+			std::fs::write(dir.path().join(p).join("src/lib.rs"), "").unwrap();
+			std::fs::write(
+				dir.path().join(p).join("Cargo.toml"),
+				format!(
+					"[package]\nname = \"{}\"\nversion = \"0.1.0-dev\"\nedition = \"2021\"\n",
+					p
+				),
+			)
+			.unwrap();
+		}
+		std::fs::write(dir.path().join("Cargo.lock"), "").unwrap();
+		(dir, fake)
+	}
+
+	fn release(dir: &Path, fake: &FakeVcs) -> Release {
+		let mut release = Release::new();
+		release.set_path(dir.to_str().unwrap());
+		release.set_vcs_backend(Box::new(fake.clone()));
+		release.load_config().unwrap();
+		release
+	}
+
+	fn manifest(dir: &Path) -> String {
+		std::fs::read_to_string(dir.join("Cargo.toml")).unwrap()
+	}
+
+	fn lockfile(dir: &Path) -> String {
+		std::fs::read_to_string(dir.join("Cargo.lock")).unwrap()
+	}
+
+	fn files(files: &[&str]) -> Vec<String> {
+		files.iter().map(|f| f.to_string()).collect()
+	}
+
+	/// The writes of a release without surprises.
+	fn release_writes() -> Vec<Call> {
+		vec![
+			Call::Commit {
+				files:   files(&["Cargo.toml", "Cargo.lock"]),
+				message: RELEASE_MESSAGE.to_string(),
+			},
+			Call::Rebase("origin/main".to_string()),
+			Call::Push("origin/main".to_string()),
+			Call::Tag("0.1.0-alpha".to_string()),
+			Call::PushTag("origin".to_string(), "0.1.0-alpha".to_string()),
+			Call::Commit {
+				files:   files(&["Cargo.toml", "Cargo.lock"]),
+				message: DEV_MESSAGE.to_string(),
+			},
+			Call::Rebase("origin/main".to_string()),
+			Call::Push("origin/main".to_string()),
+		]
+	}
+
+	/// Checks the project, and the repository after a successful release.
+	fn assert_released(dir: &Path, fake: &FakeVcs) {
+		assert!(manifest(dir).contains(r#"version = "0.1.1-dev" # keep"#));
+		assert!(lockfile(dir).contains(r#"version = "0.1.1-dev""#));
+		let messages: Vec<String> = fake.commits().into_iter().map(|c| c.message).collect();
+		assert_eq!(
+			messages[messages.len() - 2..],
+			[RELEASE_MESSAGE, DEV_MESSAGE]
+		);
+		assert_eq!(fake.remote_commits(), fake.commits());
+		assert_eq!(fake.tags(), ["0.1.0-alpha"]);
+		assert_eq!(fake.remote_tags(), ["0.1.0-alpha"]);
+		assert!(!dir.join(".git/omr-bumper").join("journal.toml").exists());
+	}
+
+	#[test]
+	fn release_commits_tags_and_pushes() {
+		let (dir, fake) = setup();
+		release(dir.path(), &fake).run().unwrap();
+
+		assert_eq!(fake.writes(), release_writes());
+		assert_released(dir.path(), &fake);
+	}
+
+	#[test]
+	fn dirty_repository_stops() {
+		let (dir, fake) = setup();
+		fake.set_dirty(&["src/main.rs"]);
+		release(dir.path(), &fake).run().unwrap();
+
+		assert_eq!(fake.writes(), []);
+		assert_eq!(manifest(dir.path()), MANIFEST);
+	}
+
+	#[test]
+	fn staged_changes_stop() {
+		let (dir, fake) = setup();
+		fake.add_status_entry(StatusEntry::tracked(
+			"src/main.rs",
+			FileState::Added,
+			FileState::Unmodified,
+		));
+		release(dir.path(), &fake).run().unwrap();
+
+		assert_eq!(fake.writes(), []);
+	}
+
+	#[test]
+	fn dirty_policies() {
+		for (policy, entry, stops) in [
+			(
+				"tracked",
+				StatusEntry::new(EntryKind::Untracked, "notes.txt"),
+				false,
+			),
+			(
+				"strict",
+				StatusEntry::new(EntryKind::Untracked, "notes.txt"),
+				true,
+			),
+			(
+				"tracked",
+				StatusEntry::new(EntryKind::Unmerged, "src/main.rs"),
+				true,
+			),
+			(
+				"ignore-submodules",
+				StatusEntry {
+					submodule: Some(SubmoduleState {
+						modified: true,
+						..Default::default()
+					}),
+					..StatusEntry::tracked("vendor/lib", FileState::Unmodified, FileState::Modified)
+				},
+				false,
+			),
+		] {
+			let (dir, fake) = setup();
+			fake.add_status_entry(entry.clone());
+			let mut release = release(dir.path(), &fake);
+			release.set_dirty_policy(policy).unwrap();
+			release.run().unwrap();
+
+			assert_eq!(fake.writes().is_empty(), stops, "{} {}", policy, entry);
+		}
+	}
+
+	#[test]
+	fn allow_dirty_releases() {
+		let (dir, fake) = setup();
+		fake.set_dirty(&["src/main.rs"]);
+		let mut release = release(dir.path(), &fake);
+		release.set_allow_dirty(true);
+		release.run().unwrap();
+
+		assert_eq!(fake.writes(), release_writes());
+	}
+
+	#[test]
+	fn ignored_lockfile_is_not_committed() {
+		let (dir, fake) = setup();
+		fake.set_ignored(&["Cargo.lock"]);
+		release(dir.path(), &fake).run().unwrap();
+
+		let commits: Vec<Vec<String>> = fake
+			.writes()
+			.into_iter()
+			.filter_map(|c| match c {
+				Call::Commit { files, .. } => Some(files),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(commits, [files(&["Cargo.toml"]), files(&["Cargo.toml"])]);
+	}
+
+	#[test]
+	fn diverged_remote_is_rebased() {
+		let (dir, fake) = setup();
+		fake.diverge("feat: remote change");
+		release(dir.path(), &fake).run().unwrap();
+
+		assert_eq!(fake.writes(), release_writes());
+		assert_eq!(fake.commits()[1].message, "feat: remote change");
+		assert_released(dir.path(), &fake);
+	}
+
+	#[test]
+	fn rejected_push_rolls_back() {
+		let (dir, fake) = setup();
+		let initial = fake.commits();
+		fake.fail_on("push");
+		let err = release(dir.path(), &fake).run().unwrap_err();
+
+		assert!(err.to_string().contains("injected failure in push"));
+		assert_eq!(fake.commits(), initial);
+		assert_eq!(fake.remote_commits(), initial);
+		assert_eq!(fake.tags(), Vec::<String>::new());
+		assert_eq!(manifest(dir.path()), MANIFEST);
+		assert_eq!(lockfile(dir.path()), LOCKFILE);
+		assert_eq!(
+			fake.writes().last(),
+			Some(&Call::Reset(initial[0].id.clone()))
+		);
+	}
+
+	#[test]
+	fn dry_run_writes_nothing() {
+		let (dir, fake) = setup();
+		let mut release = release(dir.path(), &fake);
+		release.set_dry_run(true);
+		release.run().unwrap();
+
+		assert_eq!(fake.writes(), []);
+		assert_eq!(manifest(dir.path()), MANIFEST);
+	}
+
+	#[test]
+	fn auto_bump_level_uses_commits_since_last_tag() {
+		let (dir, fake) = setup();
+		fake.add_commit("fix: something old");
+		fake.add_tag("0.0.9");
+		fake.add_commit("feat: something new");
+		fake.add_tag("latest"); // no version, not a release
+		let mut release = release(dir.path(), &fake);
+		release.set_bump_level("auto").unwrap();
+		release.run().unwrap();
+
+		assert!(fake.calls().contains(&Call::LastTag("*".to_string())));
+		// the release keeps its version, the level bumps the dev version after it
+		assert_eq!(fake.tags(), ["0.0.9", "latest", "0.1.0-alpha"]);
+		assert!(manifest(dir.path()).contains(r#"version = "0.2.0-dev" # keep"#));
+	}
+
+	#[test]
+	fn plan_shows_the_bump_level_that_is_applied() {
+		let (dir, fake) = setup();
+		fake.add_tag("0.0.9");
+		fake.add_commit("feat: something new");
+		let bump = |level: &str| {
+			let mut release = release(dir.path(), &fake);
+			release.set_bump_level(level).unwrap();
+			let plan = release.plan().unwrap();
+			let actions = plan.entries().iter().flat_map(|e| e.actions().to_vec());
+			actions
+				.filter(|a| a.starts_with("bump "))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			bump("auto"),
+			["bump minor version: 0.1.0-alpha -> 0.2.0-alpha"]
+		);
+		assert_eq!(
+			bump("patch"),
+			["bump patch version: 0.1.0-alpha -> 0.1.1-alpha"]
+		);
+	}
+
+	#[test]
+	fn auto_bump_command_bumps_the_manifest_version() {
+		let (dir, fake) = setup();
+		fake.add_tag("0.0.9");
+		fake.add_commit("feat: something new");
+		let mut release = release(dir.path(), &fake);
+		release.set_command(Command::Bump);
+		release.set_bump_level("auto").unwrap();
+		release.run().unwrap();
+
+		assert!(manifest(dir.path()).contains(r#"version = "0.2.0-dev" # keep"#));
+		assert_eq!(fake.writes(), []);
+	}
+
+	#[test]
+	fn tag_template_names_the_tags_and_finds_the_last_release() {
+		let (dir, fake) = setup();
+		fake.add_commit("fix: something old");
+		fake.add_tag("v0.0.9");
+		fake.add_commit("feat: something new");
+		fake.add_tag("0.0.10"); // doesn't match the template
+		std::fs::write(
+			dir.path().join("omr-bumper.toml"),
+			"tag_template = \"v{version}\"\n",
+		)
+		.unwrap();
+		let mut release = release(dir.path(), &fake);
+		release.set_bump_level("auto").unwrap();
+		release.run().unwrap();
+
+		assert!(fake.calls().contains(&Call::LastTag("v*".to_string())));
+		assert!(manifest(dir.path()).contains(r#"version = "0.2.0-dev" # keep"#));
+		assert_eq!(fake.tags(), ["v0.0.9", "0.0.10", "v0.1.0-alpha"]);
+		assert_eq!(fake.remote_tags(), ["v0.1.0-alpha"]);
+	}
+
+	#[test]
+	fn tag_template_precedence() {
+		let (dir, fake) = setup();
+		let config = r#"
+tag_template = "v{version}"
+steps = [
+	"CargoLoadManifest",
+	"ManifestSetVersionSuffix",
+	"ManifestPrintVersion",
+	"GitTag",
+	"GitTag(stable-{major}.{minor})",
+]
+"#;
+		std::fs::write(dir.path().join("omr-bumper.toml"), config).unwrap();
+		let mut release = release(dir.path(), &fake);
+		release
+			.set_tag_template("release-{major}.{minor}.{patch}-{pre}-{date}")
+			.unwrap();
+		release.run().unwrap();
+
+		let date = changelog::today();
+		assert_eq!(
+			fake.tags(),
+			[
+				format!("release-0.1.0-alpha-{}", date),
+				"stable-0.1".to_string()
+			]
+		);
+
+		let err = release.set_tag_template("v{versoin}").unwrap_err();
+		assert!(err
+			.to_string()
+			.starts_with("Unknown placeholder `{versoin}`"));
+	}
+
+	#[test]
+	fn dev_message_names_the_bump_level() {
+		let (dir, fake) = setup();
+		let mut release = release(dir.path(), &fake);
+		release.set_bump_level("minor").unwrap();
+		release.run().unwrap();
+
+		let messages: Vec<String> = fake.commits().into_iter().map(|c| c.message).collect();
+		assert_eq!(
+			messages.last().unwrap(),
+			": Bump version back to dev release, and bump minor level - 0.2.0-dev"
+		);
+	}
+
+	#[test]
+	fn commit_message_templates_and_trailers() {
+		let (dir, fake) = setup();
+		let config = r#"
+release_commit_message = "chore(release): {packages} {old_version} -> {new_version} ({suffix})"
+dev_commit_message = "chore: {bump_level} bump to {new_version}"
+commit_trailers = ["[skip ci]"]
+"#;
+		std::fs::write(dir.path().join("omr-bumper.toml"), config).unwrap();
+		let mut release = release(dir.path(), &fake);
+		release
+			.add_commit_trailer("Signed-off-by: Jane Doe <jane@example.com>")
+			.unwrap();
+		release.run().unwrap();
+
+		let messages: Vec<String> = fake.commits().into_iter().map(|c| c.message).collect();
+		let trailers = "\n\n[skip ci]\nSigned-off-by: Jane Doe <jane@example.com>";
+		assert_eq!(
+			messages[messages.len() - 2..],
+			[
+				format!(
+					"chore(release): test-project 0.1.0-dev -> 0.1.0-alpha (alpha){}",
+					trailers
+				),
+				format!("chore: patch bump to 0.1.1-dev{}", trailers),
+			]
+		);
+
+		let err = release.set_dev_commit_message("{new_versoin}").unwrap_err();
+		assert!(err
+			.to_string()
+			.starts_with("Unknown placeholder `{new_versoin}` in commit message"));
+	}
+
+	#[test]
+	fn pre_release_counter_skips_tagged_numbers() {
+		let (dir, fake) = setup();
+		for tag in [
+			"0.1.0-alpha.1",
+			"0.1.0-alpha.2",
+			"0.1.0-beta.7",
+			"0.0.9-alpha.5",
+		] {
+			fake.add_tag(tag);
+		}
+		std::fs::write(
+			dir.path().join("omr-bumper.toml"),
+			"pre_release_counter = true\n",
+		)
+		.unwrap();
+		release(dir.path(), &fake).run().unwrap();
+
+		assert!(fake.calls().contains(&Call::TagNames("*".to_string())));
+		assert_eq!(fake.remote_tags(), ["0.1.0-alpha.3"]);
+		let messages: Vec<String> = fake.commits().into_iter().map(|c| c.message).collect();
+		assert_eq!(
+			messages[messages.len() - 2],
+			": Bump version for alpha release - 0.1.0-alpha.3"
+		);
+		assert!(manifest(dir.path()).contains(r#"version = "0.1.1-dev" # keep"#));
+	}
+
+	#[test]
+	fn pre_release_counter_continues_and_resets() {
+		let config = r#"
+steps = ["CargoLoadManifest", "ManifestSetVersionSuffix", "CargoSaveManifest"]
+"#;
+		for (version, suffix, tags, expected) in [
+			("0.1.0-dev", "alpha", &[][..], "0.1.0-alpha.1"),
+			("0.1.0-alpha", "alpha", &[][..], "0.1.0-alpha.1"),
+			(
+				"0.1.0-alpha.4",
+				"alpha",
+				&["0.1.0-alpha.2"][..],
+				"0.1.0-alpha.5",
+			),
+			(
+				"0.1.0-alpha.4",
+				"alpha",
+				&["0.1.0-alpha.9"][..],
+				"0.1.0-alpha.10",
+			),
+			(
+				"0.1.0-alpha.4",
+				"beta",
+				&["0.1.0-alpha.4"][..],
+				"0.1.0-beta.1",
+			),
+			("0.1.0-beta.1", "rc", &["v0.1.0-rc.1"][..], "0.1.0-rc.1"),
+		] {
+			let (dir, fake) = setup();
+			for tag in tags {
+				fake.add_tag(tag);
+			}
+			std::fs::write(dir.path().join("omr-bumper.toml"), config).unwrap();
+			let manifest_path = dir.path().join("Cargo.toml");
+			std::fs::write(&manifest_path, MANIFEST.replace("0.1.0-dev", version)).unwrap();
+			let mut release = release(dir.path(), &fake);
+			release.set_pre_release_suffix(suffix).unwrap();
+			release.set_pre_release_counter(true);
+			release.run().unwrap();
+
+			let expected = format!(r#"version = "{}" # keep"#, expected);
+			assert!(
+				manifest(dir.path()).contains(&expected),
+				"{} {}",
+				version,
+				suffix
+			);
+		}
+	}
+
+	#[test]
+	fn build_metadata_in_versions_and_tags() {
+		for tag_build_metadata in [false, true] {
+			let (dir, fake) = setup();
+			std::fs::write(
+				dir.path().join("omr-bumper.toml"),
+				"build_metadata = \"ci.{env:CARGO_PKG_NAME}.{hash}\"\n",
+			)
+			.unwrap();
+			let mut release = release(dir.path(), &fake);
+			release.set_tag_build_metadata(tag_build_metadata);
+			release.run().unwrap();
+
+			// the release version is set on the initial commit, the dev version on the release commit
+			let ids: Vec<String> = fake.commits().into_iter().map(|c| c.id).collect();
+			let release_version = format!("0.1.0-alpha+ci.omr-bumper.{}", ids[0]);
+			let dev_version = format!("0.1.1-dev+ci.omr-bumper.{}", ids[1]);
+			match tag_build_metadata {
+				false => assert_eq!(fake.remote_tags(), ["0.1.0-alpha"]),
+				true => assert_eq!(fake.remote_tags(), [release_version.as_str()]),
+			}
+			assert_eq!(
+				fake.commits()[1].message,
+				format!(": Bump version for alpha release - {}", release_version)
+			);
+			assert!(
+				manifest(dir.path()).contains(&format!(r#"version = "{}" # keep"#, dev_version))
+			);
+		}
+	}
+
+	#[test]
+	fn build_metadata_needs_its_variables() {
+		let (dir, fake) = setup();
+		let mut release = release(dir.path(), &fake);
+		release
+			.set_build_metadata("{env:OMR_BUMPER_TEST_UNSET}")
+			.unwrap();
+		let err = release.run().unwrap_err();
+
+		assert_eq!(
+			err.to_string(),
+			"Environment variable OMR_BUMPER_TEST_UNSET is not set, build metadata template `{env:OMR_BUMPER_TEST_UNSET}` needs it"
+		);
+		assert_eq!(fake.writes(), []);
+		assert_eq!(manifest(dir.path()), MANIFEST);
+		assert!(release.set_build_metadata("{sha}").is_err());
+	}
+
+	#[test]
+	fn stable_release() {
+		let (dir, fake) = setup();
+		let mut release = release(dir.path(), &fake);
+		release.set_stable(true);
+		release.run().unwrap();
+
+		assert_eq!(fake.remote_tags(), ["0.1.0"]);
+		let messages: Vec<String> = fake.commits().into_iter().map(|c| c.message).collect();
+		assert_eq!(
+			messages[messages.len() - 2],
+			": Bump version for stable release - 0.1.0"
+		);
+		assert!(manifest(dir.path()).contains(r#"version = "0.1.1-dev" # keep"#));
+	}
+
+	#[test]
+	fn stable_release_of_a_released_version_fails() {
+		let (dir, fake) = setup();
+		fake.add_tag("v0.1.0");
+		std::fs::write(
+			dir.path().join("omr-bumper.toml"),
+			"tag_template = \"v{version}\"\n",
+		)
+		.unwrap();
+		let mut release = release(dir.path(), &fake);
+		release.set_stable(true);
+		let err = release.run().unwrap_err();
+
+		assert_eq!(
+			err.to_string(),
+			"0.1.0 was released already, tag v0.1.0 exists. Bump the version first"
+		);
+		assert_eq!(fake.writes(), []);
+		assert_eq!(manifest(dir.path()), MANIFEST);
+	}
+
+	#[test]
+	fn invalid_pre_release_suffix() {
+		let mut release = Release::new();
+		let err = release.set_pre_release_suffix("al pha").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Invalid pre-release suffix \"al pha\": unexpected character in pre-release identifier"
+		);
+		assert!(release.set_pre_release_suffix("").is_err());
+		release.set_pre_release_suffix("rc.1").unwrap();
+	}
+
+	#[test]
+	fn target_version_release() {
+		let (dir, fake) = setup();
+		let mut release = release(dir.path(), &fake);
+		release.set_target_version("1.0.0").unwrap();
+		release.run().unwrap();
+
+		assert_eq!(fake.remote_tags(), ["1.0.0"]);
+		let messages: Vec<String> = fake.commits().into_iter().map(|c| c.message).collect();
+		assert_eq!(
+			messages[messages.len() - 2],
+			": Bump version for stable release - 1.0.0"
+		);
+		assert!(manifest(dir.path()).contains(r#"version = "1.0.1-dev" # keep"#));
+	}
+
+	#[test]
+	fn target_version_must_be_greater_than_the_latest_tag() {
+		for (target, error) in [
+			(
+				"0.1.5",
+				Some("Version 0.1.5 is not greater than 0.2.0 of tag 0.2.0. Use --force to set it anyway"),
+			),
+			(
+				"0.2.0",
+				Some("Version 0.2.0 is not greater than 0.2.0 of tag 0.2.0. Use --force to set it anyway"),
+			),
+			("0.2.0-hotfix.1", Some("Version 0.2.0-hotfix.1 is not greater than 0.2.0 of tag 0.2.0. Use --force to set it anyway")),
+			("0.2.1-hotfix.1", None),
+		] {
+			let (dir, fake) = setup();
+			fake.add_tag("0.1.0-alpha");
+			fake.add_tag("0.2.0");
+			fake.add_tag("latest"); // not a version
+			let mut release = release(dir.path(), &fake);
+			release.set_target_version(target).unwrap();
+			release.set_command(Command::SetVersion);
+			let result = release.run();
+			assert_eq!(result.err().map(|e| e.to_string()).as_deref(), error);
+
+			release.set_force(true);
+			release.run().unwrap();
+			let expected = format!(r#"version = "{}" # keep"#, target);
+			assert!(manifest(dir.path()).contains(&expected), "{}", target);
+		}
+	}
+
+	#[test]
+	fn set_version_only_changes_the_manifest_and_the_lockfile() {
+		let (dir, fake) = setup();
+		let mut release = release(dir.path(), &fake);
+		release.set_target_version("0.3.19-hotfix.1").unwrap();
+		release.set_command(Command::SetVersion);
+		release.run().unwrap();
+
+		assert!(manifest(dir.path()).contains(r#"version = "0.3.19-hotfix.1" # keep"#));
+		assert!(lockfile(dir.path()).contains(r#"version = "0.3.19-hotfix.1""#));
+		assert_eq!(fake.writes(), []);
+
+		let err = Release::new().set_target_version("1.0").unwrap_err();
+		assert!(err.to_string().starts_with("Invalid version \"1.0\""));
+	}
+
+	#[test]
+	fn status_leaves_the_journal_alone() {
+		let (dir, fake) = setup();
+		let journal = dir.path().join(".git/omr-bumper/journal.toml");
+		std::fs::create_dir_all(journal.parent().unwrap()).unwrap();
+		std::fs::write(&journal, "completed = 3\n").unwrap();
+		let mut release = release(dir.path(), &fake);
+		release.set_command(Command::Status);
+		release.run().unwrap();
+
+		assert_eq!(
+			std::fs::read_to_string(&journal).unwrap(),
+			"completed = 3\n"
+		);
+		assert_eq!(fake.writes(), []);
+		assert!(fake.calls().contains(&Call::LastTag("*".to_string())));
+	}
+
+	/// Fails the release after the release commit was pushed, leaves the journal.
+	fn fail_after_push(dir: &Path, fake: &FakeVcs) -> PathBuf {
+		fake.fail_on("push_tag");
+		release(dir, fake).run().unwrap_err();
+		let journal = dir.join(".git/omr-bumper/journal.toml");
+		assert!(journal.exists());
+		journal
+	}
+
+	#[test]
+	fn resume_finishes_and_removes_the_journal() {
+		let (dir, fake) = setup();
+		let journal = fail_after_push(dir.path(), &fake);
+		let mut release = release(dir.path(), &fake);
+		release.set_resume(true);
+		release.run().unwrap();
+
+		assert!(!journal.exists());
+		assert_released(dir.path(), &fake);
+	}
+
+	#[test]
+	fn resume_refuses_a_changed_pipeline_or_version() {
+		let (dir, fake) = setup();
+		let journal = fail_after_push(dir.path(), &fake);
+		let resume = |settings: &dyn Fn(&mut Release)| {
+			let mut release = release(dir.path(), &fake);
+			release.set_resume(true);
+			settings(&mut release);
+			release.run().unwrap_err().to_string()
+		};
+
+		assert_eq!(
+			resume(&|r| r.set_skip_tag(true)),
+			"Steps changed since the journal was written. Refusing to resume"
+		);
+		// the target version replaces the suffix step
+		assert_eq!(
+			resume(&|r| r.set_target_version("0.2.0").unwrap()),
+			"Steps changed since the journal was written. Refusing to resume"
+		);
+		let manifest = manifest(dir.path());
+		std::fs::write(
+			dir.path().join("Cargo.toml"),
+			manifest.replace("0.1.0-alpha", "0.1.0-beta"),
+		)
+		.unwrap();
+		assert!(resume(&|_| {})
+			.ends_with("Cargo.toml changed since the journal was written. Refusing to resume"));
+		assert!(journal.exists());
+	}
+
+	#[test]
+	fn undo_resets_to_before_the_release() {
+		let (dir, fake) = setup();
+		let initial = fake.commits();
+		release(dir.path(), &fake).run().unwrap();
+		let mut undo = release(dir.path(), &fake);
+		undo.set_command(Command::Undo);
+		undo.run().unwrap();
+
+		assert_eq!(fake.commits(), initial);
+		assert_eq!(fake.tags(), Vec::<String>::new());
+		assert!(fake
+			.calls()
+			.contains(&Call::ResetHard("0.1.0-alpha^{commit}~1".to_string())));
+		assert_eq!(fake.remote_tags(), ["0.1.0-alpha"]);
+	}
+
+	#[test]
+	fn undo_only_reverts_the_last_thing_on_the_branch() {
+		let (dir, fake) = setup();
+		release(dir.path(), &fake).run().unwrap();
+		fake.add_commit("More work");
+		let commits = fake.commits();
+		let mut undo = release(dir.path(), &fake);
+		undo.set_command(Command::Undo);
+		let err = undo.run().unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"HEAD is 2 commit(s) past 0.1.0-alpha, the release isn't the last thing on the branch, nothing undone"
+		);
+		assert_eq!(fake.commits(), commits);
+
+		// a tag on a commit that isn't a release
+		let (dir, fake) = setup();
+		fake.add_commit("Some work");
+		fake.add_tag("0.1.0-alpha");
+		let mut undo = release(dir.path(), &fake);
+		undo.set_command(Command::Undo);
+		let err = undo.run().unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Tag 0.1.0-alpha isn't on a release commit, nothing undone"
+		);
+		assert_eq!(fake.writes(), []);
+	}
+
+	#[test]
+	fn missing_upstream_fails_before_pushing() {
+		let (dir, fake) = setup();
+		let initial = fake.commits();
+		fake.set_upstream(None);
+		let err = release(dir.path(), &fake).run().unwrap_err();
+		assert!(err.to_string().starts_with("Branch main has no upstream"));
+		assert_eq!(fake.writes(), []);
+
+		// past the preflight check, the fetch fails
+		let mut allowed = release(dir.path(), &fake);
+		allowed.set_allow_no_upstream(true);
+		let err = allowed.run().unwrap_err();
+		assert!(err.to_string().starts_with("No upstream"));
+
+		assert!(fake.calls().iter().all(|c| c.name() != "push"));
+		assert_eq!(fake.commits(), initial);
+		assert_eq!(manifest(dir.path()), MANIFEST);
+	}
+
+	#[test]
+	fn configured_remote_replaces_the_missing_upstream() {
+		let (dir, fake) = setup();
+		fake.set_upstream(None);
+		std::fs::write(dir.path().join("omr-bumper.toml"), "remote = \"origin\"\n").unwrap();
+		release(dir.path(), &fake).run().unwrap();
+
+		assert_released(dir.path(), &fake);
+		assert_eq!(fake.writes(), release_writes());
+	}
+
+	#[test]
+	fn remote_and_target_branch_override_the_upstream() {
+		for (remote, branch, error) in [
+			(
+				"fork",
+				"main",
+				"'fork' does not appear to be a git repository",
+			),
+			(
+				"origin",
+				"release",
+				"couldn't find remote ref refs/heads/release",
+			),
+		] {
+			let (dir, fake) = setup();
+			let mut release = release(dir.path(), &fake);
+			release.set_remote(remote);
+			release.set_target_branch(branch);
+			let err = release.run().unwrap_err();
+
+			assert_eq!(err.to_string(), error);
+			let upstream = format!("{}/{}", remote, branch);
+			assert!(fake.calls().contains(&Call::Fetch(upstream)));
+			assert!(fake.calls().iter().all(|c| c.name() != "push"));
+			assert_eq!(manifest(dir.path()), MANIFEST);
+		}
+	}
+
+	#[test]
+	fn signed_release() {
+		let (dir, fake) = setup();
+		let initial = fake.commits().len();
+		let mut release = release(dir.path(), &fake);
+		release.set_sign(true);
+		release.run().unwrap();
+
+		assert_released(dir.path(), &fake);
+		assert!(fake.commits()[initial..].iter().all(|c| c.signed));
+		let calls = fake.calls();
+		let verified = calls.iter().position(|c| c.name() == "verify_tag");
+		let pushed = calls.iter().position(|c| c.name() == "push_tag");
+		assert!(verified.unwrap() < pushed.unwrap());
+	}
+
+	#[test]
+	fn signed_release_onto_a_diverged_upstream() {
+		let (dir, fake) = setup();
+		let initial = fake.commits().len();
+		fake.diverge("Change from elsewhere");
+		let mut release = release(dir.path(), &fake);
+		release.set_sign(true);
+		release.run().unwrap();
+
+		// the release commits were rewritten by the rebase, and signed again
+		let commits = fake.remote_commits();
+		assert_eq!(commits[initial].message, "Change from elsewhere");
+		assert!(!commits[initial].signed);
+		assert!(commits[initial + 1..].iter().all(|c| c.signed));
+		assert_eq!(commits.len(), initial + 3);
+	}
+
+	#[test]
+	fn invalid_tag_signature_is_not_pushed() {
+		let (dir, fake) = setup();
+		fake.fail_on("verify_tag");
+		let mut release = release(dir.path(), &fake);
+		release.set_sign(true);
+		release.run().unwrap_err();
+
+		assert_eq!(fake.tags(), Vec::<String>::new());
+		assert!(fake.calls().iter().all(|c| c.name() != "push_tag"));
+	}
+
+	#[test]
+	fn failed_package_tag_deletes_the_earlier_tags() {
+		let (dir, fake) = setup_workspace();
+		fake.add_tag("b-v0.1.0-alpha");
+		let mut release = release(dir.path(), &fake);
+		release.set_packages(&files(&["a", "b"]));
+		let err = release.run().unwrap_err();
+
+		assert_eq!(err.to_string(), "tag 'b-v0.1.0-alpha' already exists");
+		assert_eq!(fake.tags(), files(&["b-v0.1.0-alpha"]));
+		assert!(fake
+			.writes()
+			.contains(&Call::DeleteTag("a-v0.1.0-alpha".to_string())));
+	}
+
+	/// Releases the packages `a`, and `b` of a workspace where `a` depends on `b`,
+	/// with a commit per package.
+	fn release_packages() -> (tempfile::TempDir, FakeVcs, Vec<FakeCommit>) {
+		let (dir, fake) = setup_workspace();
+		std::fs::write(
+			dir.path().join("a/Cargo.toml"),
+			r#"[package]
+name = "a"
+version = "0.1.0-dev"
+edition = "2021"
+
+[dependencies]
+b = { path = "../b", version = "0.1.0-dev" }
+"#,
+		)
+		.unwrap();
+		std::fs::write(dir.path().join("CHANGELOG.md"), "# Changelog\n").unwrap();
+		let initial = fake.commits();
+		let mut release = release(dir.path(), &fake);
+		release.set_packages(&files(&["a", "b"]));
+		release.set_commit_per_package(true);
+		release.run().unwrap();
+		(dir, fake, initial)
+	}
+
+	#[test]
+	fn commit_per_package_commits_in_dependency_order() {
+		let (_dir, fake, initial) = release_packages();
+
+		let commits: Vec<_> = fake
+			.commits()
+			.into_iter()
+			.skip(initial.len())
+			.map(|c| (c.message, c.files))
+			.collect();
+		assert_eq!(
+			commits,
+			[
+				(
+					": Bump version for alpha release - b 0.1.0-alpha".to_string(),
+					files(&["b/Cargo.toml"])
+				),
+				(
+					": Bump version for alpha release - a 0.1.0-alpha".to_string(),
+					files(&["a/Cargo.toml", "CHANGELOG.md", "Cargo.lock"])
+				),
+				(
+					": Bump version back to dev release, and bump patch level - b 0.1.1-dev"
+						.to_string(),
+					files(&["b/Cargo.toml"])
+				),
+				(
+					": Bump version back to dev release, and bump patch level - a 0.1.1-dev"
+						.to_string(),
+					files(&["a/Cargo.toml", "Cargo.lock"])
+				),
+			]
+		);
+		assert_eq!(fake.tags(), files(&["b-v0.1.0-alpha", "a-v0.1.0-alpha"]));
+		assert_eq!(fake.remote_tags(), fake.tags());
+	}
+
+	#[test]
+	fn undo_removes_every_package_commit() {
+		let (dir, fake, initial) = release_packages();
+		let mut undo = release(dir.path(), &fake);
+		undo.set_packages(&files(&["a", "b"]));
+		undo.set_commit_per_package(true);
+		undo.set_command(Command::Undo);
+		undo.run().unwrap();
+
+		assert_eq!(fake.commits(), initial);
+		assert_eq!(fake.tags(), Vec::<String>::new());
+		assert!(fake
+			.calls()
+			.contains(&Call::ResetHard("b-v0.1.0-alpha^{commit}~2".to_string())));
+	}
+
+	#[test]
+	fn unknown_package_is_rejected() {
+		let (dir, fake) = setup_workspace();
+		let mut release = release(dir.path(), &fake);
+		release.set_packages(&files(&["a", "c"]));
+		let err = release.run().unwrap_err();
+
+		assert_eq!(
+			err.to_string(),
+			"Package c is not a member of the workspace"
+		);
+		assert_eq!(fake.writes(), []);
+	}
+
+	#[test]
+	fn skip_push_does_not_need_an_upstream() {
+		let (dir, fake) = setup();
+		fake.set_upstream(None);
+		let mut release = release(dir.path(), &fake);
+		release.set_skip_push(true);
+		release.run().unwrap();
+
+		assert!(manifest(dir.path()).contains(r#"version = "0.1.1-dev" # keep"#));
+		assert_eq!(fake.tags(), ["0.1.0-alpha"]);
+		assert_eq!(fake.remote_tags(), Vec::<String>::new());
+	}
+
+	#[test]
+	fn preflight_checks_branch_and_upstream() {
+		type Case = (fn(&FakeVcs), fn(&mut Release), &'static str);
+		let cases: [Case; 4] = [
+			(
+				|f| f.set_branch(Some("feature/x")),
+				|r| r.set_allow_any_branch(true),
+				"Branch feature/x is not allowed for releases (allowed: main, master)",
+			),
+			(
+				|f| f.set_branch(None),
+				|r| r.set_allow_any_branch(true),
+				"HEAD is detached",
+			),
+			(
+				|f| {
+					f.diverge("elsewhere");
+					f.fetch_remote();
+				},
+				|r| r.set_allow_behind(true),
+				"Branch main is 1 commit(s) behind origin/main",
+			),
+			(
+				|f| {
+					f.diverge("elsewhere");
+					f.fetch_remote();
+					f.add_commit("local");
+				},
+				|r| r.set_allow_diverged(true),
+				"Branch main has diverged from origin/main (1 ahead, 1 behind)",
+			),
+		];
+		for (prepare, allow, error) in cases {
+			let (dir, fake) = setup();
+			prepare(&fake);
+			let err = release(dir.path(), &fake).run().unwrap_err();
+			assert!(err.to_string().starts_with(error), "{}", err);
+			assert_eq!(fake.writes(), []);
+			assert_eq!(manifest(dir.path()), MANIFEST);
+
+			let mut release = release(dir.path(), &fake);
+			allow(&mut release);
+			release.run().unwrap();
+			assert_released(dir.path(), &fake);
+		}
+	}
+
+	#[test]
+	fn preflight_allows_configured_branches() {
+		let (dir, fake) = setup();
+		fake.set_branch(Some("release/1.x"));
+		fake.add_commit("local");
+		std::fs::write(
+			dir.path().join("omr-bumper.toml"),
+			"branches = [\"release/*\"]\n",
+		)
+		.unwrap();
+		release(dir.path(), &fake).run().unwrap();
+
+		assert_released(dir.path(), &fake);
+	}
+
+	/// Fails every call to the backend once, the release either rolls back completely,
+	/// or can be finished with `--resume`, when something was pushed already.
+	#[test]
+	fn failure_at_each_call_rolls_back_or_resumes() {
+		let calls = {
+			let (dir, fake) = setup();
+			release(dir.path(), &fake).run().unwrap();
+			fake.calls()
+		};
+		let first_push = calls.iter().position(|c| c.name() == "push").unwrap();
+		for (i, call) in calls.iter().enumerate() {
+			let (dir, fake) = setup();
+			let initial = fake.commits();
+			fake.fail_on_call(i);
+			let err = release(dir.path(), &fake).run().unwrap_err();
+			assert_eq!(
+				err.to_string(),
+				format!("injected failure in {}", call.name()),
+				"call {} {:?}",
+				i,
+				call
+			);
+
+			let mut release = release(dir.path(), &fake);
+			if i <= first_push {
+				assert_eq!(fake.commits(), initial, "call {} {:?}", i, call);
+				assert_eq!(fake.tags(), Vec::<String>::new(), "call {} {:?}", i, call);
+				assert_eq!(manifest(dir.path()), MANIFEST, "call {} {:?}", i, call);
+				assert_eq!(lockfile(dir.path()), LOCKFILE, "call {} {:?}", i, call);
+			} else {
+				release.set_resume(true);
+			}
+			release.run().unwrap();
+			assert_released(dir.path(), &fake);
+		}
 	}
 }
@@ -1,475 +1,566 @@
-use git2::{
-	Cred,
-	FetchOptions,
-	PushOptions,
-	RemoteCallbacks,
-//	Repository,
-//	Signature,
-	Status,
-};
+use std::path::{Path, PathBuf};
 
 //use anyhow::*;
 use anyhow::bail;
+use git2::{
+	BranchType, ErrorCode, FetchOptions, ObjectType, PushOptions, RemoteCallbacks, ResetType, Sort,
+	Status, StatusOptions,
+};
 
-use std::path::Path;
-
+use crate::credentials::CredentialChain;
+use crate::event::StatusSink;
+use crate::git_status::{EntryKind, FileState, GitStatus, StatusEntry, SubmoduleState};
+use crate::signing::Signing;
+use crate::vcs::{glob_match, Upstream, VcsBackend};
 
+/// `VcsBackend` using libgit2.
 pub struct Repository {
-	path: String,
-	repo: Option< git2::Repository >,
+	path:   PathBuf,
+	repo:   Option<git2::Repository>,
+	status: StatusSink,
 }
 
 impl Repository {
-	pub fn new( path: &str ) -> Self {
+	pub fn new(path: &Path, status: StatusSink) -> Self {
 		Self {
 			path: path.to_owned(),
 			repo: None,
+			status,
 		}
 	}
 
-	pub fn open( &mut self ) -> anyhow::Result<()> {
-		let repo = match git2::Repository::discover( &self.path ) {
-		    Ok(repo) => repo,
-		    Err(e) => bail!("failed to open: {}", e),
+	pub fn open(&mut self) -> anyhow::Result<()> {
+		let repo = match git2::Repository::discover(&self.path) {
+			Ok(repo) => repo,
+			Err(e) => bail!("failed to open: {}", e),
 		};
 
-//		dbg!(&repo.state());
+		//		dbg!(&repo.state());
 
-		self.repo = Some( repo );
+		self.repo = Some(repo);
 
 		Ok(())
 	}
 
-	pub fn get_dirty( &mut self ) -> Vec<String> {
+	fn repo(&self) -> anyhow::Result<&git2::Repository> {
 		match &self.repo {
-			Some( repo ) => {
-				let mut dirty = Vec::new();
-				let mut check_s = Status::empty();
-				check_s.insert(Status::INDEX_NEW);
-				check_s.insert(Status::INDEX_MODIFIED);
-				check_s.insert(Status::WT_NEW);
-				check_s.insert(Status::WT_MODIFIED);
-
-				let mut skip_s = Status::empty();
-				skip_s.insert(Status::IGNORED);
-				skip_s.insert(Status::WT_NEW);
-
-				for se in repo.statuses( None ).unwrap().iter() {
-					let s = se.status();
-//					println!("Maybe dirty {:?} {}", s, se.path().unwrap_or( "" ) );
-					if s.intersects( check_s ) {
-						dirty.push( se.path().unwrap_or( "" ).to_owned());
-					} else {
-						if !s.intersects( skip_s ) {
-							println!("Not dirty {:?} {}", s, se.path().unwrap_or( "" ) );
-						}
-					}
-				}
-				dirty
-			},
-			None => {
-				Vec::new()
-			},
+			Some(repo) => Ok(repo),
+			None => bail!("No repo open"),
 		}
 	}
 
-	pub fn commit( &mut self, files: &Vec<String>, message: &str ) -> anyhow::Result<()> {
-		match &self.repo {
-			Some( repo ) => {
-				/*
-				let name = "Somebody";
-				let email = "some@body.org";
-				let author = Signature::now( &name, &email )?;
-				let commiter = Signature::now( &name, &email )?;
-				*/
-
-				let sig = repo.signature()?;
-
-				let mut index = match repo.index() {
-					Err(e) => bail!("No index for repository {}", &e),
-					Ok(index) => index,
-				};
-				let cwd = match std::env::current_dir() {
-					Ok( wd ) => wd,
-					Err( e ) => bail!("No current working directory found: {}", &e),
-				};
-//				dbg!(&repo.path());
-//				dbg!(&repo.workdir());
-				let rwd = match repo.workdir() {
-					Some( wd ) => wd,
-					None => bail!("No workdir for repository"),
-				};
-//				dbg!(&cwd);
-//				dbg!(&rwd);				
-				for f in files.iter() {
-					let p = Path::new( &cwd ).join( &f );
-//					dbg!(&p);
-					// filenames are relative to working directory
-					// make them relative to repository root
-					let p = match p.strip_prefix( &rwd ) {
-						Ok( p ) => p,
-						Err( e ) => bail!("Error stripping {:?} from {:?}: {}", &rwd, &p, &e),
-					};
-//					dbg!(&p);
-					index.add_path( &p )?;
-				};
-
-				index.write()?;
-
-				let mut index = match repo.index() {
-					Err(e) => bail!("No index for repository {}", &e),
-					Ok(index) => index,
-				};
-/*
-pub fn write_tree(&mut self) -> Result<Oid, Error>
-*/
-				let oid = match index.write_tree() {
-					Ok( oid ) => oid,
-					Err( e ) => bail!("Error writing tree for repository: {}", &e ),
-				};
-				index.write()?;
+	/// `file` relative to the repository root, as libgit2 expects it.
+	fn repo_path(&self, file: &str) -> anyhow::Result<PathBuf> {
+		let repo = self.repo()?;
+		let workdir = match repo.workdir() {
+			Some(wd) => wd.canonicalize()?,
+			None => bail!("No workdir for repository"),
+		};
+		let path = self.path.canonicalize()?.join(file);
+		match path.strip_prefix(&workdir) {
+			Ok(p) => Ok(p.to_owned()),
+			Err(e) => bail!("Error stripping {:?} from {:?}: {}", &workdir, &path, &e),
+		}
+	}
 
-//				dbg!(&oid);
+	/// The current branch, and its upstream, e.g. `main`, and `origin/main`.
+	fn branch_upstream(&self) -> anyhow::Result<(String, Option<git2::Branch<'_>>)> {
+		let repo = self.repo()?;
+		let head = repo.head()?;
+		if !head.is_branch() {
+			bail!("HEAD is detached, not on a branch");
+		}
+		let name = match head.shorthand() {
+			Some(name) => name.to_string(),
+			None => bail!("Branch name is not valid utf-8"),
+		};
+		let branch = repo.find_branch(&name, BranchType::Local)?;
+		let upstream = match branch.upstream() {
+			Ok(u) => Some(u),
+			Err(e) if e.code() == ErrorCode::NotFound => None,
+			Err(e) => return Err(e.into()),
+		};
+		Ok((name, upstream))
+	}
 
-				let tree = match repo.find_tree(oid) {
-					Ok( tree ) => tree,
-					Err( e ) => bail!("Error findind tree for OID {}: {}", &oid, &e),
-				};
+	/// `signing` with the unset fields from the git config.
+	fn resolve_signing(&self, signing: &Signing) -> anyhow::Result<Signing> {
+		let config = self.repo()?.config()?;
+		signing.resolve(|key| config.get_string(key).ok())
+	}
 
-//				dbg!(&tree);
+	/// Points the current branch, or a detached HEAD, to `oid`, like `git commit` does.
+	fn update_head(&self, oid: git2::Oid, message: &str) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let head = repo.find_reference("HEAD")?.resolve()?;
+		let summary = message.lines().next().unwrap_or_default();
+		repo.reference(
+			head.name().unwrap_or("HEAD"),
+			oid,
+			true,
+			&format!("commit: {}", summary),
+		)?;
+		Ok(())
+	}
 
-				let parent = match repo.revparse_ext( "HEAD" ) {
-					Ok( ( object, _ ) ) => object,
-					Err( e ) => bail!( "Error finding HEAD {}", &e ),
-				};
+	/// Recreates the commits after `base` up to HEAD with signatures, e.g. after a rebase.
+	fn sign_commits(&self, base: git2::Oid, signing: &Signing) -> anyhow::Result<()> {
+		let signing = self.resolve_signing(signing)?;
+		let repo = self.repo()?;
+		let mut walk = repo.revwalk()?;
+		walk.push_head()?;
+		walk.hide(base)?;
+		walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+
+		let mut parent = repo.find_commit(base)?;
+		for oid in walk {
+			let commit = repo.find_commit(oid?)?;
+			let Some(message) = commit.message() else {
+				bail!("Message of commit {} is not valid utf-8", commit.id());
+			};
+			let buffer = repo.commit_create_buffer(
+				&commit.author(),
+				&commit.committer(),
+				message,
+				&commit.tree()?,
+				&[&parent],
+			)?;
+			let Some(buffer) = buffer.as_str() else {
+				bail!("Commit is not valid utf-8");
+			};
+			let signature = signing.sign(buffer)?;
+			let oid = repo.commit_signed(buffer, &signature, Some("gpgsig"))?;
+			parent = repo.find_commit(oid)?;
+		}
+		if parent.id() != base {
+			self.update_head(parent.id(), parent.message().unwrap_or_default())?;
+		}
+		Ok(())
+	}
 
-				let parent = match parent.as_commit() {
-					Some( commit ) => commit,
-					None => bail!( "Parent is not a commit" ),
-				};
+	fn push_refspecs(&self, remote_name: &str, refspecs: &[String]) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let mut remote = match repo.find_remote(remote_name) {
+			Ok(remote) => remote,
+			Err(e) => bail!("Couldn't find remote({}): {}", &remote_name, &e),
+		};
 
-//				dbg!(&parent);
-				/*
-pub fn commit(
-    &self,
-    update_ref: Option<&str>,
-    author: &Signature<'_>,
-    committer: &Signature<'_>,
-    message: &str,
-    tree: &Tree<'_>,
-    parents: &[&Commit<'_>]
-) -> Result<Oid, Error>				
-				*/
-				/*
-pub fn find_tree(&self, oid: Oid) -> Result<Tree<'_>, Error>				
-
-pub fn revparse_ext(
-    &self,
-    spec: &str
-) -> Result<(Object<'_>, Option<Reference<'_>>), Error>
-				*/
-				repo.commit(
-					Some("HEAD"),
-					&sig,
-					&sig,
-					message,
-					&tree,
-					&[ parent ],
-				)?;
+		let mut rejected = Vec::new();
+		{
+			let mut cbs = RemoteCallbacks::new();
+			let mut chain = CredentialChain::new(repo.config()?);
+			cbs.credentials(move |url, username, allowed| {
+				chain.credentials(url, username, allowed)
+			});
+			cbs.push_update_reference(|name, status| {
+				if let Some(status) = status {
+					rejected.push(format!("{}: {}", name, status));
+				}
 				Ok(())
-			},
-			None => bail!( "No repo open for commit" ),
+			});
+			let mut opts = PushOptions::new();
+			opts.remote_callbacks(cbs);
+			if let Err(e) = remote.push(refspecs, Some(&mut opts)) {
+				bail!("Error pushing to {}: {}", &remote_name, &e);
+			}
+		}
+		if !rejected.is_empty() {
+			bail!("Push rejected by {}: {}", &remote_name, rejected.join(", "));
 		}
+		Ok(())
 	}
+}
 
-	pub fn tag( &mut self, tag: &str, msg: &str ) -> anyhow::Result<()> {
-		match &self.repo {
-			Some( repo ) => {
-				let rv = repo.revparse( "HEAD" )?;
-				let ho = match rv.from() {
-					Some( ho ) => ho,
-					None => bail!( "No HEAD found!" ),
-				};
-				println!("Tagging {} with {}", &ho.id(), &tag);
-//				dbg!(&ho);
-
-				let sig = repo.signature()?;
+impl VcsBackend for Repository {
+	fn status(&mut self) -> anyhow::Result<GitStatus> {
+		let repo = self.repo()?;
+		let mut status = GitStatus::default();
+
+		let head = repo.head().ok();
+		status.branch.oid = head
+			.as_ref()
+			.and_then(|h| h.target())
+			.map(|oid| oid.to_string());
+		if let Ok((name, upstream)) = self.branch_upstream() {
+			status.branch.head = Some(name);
+			if let Some(upstream) = upstream {
+				status.branch.upstream = upstream.name()?.map(|n| n.to_string());
+				if let (Some(local), Some(remote)) = (
+					head.as_ref().and_then(|h| h.target()),
+					upstream.get().target(),
+				) {
+					let (ahead, behind) = repo.graph_ahead_behind(local, remote)?;
+					status.branch.ahead = ahead;
+					status.branch.behind = behind;
+				}
+			}
+		}
 
-				let _tag_oid = repo.tag( tag, &ho, &sig, msg, true )?;
-//				dbg!(&tag_oid);
-				Ok(())
-/*
-pub fn tag(
-    &self,
-    name: &str,
-    target: &Object<'_>,
-    tagger: &Signature<'_>,
-    message: &str,
-    force: bool
-) -> Result<Oid, Error>
-*/
-			},
-			None => bail!( "No repo open for tag" ),
+		let mut opts = StatusOptions::new();
+		opts.include_untracked(true)
+			.include_ignored(false)
+			.renames_head_to_index(true);
+		for se in repo.statuses(Some(&mut opts))?.iter() {
+			let s = se.status();
+			let path = se.path().unwrap_or("").to_owned();
+			if s.contains(Status::CONFLICTED) {
+				status
+					.entries
+					.push(StatusEntry::new(EntryKind::Unmerged, &path));
+				continue;
+			}
+			if s == Status::WT_NEW {
+				status
+					.entries
+					.push(StatusEntry::new(EntryKind::Untracked, &path));
+				continue;
+			}
+			let index = match s {
+				s if s.contains(Status::INDEX_NEW) => FileState::Added,
+				s if s.contains(Status::INDEX_MODIFIED) => FileState::Modified,
+				s if s.contains(Status::INDEX_DELETED) => FileState::Deleted,
+				s if s.contains(Status::INDEX_RENAMED) => FileState::Renamed,
+				s if s.contains(Status::INDEX_TYPECHANGE) => FileState::TypeChanged,
+				_ => FileState::Unmodified,
+			};
+			let worktree = match s {
+				s if s.contains(Status::WT_MODIFIED) => FileState::Modified,
+				s if s.contains(Status::WT_DELETED) => FileState::Deleted,
+				s if s.contains(Status::WT_RENAMED) => FileState::Renamed,
+				s if s.contains(Status::WT_TYPECHANGE) => FileState::TypeChanged,
+				_ => FileState::Unmodified,
+			};
+			let mut entry = StatusEntry::tracked(&path, index, worktree);
+			if let Some(delta) = se.head_to_index() {
+				if index == FileState::Renamed {
+					entry.source = delta.old_file().path().map(|p| p.display().to_string());
+					if let Some(new) = delta.new_file().path() {
+						entry.path = new.display().to_string();
+					}
+				}
+			}
+			if let Ok(sub) = repo.submodule_status(&path, git2::SubmoduleIgnore::None) {
+				entry.submodule = Some(SubmoduleState {
+					commit_changed: sub.contains(git2::SubmoduleStatus::WD_MODIFIED),
+					modified:       sub.intersects(
+						git2::SubmoduleStatus::WD_INDEX_MODIFIED
+							| git2::SubmoduleStatus::WD_WD_MODIFIED,
+					),
+					untracked:      sub.contains(git2::SubmoduleStatus::WD_UNTRACKED),
+				});
+			}
+			status.entries.push(entry);
 		}
+		Ok(status)
 	}
 
-	fn credentials_cb( url: &str, username_from_url: Option<&str>, _allowed_types: git2::CredentialType ) -> Result<Cred, git2::Error> {
-//		dbg!(&username_from_url);
-		dbg!(&url);
-		Cred::ssh_key(
-			username_from_url.unwrap(),
-			None,
-			std::path::Path::new(&format!("{}/.ssh/id_ed25519", std::env::var("HOME").unwrap())),
-//			std::path::Path::new(&format!("{}/.ssh/id_rsa", std::env::var("HOME").unwrap())),
-			None,
-		)
-	}
+	fn commit(
+		&mut self,
+		files: &[String],
+		message: &str,
+		signing: Option<&Signing>,
+	) -> anyhow::Result<()> {
+		let mut paths = Vec::new();
+		for f in files.iter() {
+			paths.push(self.repo_path(f)?);
+		}
+		let repo = self.repo()?;
+		let sig = repo.signature()?;
 
-	pub fn fetch( &mut self ) -> anyhow::Result<usize> {
-		match &self.repo {
-			Some( repo )	=> {
-				let remote_name = "origin";
-				let mut remote = match repo.find_remote( &remote_name ) {
-					Ok( remote )	=> remote,
-					Err( e )		=> bail!( "Couldn't find remote({}): {}", &remote_name, &e ),
-				};
+		let mut index = match repo.index() {
+			Err(e) => bail!("No index for repository {}", &e),
+			Ok(index) => index,
+		};
+		for p in paths.iter() {
+			index.add_path(p)?;
+		}
+		index.write()?;
 
-//				dbg!(&remote.name(), &remote.url());
+		let oid = match index.write_tree() {
+			Ok(oid) => oid,
+			Err(e) => bail!("Error writing tree for repository: {}", &e),
+		};
+		let tree = match repo.find_tree(oid) {
+			Ok(tree) => tree,
+			Err(e) => bail!("Error finding tree for OID {}: {}", &oid, &e),
+		};
+		let parent = match repo.head().and_then(|h| h.peel_to_commit()) {
+			Ok(commit) => commit,
+			Err(e) => bail!("Error finding HEAD {}", &e),
+		};
 
-				let mut cbs = RemoteCallbacks::new();
-				cbs.credentials(|url, username_from_url, allowed_types| { Repository::credentials_cb( url, username_from_url, allowed_types ) });
-				cbs.transfer_progress(|progress| {
-					println!("Transfer progress: {}", progress.received_bytes());
-					println!("{}/{} objects", progress.received_objects(), progress.total_objects());
-//					dbg!(&progress.received_bytes());
-					true
-				});
-				let mut opts = FetchOptions::new();
-				opts.remote_callbacks( cbs );
-				dbg!(&remote.url());
-				remote.fetch(&["main"], Some( &mut opts ), None)?;
-				let stats = remote.stats();
-				println!("Fetched {} bytes.", stats.received_bytes());
-				println!("Fetched {} objects.", stats.received_objects());
-				Ok(stats.total_objects())
+		match signing {
+			Some(signing) => {
+				let signing = self.resolve_signing(signing)?;
+				let buffer = repo.commit_create_buffer(&sig, &sig, message, &tree, &[&parent])?;
+				let Some(buffer) = buffer.as_str() else {
+					bail!("Commit is not valid utf-8");
+				};
+				let signature = signing.sign(buffer)?;
+				let oid = repo.commit_signed(buffer, &signature, Some("gpgsig"))?;
+				self.update_head(oid, message)?;
+			},
+			None => {
+				repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])?;
 			},
-			None			=> bail!( "No repo open for fetch" ),
 		}
+		Ok(())
 	}
 
-	pub fn rebase( &mut self ) -> anyhow::Result<()> {
-		match &self.repo {
-			Some( repo ) => {
-//				let head = repo.head()?; // wrong, this is local HEAD, we want origin/HEAD
-//				let upstream = repo.reference_to_annotated_commit( &head )?;
-				let rv = match repo.revparse( "origin/HEAD" ) {
-					Ok( rv ) => rv,
-					Err( e ) => bail!( "Error: {:?}", &e ),
-				};
+	fn check_ignore(&mut self, file: &str) -> anyhow::Result<bool> {
+		let path = self.repo_path(file)?;
+		let repo = self.repo()?;
+		// tracked files are never ignored, same as `git check-ignore`
+		match repo.status_file(&path) {
+			Ok(s) => Ok(s.contains(Status::IGNORED)),
+			Err(e) if e.code() == ErrorCode::NotFound => Ok(repo.is_path_ignored(&path)?),
+			Err(e) => Err(e.into()),
+		}
+	}
 
-				let oho = match rv.from() {
-					Some( oho ) => oho,
-					None => bail!( "No origin/HEAD found!" ),
-				};
-//				dbg!(&oho);
+	fn tag(&mut self, tag: &str, msg: &str, signing: Option<&Signing>) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let head = repo.head()?.peel(ObjectType::Commit)?;
+		status!(self.status, "Tagging {} with {}", &head.id(), &tag);
+		let sig = repo.signature()?;
+		let Some(signing) = signing else {
+			repo.tag(tag, &head, &sig, msg, false)?;
+			return Ok(());
+		};
 
-				let upstream = match repo.find_annotated_commit( oho.id() ) {
-					Ok( commit ) => commit,
-					Err( e ) => bail!( "No commit for origin/HEAD! {}", &e ),
-				};
-/*		
-pub fn revparse(&self, spec: &str) -> Result<Revspec<'_>, Error>
-*/				
-				println!("Rebasing on upstream {} {}", upstream.id(), "" ); //upstream.refname().unwrap_or("") );
-//				let upstream = None; // AnnotatedCommit
-				let mut rebase = repo.rebase( None, Some( &upstream ), None, None )?;
-				println!("{}", rebase.len());
-				while let Some( ro ) = rebase.next() {
-
-//				};
-//				for ro in &mut rebase {
-					match ro {
-						Ok( _ro ) => {
-//							dbg!( &ro );
-							// commit
-							/*
-pub fn commit(
-    &mut self,
-    author: Option<&Signature<'_>>,
-    committer: &Signature<'_>,
-    message: Option<&str>
-) -> Result<Oid, Error>
-*/							
-							let sig = repo.signature()?;
-
-							match rebase.commit(
-								None,
-								&sig,
-								None,
-							) {
-								Ok( _r ) => {
-//									dbg!( &r );
-								},
-								Err( e ) => {
-									dbg!( &e );
-									// :TODO: decide when to bail
-//									bail!( "Rebase Error {:?}", &e );
-								},
-							}
-						},
-						Err( e ) => {
-							dbg!( &e );
-						},
-					}
-				}
-//				rebase.abort()?;
-				rebase.finish(None)?;
-//				dbg!(&rebase);
+		// libgit2 can't sign tags, the signature is appended to the tag object
+		let name = format!("refs/tags/{}", tag);
+		if repo.find_reference(&name).is_ok() {
+			bail!("tag '{}' already exists", tag);
+		}
+		let signing = self.resolve_signing(signing)?;
+		let when = sig.when();
+		let offset = when.offset_minutes();
+		let payload = format!(
+			"object {}\ntype commit\ntag {}\ntagger {} <{}> {} {}{:02}{:02}\n\n{}\n",
+			head.id(),
+			tag,
+			sig.name().unwrap_or_default(),
+			sig.email().unwrap_or_default(),
+			when.seconds(),
+			if offset < 0 { '-' } else { '+' },
+			offset.abs() / 60,
+			offset.abs() % 60,
+			msg.trim_end()
+		);
+		let signature = signing.sign(&payload)?;
+		let object = format!("{}{}", payload, signature);
+		let oid = repo.odb()?.write(ObjectType::Tag, object.as_bytes())?;
+		repo.reference(&name, oid, false, "tag")?;
+		Ok(())
+	}
 
-			},
-			None => bail!( "No repo open for rebase" ),
+	fn verify_tag(&mut self, tag: &str, signing: &Signing) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let oid = match repo.refname_to_id(&format!("refs/tags/{}", tag)) {
+			Ok(oid) => oid,
+			Err(e) => bail!("Couldn't find tag {}: {}", tag, &e),
+		};
+		let odb = repo.odb()?;
+		let object = odb.read(oid)?;
+		if object.kind() != ObjectType::Tag {
+			bail!("Tag {} is not signed", tag);
 		}
+		let signing = self.resolve_signing(signing)?;
+		signing.verify_tag(tag, &String::from_utf8_lossy(object.data()))
+	}
+
+	fn delete_tag(&mut self, tag: &str) -> anyhow::Result<()> {
+		self.repo()?.tag_delete(tag)?;
 		Ok(())
-		/*
+	}
 
-pub fn head(&self) -> Result<Reference<'_>, Error>
+	fn head(&mut self) -> anyhow::Result<String> {
+		let head = self.repo()?.head()?.peel_to_commit()?;
+		Ok(head.id().to_string())
+	}
 
-pub fn reference_to_annotated_commit(
-    &self,
-    reference: &Reference<'_>
-) -> Result<AnnotatedCommit<'_>, Error>
+	fn git_dir(&mut self) -> anyhow::Result<PathBuf> {
+		Ok(self.repo()?.path().canonicalize()?)
+	}
 
+	fn upstream(&mut self) -> anyhow::Result<Option<Upstream>> {
+		let repo = self.repo()?;
+		let Ok((branch, _)) = self.branch_upstream() else {
+			return Ok(None);
+		};
+		let config = repo.config()?;
+		match (
+			config.get_string(&format!("branch.{}.remote", branch)),
+			config.get_string(&format!("branch.{}.merge", branch)),
+		) {
+			(Ok(remote), Ok(merge)) => {
+				let merge = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+				Ok(Some(Upstream::new(&remote, merge)))
+			},
+			_ => Ok(None),
+		}
+	}
 
-pub fn rebase(
-    &self,
-    branch: Option<&AnnotatedCommit<'_>>,
-    upstream: Option<&AnnotatedCommit<'_>>,
-    onto: Option<&AnnotatedCommit<'_>>,
-    opts: Option<&mut RebaseOptions<'_>>
-) -> Result<Rebase<'_>, Error>
-		*/
+	fn tag_names(&mut self, pattern: &str) -> anyhow::Result<Vec<String>> {
+		let repo = self.repo()?;
+		let tags = repo.tag_names(Some(pattern))?;
+		Ok(tags
+			.iter()
+			.flatten()
+			.filter(|t| glob_match(pattern, t))
+			.map(|t| t.to_string())
+			.collect())
 	}
 
-	pub fn push( &mut self ) -> anyhow::Result<usize> {
-		match &self.repo {
-			Some( repo ) => {
-				let remote_name = "origin";
-				let mut remote = match repo.find_remote( &remote_name ) {
-					Ok( remote )	=> remote,
-					Err( e )		=> bail!( "Couldn't find remote({}): {}", &remote_name, &e ),
-				};
+	fn last_tag(&mut self, pattern: &str, exclude: &[String]) -> anyhow::Result<Option<String>> {
+		let repo = self.repo()?;
+		let mut tags: Vec<(git2::Oid, String)> = Vec::new();
+		for name in repo.tag_names(Some(pattern))?.iter().flatten() {
+			if exclude.iter().any(|e| e == name) || !glob_match(pattern, name) {
+				continue;
+			}
+			let commit = repo
+				.revparse_single(&format!("refs/tags/{}", name))?
+				.peel_to_commit()?;
+			tags.push((commit.id(), name.to_string()));
+		}
+		if tags.is_empty() {
+			return Ok(None);
+		}
 
-				dbg!(remote.name());
+		let mut walk = repo.revwalk()?;
+		walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+		walk.push_head()?;
+		for oid in walk {
+			let oid = oid?;
+			if let Some((_, name)) = tags.iter().find(|(t, _)| *t == oid) {
+				return Ok(Some(name.clone()));
+			}
+		}
+		Ok(None)
+	}
 
-				let mut cbs = RemoteCallbacks::new();
-				cbs.credentials(|url, username_from_url, allowed_types| { Repository::credentials_cb( url, username_from_url, allowed_types ) });
-				cbs.transfer_progress(|progress| {
-					println!("Transfer progress: {}", progress.received_bytes());
-					println!("{}/{} objects", progress.received_objects(), progress.total_objects());
-//					dbg!(&progress.received_bytes());
-					true
-				});
-				let mut opts = PushOptions::new();
-				opts.remote_callbacks( cbs );
-				remote.push(
-					&["refs/heads/main"],
-					Some( &mut opts )
-				)?;
-
-				/*
-				// only works for fetch!!!
-				let stats = remote.stats();
-				println!("Pushed {} bytes.", stats.received_bytes());
-				println!("Pushed {} objects.", stats.received_objects());
-				Ok(stats.total_objects())
-				*/
-
-				Ok( 0 )
-
-				/*
-pub fn push<Str: AsRef<str> + IntoCString + Clone>(
-    &mut self,
-    refspecs: &[Str],
-    opts: Option<&mut PushOptions<'_>>
-) -> Result<(), Error>
-				*/
-			},
-			None => bail!( "No repo open for push" ),
+	fn commit_messages(&mut self, since: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+		let repo = self.repo()?;
+		let mut walk = repo.revwalk()?;
+		walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+		walk.push_head()?;
+		if let Some(since) = since {
+			walk.hide(repo.revparse_single(since)?.peel_to_commit()?.id())?;
 		}
+		let mut commits = Vec::new();
+		for oid in walk {
+			let commit = repo.find_commit(oid?)?;
+			let message = String::from_utf8_lossy(commit.message_bytes()).to_string();
+			commits.push((commit.id().to_string(), message));
+		}
+		Ok(commits)
 	}
 
-	pub fn push_tag( &mut self, tag: &str ) -> anyhow::Result<usize> {
-		match &self.repo {
-			Some( repo ) => {
-				let remote_name = "origin";
-				let mut remote = match repo.find_remote( &remote_name ) {
-					Ok( remote )	=> remote,
-					Err( e )		=> bail!( "Couldn't find remote({}): {}", &remote_name, &e ),
-				};
+	fn reset(&mut self, commit: &str) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let commit = repo.revparse_single(commit)?;
+		repo.reset(&commit, ResetType::Mixed, None)?;
+		Ok(())
+	}
 
-				dbg!(remote.name());
-
-				let mut cbs = RemoteCallbacks::new();
-				cbs.credentials(|_url, username_from_url, _allowed_types| {
-//					dbg!(&username_from_url);
-					Cred::ssh_key(
-						username_from_url.unwrap(),
-						None,
-						std::path::Path::new(&format!("{}/.ssh/id_ed25519", std::env::var("HOME").unwrap())),
-//						std::path::Path::new(&format!("{}/.ssh/id_rsa", std::env::var("HOME").unwrap())),
-						None,
-					)
-				});
-				cbs.transfer_progress(|progress| {
-					println!("Transfer progress: {}", progress.received_bytes());
-					println!("{}/{} objects", progress.received_objects(), progress.total_objects());
-//					dbg!(&progress.received_bytes());
-					true
-				});
-				cbs.push_update_reference(|name, status|{
-					println!("Push Update Reference: {} -> {:?}", name, status);
-					Ok(())
-				});
-				let mut opts = PushOptions::new();
-				opts.remote_callbacks( cbs );
-				let tag_ref = format!("refs/tags/{}", &tag);
-				println!("Pushing ref {}", &tag_ref);
-				match remote.push(
-					&[ &tag_ref ],
-					Some( &mut opts )
-				) {
-					Ok(()) => {},
-					Err( e ) => {
-						bail!("Error pushing ref to remote: {:?}", &e );
-					},
-				};
-//git push <corresponding remote> refs/tags/*:refs/tags/*	
-
-				/*
-				// only works for fetch!!!
-				let stats = remote.stats();
-				println!("Pushed {} bytes.", stats.received_bytes());
-				println!("Pushed {} objects.", stats.received_objects());
-				Ok(stats.total_objects())
-				*/
-
-				Ok( 0 )
-
-				/*
-pub fn push<Str: AsRef<str> + IntoCString + Clone>(
-    &mut self,
-    refspecs: &[Str],
-    opts: Option<&mut PushOptions<'_>>
-) -> Result<(), Error>
-				*/
-			},
-			None => bail!( "No repo open for push" ),
+	fn reset_hard(&mut self, commit: &str) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let commit = repo.revparse_single(commit)?.peel(ObjectType::Commit)?;
+		repo.reset(&commit, ResetType::Hard, None)?;
+		Ok(())
+	}
+
+	fn abort_rebase(&mut self) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		match repo.open_rebase(None) {
+			Ok(mut rebase) => rebase.abort()?,
+			Err(e) if e.code() == ErrorCode::NotFound => {},
+			Err(e) => return Err(e.into()),
 		}
+		Ok(())
 	}
 
-}
+	fn fetch(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		let repo = self.repo()?;
+		let mut remote = match repo.find_remote(&upstream.remote) {
+			Ok(remote) => remote,
+			Err(e) => bail!("Couldn't find remote({}): {}", &upstream.remote, &e),
+		};
 
+		let mut cbs = RemoteCallbacks::new();
+		let mut chain = CredentialChain::new(repo.config()?);
+		cbs.credentials(move |url, username, allowed| chain.credentials(url, username, allowed));
+		let mut opts = FetchOptions::new();
+		opts.remote_callbacks(cbs);
+		remote.fetch(&[upstream.fetch_refspec()], Some(&mut opts), None)?;
+		let stats = remote.stats();
+		tracing::debug!(
+			"Fetched {} objects, {} bytes",
+			stats.received_objects(),
+			stats.received_bytes()
+		);
+		// same as the git cli, fetched changes are handled by the rebase
+		Ok(0)
+	}
 
+	fn rebase(&mut self, upstream: &Upstream, signing: Option<&Signing>) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let upstream = match repo.find_reference(&upstream.tracking_ref()) {
+			Ok(upstream) => upstream,
+			Err(e) => bail!("Couldn't find {}: {}", upstream, &e),
+		};
+		let upstream = repo.reference_to_annotated_commit(&upstream)?;
+		// like `git rebase`, nothing is rewritten
+		let head = repo.head()?.peel_to_commit()?.id();
+		if head == upstream.id() || repo.graph_descendant_of(head, upstream.id())? {
+			status!(
+				self.status,
+				"Already up to date with upstream {}",
+				upstream.id()
+			);
+			return Ok(());
+		}
+		status!(self.status, "Rebasing on upstream {}", upstream.id());
+
+		let sig = repo.signature()?;
+		let mut rebase = repo.rebase(None, Some(&upstream), None, None)?;
+		while let Some(op) = rebase.next() {
+			if let Err(e) = op {
+				rebase.abort()?;
+				bail!("Rebase failed: {}", &e);
+			}
+			match rebase.commit(None, &sig, None) {
+				Ok(_) => {},
+				// the change is already upstream
+				Err(e) if e.code() == ErrorCode::Applied => {},
+				Err(e) => {
+					rebase.abort()?;
+					bail!("Rebase failed: {}", &e);
+				},
+			}
+		}
+		rebase.finish(None)?;
 
+		// libgit2 rewrites the commits without signatures
+		if let Some(signing) = signing {
+			self.sign_commits(upstream.id(), signing)?;
+		}
+		Ok(())
+	}
 
+	fn push(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		let (branch, _) = self.branch_upstream()?;
+		let refspec = format!("refs/heads/{}:{}", branch, upstream.remote_ref());
+		self.push_refspecs(&upstream.remote, &[refspec])?;
+		Ok(0)
+	}
 
+	fn push_tag(&mut self, remote: &str, tag: &str) -> anyhow::Result<usize> {
+		let tag_ref = format!("refs/tags/{}", &tag);
+		status!(self.status, "Pushing ref {}", &tag_ref);
+		self.push_refspecs(remote, &[tag_ref])?;
+		Ok(0)
+	}
+}
@@ -1,125 +1,303 @@
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output};
 
 //use anyhow::*;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 
+use crate::git_status::GitStatus;
+use crate::signing::Signing;
+use crate::vcs::{Upstream, VcsBackend};
+
 pub struct Repository {
-	path: PathBuf,
+	path:   PathBuf,
+	output: Vec<(String, String)>,
 }
 
 impl Repository {
 	pub fn new(path: &Path) -> Self {
 		Self {
-			path: path.to_owned(),
+			path:   path.to_owned(),
+			output: Vec::new(),
 		}
 	}
 
-	#[allow(dead_code)]
 	pub fn open(&mut self) -> anyhow::Result<()> {
 		Ok(())
 	}
 
-	pub fn get_dirty(&mut self) -> anyhow::Result<Vec<String>> {
-		// :HACK: This is super hacky, and very incomplete
-		let mut changed_files = Vec::new();
-		// Note: do not trim!
-		let changes = self.git_cmd(&["status", "--branch", "--porcelain"])?;
-		// let changes = changes.trim();
-		for c in changes.split('\n') {
-			// let c = c.trim();
-			if c.len() >= 4 {
-				//				eprintln!("Change: {}", &c);
-				match &c[0..2] {
-					"##" => println!(":TODO: branch status ignored {}", &c),
-					" M" => changed_files.push(c[2..].to_string()),
-					"??" => println!("Untracked file (not considered dirty): {}", &c[3..]),
-					o => eprintln!("Unhandled status prefix {:?}", &o),
-				}
+	fn git_output(&self, args: &[&str]) -> anyhow::Result<Output> {
+		let path = match self.path.clone().into_os_string().into_string() {
+			Ok(p) => p,
+			Err(e) => bail!("Can not convert path to string {:?}", &e),
+		};
+
+		tracing::debug!("git -C {} {}", &path, args.join(" "));
+		let args: Vec<&str> = args.iter().map(|s| s.trim()).collect();
+		let output = Command::new("git")
+			.arg("-C")
+			.arg(&path)
+			.args(&args)
+			.output()
+			.with_context(|| format!("error running git `{args:?}`"))?;
+		//		trace!("git output = {:?}", output);
+		Ok(output)
+	}
+
+	fn git_cmd(&mut self, args: &[&str]) -> anyhow::Result<String> {
+		let output = self.git_output(args)?;
+		if output.status.success() {
+			let stdout = Self::string_from_bytes(output.stdout)?;
+			tracing::debug!("{}", stdout);
+			if !stdout.trim().is_empty() {
+				self.output
+					.push((format!("git {}", args.join(" ")), stdout.clone()));
 			}
+			Ok(stdout)
+		} else {
+			Err(Self::error_from_output(output)?)
 		}
-		Ok(changed_files)
 	}
 
-	pub fn commit(&mut self, files: &[String], message: &str) -> anyhow::Result<()> {
+	fn error_from_output(output: Output) -> anyhow::Result<anyhow::Error> {
+		let mut error = "error while running git:\n".to_string();
+		let stdout = Self::string_from_bytes(output.stdout)?;
+		if !stdout.is_empty() {
+			error.push_str("- stdout: ");
+			error.push_str(&stdout);
+		}
+		let stderr = Self::string_from_bytes(output.stderr)?;
+		if !stderr.is_empty() {
+			error.push_str("- stderr: ");
+			error.push_str(&stderr);
+		}
+		Ok(anyhow!(error))
+	}
+
+	/// `git -c gpg.format=…` for a format that isn't the configured one.
+	fn signing_args(signing: &Signing) -> Vec<String> {
+		let mut args = Vec::new();
+		if let Some(format) = signing.format {
+			args.push("-c".to_string());
+			args.push(format!("gpg.format={}", format));
+		}
+		args
+	}
+
+	fn config(&self, key: &str) -> Option<String> {
+		let output = self.git_output(&["config", "--get", key]).ok()?;
+		if !output.status.success() {
+			return None;
+		}
+		let value = Self::string_from_bytes(output.stdout).ok()?;
+		Some(value.trim().to_string())
+	}
+
+	fn string_from_bytes(bytes: Vec<u8>) -> anyhow::Result<String> {
+		let stdout = String::from_utf8(bytes).context("cannot extract stderr")?;
+		//		let stdout = stdout.trim();
+		Ok(stdout)
+	}
+}
+
+impl VcsBackend for Repository {
+	fn status(&mut self) -> anyhow::Result<GitStatus> {
+		let output = self.git_output(&["status", "--porcelain=v2", "-z", "--branch"])?;
+		if !output.status.success() {
+			return Err(Self::error_from_output(output)?);
+		}
+		GitStatus::parse_porcelain_v2(&Self::string_from_bytes(output.stdout)?)
+	}
+
+	fn commit(
+		&mut self,
+		files: &[String],
+		message: &str,
+		signing: Option<&Signing>,
+	) -> anyhow::Result<()> {
 		for f in files.iter() {
 			//let p = Path::new(&cwd).join(&f);
 			self.git_cmd(&["add", f])?;
 		}
 		self.git_cmd(&["status"])?;
-		self.git_cmd(&["commit", "-m", message])?;
+		match signing {
+			Some(signing) => {
+				let mut args = Self::signing_args(signing);
+				args.extend(["commit", "-m", message].map(String::from));
+				args.push(format!("-S{}", signing.key.as_deref().unwrap_or_default()));
+				let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+				self.git_cmd(&args)?;
+			},
+			None => {
+				self.git_cmd(&["commit", "-m", message])?;
+			},
+		}
 		self.git_cmd(&["status"])?;
 		Ok(())
 	}
 
-	pub fn check_ignore(&mut self, file: &str) -> anyhow::Result<bool> {
-		let r = self.git_cmd(&["check-ignore", file])?;
-		Ok(!r.is_empty())
+	fn check_ignore(&mut self, file: &str) -> anyhow::Result<bool> {
+		// Note: `git check-ignore` exits with 1 if the file is *not* ignored
+		let output = self.git_output(&["check-ignore", file])?;
+		match output.status.code() {
+			Some(0) => Ok(true),
+			Some(1) => Ok(false),
+			_ => Err(Self::error_from_output(output)?),
+		}
 	}
 
-	pub fn tag(&mut self, tag: &str, msg: &str) -> anyhow::Result<()> {
-		self.git_cmd(&["tag", tag, "-m", msg])?;
+	fn tag(&mut self, tag: &str, msg: &str, signing: Option<&Signing>) -> anyhow::Result<()> {
+		match signing {
+			Some(signing) => {
+				let mut args = Self::signing_args(signing);
+				args.extend(["tag", "-s", tag, "-m", msg].map(String::from));
+				if let Some(key) = &signing.key {
+					args.extend(["-u".to_string(), key.clone()]);
+				}
+				let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+				self.git_cmd(&args)?;
+			},
+			None => {
+				self.git_cmd(&["tag", tag, "-m", msg])?;
+			},
+		}
 		Ok(())
 	}
 
-	pub fn fetch(&mut self) -> anyhow::Result<usize> {
-		self.git_cmd(&["fetch"])?;
-		Ok(0)
+	fn verify_tag(&mut self, tag: &str, signing: &Signing) -> anyhow::Result<()> {
+		let output = self.git_output(&["cat-file", "tag", &format!("refs/tags/{}", tag)])?;
+		if !output.status.success() {
+			return Err(Self::error_from_output(output)?);
+		}
+		let object = Self::string_from_bytes(output.stdout)?;
+		let signing = signing.resolve(|key| self.config(key))?;
+		signing.verify_tag(tag, &object)
 	}
 
-	pub fn rebase(&mut self) -> anyhow::Result<()> {
-		self.git_cmd(&["rebase"])?;
-		Ok(())
+	fn head(&mut self) -> anyhow::Result<String> {
+		let head = self.git_cmd(&["rev-parse", "HEAD"])?;
+		Ok(head.trim().to_string())
 	}
 
-	pub fn push(&mut self) -> anyhow::Result<usize> {
-		self.git_cmd(&["push"])?;
-		Ok(0)
+	fn git_dir(&mut self) -> anyhow::Result<PathBuf> {
+		let git_dir = self.git_cmd(&["rev-parse", "--absolute-git-dir"])?;
+		Ok(PathBuf::from(git_dir.trim()))
 	}
 
-	pub fn push_tag(&mut self, tag: &str) -> anyhow::Result<usize> {
-		self.git_cmd(&["push", "origin", tag])?;
-		Ok(0)
+	fn upstream(&mut self) -> anyhow::Result<Option<Upstream>> {
+		let output = self.git_output(&["symbolic-ref", "-q", "HEAD"])?;
+		if !output.status.success() {
+			// detached
+			return Ok(None);
+		}
+		let branch = Self::string_from_bytes(output.stdout)?;
+		let upstream = self.git_cmd(&[
+			"for-each-ref",
+			"--format=%(upstream:remotename)%00%(upstream:remoteref)",
+			branch.trim(),
+		])?;
+		match upstream.trim().split_once('\0') {
+			Some((remote, merge)) if !remote.is_empty() => {
+				let branch = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+				Ok(Some(Upstream::new(remote, branch)))
+			},
+			_ => Ok(None),
+		}
 	}
 
-	fn git_cmd(&self, args: &[&str]) -> anyhow::Result<String> {
-		let path = match self.path.clone().into_os_string().into_string() {
-			Ok(p) => p,
-			Err(e) => bail!("Can not convert path to string {:?}", &e),
+	fn tag_names(&mut self, pattern: &str) -> anyhow::Result<Vec<String>> {
+		let tags = self.git_cmd(&["tag", "--list", pattern])?;
+		Ok(tags.lines().map(|t| t.to_string()).collect())
+	}
+
+	fn last_tag(&mut self, pattern: &str, exclude: &[String]) -> anyhow::Result<Option<String>> {
+		let mut args = vec!["describe", "--tags", "--abbrev=0", "--match", pattern];
+		for e in exclude.iter() {
+			args.push("--exclude");
+			args.push(e);
+		}
+		args.push("HEAD");
+		let output = self.git_output(&args)?;
+		if !output.status.success() {
+			// no matching tag
+			return Ok(None);
+		}
+		let tag = Self::string_from_bytes(output.stdout)?;
+		Ok(Some(tag.trim().to_string()))
+	}
+
+	fn commit_messages(&mut self, since: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+		let range = match since {
+			Some(since) => format!("{}..HEAD", since),
+			None => "HEAD".to_string(),
 		};
+		let log = self.git_cmd(&["log", "--format=%H%x00%B%x00", &range])?;
+		let mut fields = log.split('\0');
+		let mut commits = Vec::new();
+		while let (Some(hash), Some(message)) = (fields.next(), fields.next()) {
+			commits.push((hash.trim().to_string(), message.to_string()));
+		}
+		Ok(commits)
+	}
 
-		tracing::debug!("git -C {} {}", &path, args.join(" "));
-		let args: Vec<&str> = args.iter().map(|s| s.trim()).collect();
-		let output = Command::new("git")
-			.arg("-C")
-			.arg(&path)
-			.args(&args)
-			.output()
-			.with_context(|| format!("error running git `{args:?}`"))?;
-		//		trace!("git output = {:?}", output);
-		let stdout = Self::string_from_bytes(output.stdout)?;
-		if output.status.success() {
-			tracing::debug!("{}", stdout);
-			Ok(stdout)
-		} else {
-			let mut error = "error while running git:\n".to_string();
-			if !stdout.is_empty() {
-				error.push_str("- stdout: ");
-				error.push_str(&stdout);
-			}
-			let stderr = Self::string_from_bytes(output.stderr)?;
-			if !stderr.is_empty() {
-				error.push_str("- stderr: ");
-				error.push_str(&stderr);
-			}
-			Err(anyhow!(error))
+	fn delete_tag(&mut self, tag: &str) -> anyhow::Result<()> {
+		self.git_cmd(&["tag", "-d", tag])?;
+		Ok(())
+	}
+
+	fn reset(&mut self, commit: &str) -> anyhow::Result<()> {
+		self.git_cmd(&["reset", "--quiet", commit])?;
+		Ok(())
+	}
+
+	fn reset_hard(&mut self, commit: &str) -> anyhow::Result<()> {
+		self.git_cmd(&["reset", "--quiet", "--hard", commit])?;
+		Ok(())
+	}
+
+	fn abort_rebase(&mut self) -> anyhow::Result<()> {
+		let git_dir = self.git_dir()?;
+		if git_dir.join("rebase-merge").exists() || git_dir.join("rebase-apply").exists() {
+			self.git_cmd(&["rebase", "--abort"])?;
 		}
+		Ok(())
 	}
-	fn string_from_bytes(bytes: Vec<u8>) -> anyhow::Result<String> {
-		let stdout = String::from_utf8(bytes).context("cannot extract stderr")?;
-		//		let stdout = stdout.trim();
-		Ok(stdout)
+
+	fn fetch(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		self.git_cmd(&["fetch", &upstream.remote, &upstream.fetch_refspec()])?;
+		Ok(0)
+	}
+
+	fn rebase(&mut self, upstream: &Upstream, signing: Option<&Signing>) -> anyhow::Result<()> {
+		match signing {
+			Some(signing) => {
+				let mut args = Self::signing_args(signing);
+				args.push("rebase".to_string());
+				args.push(format!("-S{}", signing.key.as_deref().unwrap_or_default()));
+				args.push(upstream.tracking_ref());
+				let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+				self.git_cmd(&args)?;
+			},
+			None => {
+				self.git_cmd(&["rebase", &upstream.tracking_ref()])?;
+			},
+		}
+		Ok(())
+	}
+
+	fn push(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		let refspec = format!("HEAD:{}", upstream.remote_ref());
+		self.git_cmd(&["push", &upstream.remote, &refspec])?;
+		Ok(0)
+	}
+
+	fn push_tag(&mut self, remote: &str, tag: &str) -> anyhow::Result<usize> {
+		self.git_cmd(&["push", remote, &format!("refs/tags/{}", tag)])?;
+		Ok(0)
+	}
+
+	fn take_output(&mut self) -> Vec<(String, String)> {
+		std::mem::take(&mut self.output)
 	}
 }
@@ -0,0 +1,534 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use anyhow::bail;
+use gix::bstr::{BStr, ByteSlice};
+use gix::refs::transaction::{Change, LogChange, PreviousValue, RefEdit, RefLog};
+use gix::refs::Target;
+use gix::status::index_worktree::iter::Item;
+use gix::status::plumbing::index_as_worktree::{Change as WorktreeChange, EntryStatus};
+
+use crate::event::StatusSink;
+use crate::git_status::{
+	BranchStatus, EntryKind, FileState, GitStatus, StatusEntry, SubmoduleState,
+};
+use crate::signing::Signing;
+use crate::vcs::{glob_match, Upstream, VcsBackend};
+
+/// `VcsBackend` using gitoxide.
+///
+/// gitoxide can neither push, rebase, nor sign yet,
+/// so `rebase`, `abort_rebase`, `push`, `push_tag`, and signed commits, and tags run the `git` binary.
+pub struct Repository {
+	path:   PathBuf,
+	repo:   Option<gix::Repository>,
+	cli:    crate::repository_cmdgit::Repository,
+	status: StatusSink,
+}
+
+impl Repository {
+	pub fn new(path: &Path, status: StatusSink) -> Self {
+		Self {
+			path: path.to_owned(),
+			repo: None,
+			cli: crate::repository_cmdgit::Repository::new(path),
+			status,
+		}
+	}
+
+	pub fn open(&mut self) -> anyhow::Result<()> {
+		let repo = match gix::discover(&self.path) {
+			Ok(repo) => repo,
+			Err(e) => bail!("failed to open: {}", e),
+		};
+		self.repo = Some(repo);
+		self.cli.open()?;
+
+		Ok(())
+	}
+
+	fn repo(&self) -> anyhow::Result<&gix::Repository> {
+		match &self.repo {
+			Some(repo) => Ok(repo),
+			None => bail!("No repo open"),
+		}
+	}
+
+	/// `file` relative to the repository root, with `/` separators, as the index stores it.
+	fn repo_path(&self, file: &str) -> anyhow::Result<String> {
+		let repo = self.repo()?;
+		let workdir = match repo.work_dir() {
+			Some(wd) => wd.canonicalize()?,
+			None => bail!("No workdir for repository"),
+		};
+		let path = self.path.canonicalize()?.join(file);
+		let path = match path.strip_prefix(&workdir) {
+			Ok(p) => p.to_owned(),
+			Err(e) => bail!("Error stripping {:?} from {:?}: {}", &workdir, &path, &e),
+		};
+		let parts: Vec<String> = path
+			.components()
+			.map(|c| c.as_os_str().to_string_lossy().to_string())
+			.collect();
+		Ok(parts.join("/"))
+	}
+
+	/// The full name of the current branch, e.g. `refs/heads/main`.
+	fn branch(&self) -> anyhow::Result<gix::refs::FullName> {
+		match self.repo()?.head_name()? {
+			Some(name) => Ok(name),
+			None => bail!("HEAD is detached, not on a branch"),
+		}
+	}
+
+	/// The current branch, its upstream, and how far they diverged.
+	fn branch_status(&self) -> anyhow::Result<BranchStatus> {
+		let repo = self.repo()?;
+		let mut branch = BranchStatus {
+			oid: repo.head_id().ok().map(|id| id.to_string()),
+			..Default::default()
+		};
+		let Ok(name) = self.branch() else {
+			return Ok(branch);
+		};
+		branch.head = Some(name.shorten().to_string());
+		let Some(Ok(upstream)) =
+			repo.branch_remote_tracking_ref_name(name.as_ref(), gix::remote::Direction::Fetch)
+		else {
+			return Ok(branch);
+		};
+		branch.upstream = Some(upstream.shorten().to_string());
+
+		let (Ok(local), Some(mut remote)) =
+			(repo.head_id(), repo.try_find_reference(upstream.as_ref())?)
+		else {
+			return Ok(branch);
+		};
+		let remote = remote.peel_to_id_in_place()?;
+		let ancestors = |tip: gix::ObjectId| -> anyhow::Result<HashSet<gix::ObjectId>> {
+			let mut ids = HashSet::new();
+			for info in repo.rev_walk([tip]).all()? {
+				ids.insert(info?.id);
+			}
+			Ok(ids)
+		};
+		let local = ancestors(local.detach())?;
+		let remote = ancestors(remote.detach())?;
+		branch.ahead = local.difference(&remote).count();
+		branch.behind = remote.difference(&local).count();
+		Ok(branch)
+	}
+
+	/// Tags matching `pattern` by the commit they point to.
+	fn tags(
+		&self,
+		pattern: &str,
+		exclude: &[String],
+	) -> anyhow::Result<HashMap<gix::ObjectId, String>> {
+		let repo = self.repo()?;
+		let mut tags = HashMap::new();
+		for r in repo.references()?.tags()? {
+			let mut r = match r {
+				Ok(r) => r,
+				Err(e) => bail!("Error reading tags: {}", e),
+			};
+			let name = r.name().shorten().to_string();
+			if exclude.contains(&name) || !glob_match(pattern, &name) {
+				continue;
+			}
+			let commit = r.peel_to_commit()?;
+			tags.entry(commit.id).or_insert(name);
+		}
+		Ok(tags)
+	}
+}
+
+impl VcsBackend for Repository {
+	fn status(&mut self) -> anyhow::Result<GitStatus> {
+		let repo = self.repo()?;
+		let branch = self.branch_status()?;
+
+		// worktree vs. index
+		let mut worktree: BTreeMap<String, StatusEntry> = BTreeMap::new();
+		let changes = repo
+			.status(gix::progress::Discard)?
+			.index_worktree_rewrites(None)
+			.index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
+			.into_index_worktree_iter(Vec::new())?;
+		for item in changes {
+			match item? {
+				Item::Modification {
+					rela_path, status, ..
+				} => {
+					let path = rela_path.to_string();
+					let mut entry = StatusEntry::new(EntryKind::Tracked, &path);
+					match status {
+						EntryStatus::Conflict(_) => entry.kind = EntryKind::Unmerged,
+						EntryStatus::Change(WorktreeChange::Removed) => {
+							entry.worktree = FileState::Deleted
+						},
+						EntryStatus::Change(WorktreeChange::Type) => {
+							entry.worktree = FileState::TypeChanged
+						},
+						EntryStatus::Change(WorktreeChange::Modification { .. }) => {
+							entry.worktree = FileState::Modified
+						},
+						EntryStatus::Change(WorktreeChange::SubmoduleModification(sub)) => {
+							entry.worktree = FileState::Modified;
+							entry.submodule = Some(SubmoduleState {
+								commit_changed: sub.checked_out_head_id != sub.index_id,
+								modified:       sub.changes.iter().flatten().any(|c| {
+									matches!(c, Item::Modification { .. } | Item::Rewrite { .. })
+								}),
+								untracked:      sub
+									.changes
+									.iter()
+									.flatten()
+									.any(|c| matches!(c, Item::DirectoryContents { .. })),
+							});
+						},
+						EntryStatus::IntentToAdd => entry.worktree = FileState::Added,
+						// only the stat changed
+						EntryStatus::NeedsUpdate(_) => continue,
+					}
+					worktree.insert(path, entry);
+				},
+				Item::DirectoryContents { entry, .. } => {
+					if entry.status == gix::dir::entry::Status::Untracked {
+						let path = entry.rela_path.to_string();
+						worktree
+							.insert(path.clone(), StatusEntry::new(EntryKind::Untracked, &path));
+					}
+				},
+				Item::Rewrite {
+					source,
+					dirwalk_entry,
+					..
+				} => {
+					let path = dirwalk_entry.rela_path.to_string();
+					let mut entry = StatusEntry::new(EntryKind::Tracked, &path);
+					entry.worktree = FileState::Renamed;
+					entry.source = Some(source.rela_path().to_string());
+					worktree.insert(path, entry);
+				},
+			}
+		}
+
+		// index vs. HEAD
+		let index = repo.index_or_empty()?;
+		let head = match repo.head_tree_id() {
+			Ok(tree) => repo.index_from_tree(&tree)?,
+			// no commit yet
+			Err(_) => gix::index::File::from_state(
+				gix::index::State::new(repo.object_hash()),
+				repo.index_path(),
+			),
+		};
+		let mut head: HashMap<&BStr, gix::ObjectId> = head
+			.entries()
+			.iter()
+			.map(|e| (e.path(&head), e.id))
+			.collect();
+		let mut staged: Vec<(String, FileState)> = Vec::new();
+		for e in index.entries() {
+			let path = e.path(&index);
+			match head.remove(path) {
+				None => staged.push((path.to_string(), FileState::Added)),
+				Some(id) if id != e.id => staged.push((path.to_string(), FileState::Modified)),
+				Some(_) => {},
+			}
+		}
+		staged.extend(head.keys().map(|p| (p.to_string(), FileState::Deleted)));
+		for (path, state) in staged {
+			worktree
+				.entry(path.clone())
+				.or_insert_with(|| StatusEntry::new(EntryKind::Tracked, &path))
+				.index = state;
+		}
+
+		Ok(GitStatus {
+			branch,
+			entries: worktree.into_values().collect(),
+		})
+	}
+
+	fn commit(
+		&mut self,
+		files: &[String],
+		message: &str,
+		signing: Option<&Signing>,
+	) -> anyhow::Result<()> {
+		if signing.is_some() {
+			return self.cli.commit(files, message, signing);
+		}
+		let mut paths = Vec::new();
+		for f in files.iter() {
+			paths.push(self.repo_path(f)?);
+		}
+		let repo = self.repo()?;
+		let workdir = match repo.work_dir() {
+			Some(wd) => wd.to_owned(),
+			None => bail!("No workdir for repository"),
+		};
+
+		let parent = repo.head_commit()?;
+		let mut editor = repo.edit_tree(parent.tree_id()?)?;
+		let mut index = repo.open_index()?;
+		for p in paths.iter() {
+			let file = workdir.join(p);
+			let id = repo.write_blob(std::fs::read(&file)?)?.detach();
+			editor.upsert(p, gix::object::tree::EntryKind::Blob, id)?;
+
+			let stat = gix::index::entry::Stat::from_fs(
+				&gix::index::fs::Metadata::from_path_no_follow(&file)?,
+			)?;
+			match index.entry_mut_by_path_and_stage(p.as_bytes().as_bstr(), Default::default()) {
+				Some(entry) => {
+					entry.id = id;
+					entry.stat = stat;
+				},
+				None => {
+					index.dangerously_push_entry(
+						stat,
+						id,
+						gix::index::entry::Flags::empty(),
+						gix::index::entry::Mode::FILE,
+						p.as_bytes().as_bstr(),
+					);
+					index.sort_entries();
+				},
+			}
+		}
+		let tree = editor.write()?;
+		repo.commit("HEAD", message, tree, [parent.id])?;
+		index.write(Default::default())?;
+		Ok(())
+	}
+
+	fn check_ignore(&mut self, file: &str) -> anyhow::Result<bool> {
+		let path = self.repo_path(file)?;
+		let repo = self.repo()?;
+		let index = repo.index_or_empty()?;
+		// tracked files are never ignored, same as `git check-ignore`
+		if index.entry_by_path(path.as_bytes().as_bstr()).is_some() {
+			return Ok(false);
+		}
+		let mut excludes = repo.excludes(
+			&index,
+			None,
+			gix::worktree::stack::state::ignore::Source::WorktreeThenIdMappingIfNotSkipped,
+		)?;
+		let platform = excludes.at_entry(path.as_bytes().as_bstr(), None)?;
+		Ok(platform.is_excluded())
+	}
+
+	fn tag(&mut self, tag: &str, msg: &str, signing: Option<&Signing>) -> anyhow::Result<()> {
+		if signing.is_some() {
+			return self.cli.tag(tag, msg, signing);
+		}
+		let repo = self.repo()?;
+		let head = repo.head_id()?;
+		status!(self.status, "Tagging {} with {}", &head, &tag);
+		let tagger = match repo.committer() {
+			Some(tagger) => tagger?,
+			None => bail!("No committer configured for tagging"),
+		};
+		repo.tag(
+			tag,
+			head,
+			gix::object::Kind::Commit,
+			Some(tagger),
+			msg,
+			PreviousValue::MustNotExist,
+		)?;
+		Ok(())
+	}
+
+	fn verify_tag(&mut self, tag: &str, signing: &Signing) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let id = match repo.try_find_reference(format!("refs/tags/{}", tag).as_str())? {
+			Some(r) => r.id(),
+			None => bail!("Couldn't find tag {}", tag),
+		};
+		let object = id.object()?;
+		if object.kind != gix::object::Kind::Tag {
+			bail!("Tag {} is not signed", tag);
+		}
+		let config = repo.config_snapshot();
+		let signing = signing.resolve(|key| config.string(key).map(|v| v.to_string()))?;
+		signing.verify_tag(tag, &object.data.to_str_lossy())
+	}
+
+	fn delete_tag(&mut self, tag: &str) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		repo.find_reference(format!("refs/tags/{}", tag).as_str())?
+			.delete()?;
+		Ok(())
+	}
+
+	fn head(&mut self) -> anyhow::Result<String> {
+		Ok(self.repo()?.head_id()?.to_string())
+	}
+
+	fn git_dir(&mut self) -> anyhow::Result<PathBuf> {
+		Ok(self.repo()?.git_dir().canonicalize()?)
+	}
+
+	fn upstream(&mut self) -> anyhow::Result<Option<Upstream>> {
+		let Ok(branch) = self.branch() else {
+			return Ok(None);
+		};
+		let repo = self.repo()?;
+		let remote = repo.branch_remote_name(branch.shorten(), gix::remote::Direction::Fetch);
+		let merge = repo.branch_remote_ref_name(branch.as_ref(), gix::remote::Direction::Fetch);
+		match (remote, merge) {
+			(Some(remote), Some(merge)) => Ok(Some(Upstream::new(
+				&remote.as_bstr().to_string(),
+				&merge?.shorten().to_string(),
+			))),
+			_ => Ok(None),
+		}
+	}
+
+	fn tag_names(&mut self, pattern: &str) -> anyhow::Result<Vec<String>> {
+		let repo = self.repo()?;
+		let mut tags = Vec::new();
+		for r in repo.references()?.tags()? {
+			let r = match r {
+				Ok(r) => r,
+				Err(e) => bail!("Error reading tags: {}", e),
+			};
+			let name = r.name().shorten().to_string();
+			if glob_match(pattern, &name) {
+				tags.push(name);
+			}
+		}
+		Ok(tags)
+	}
+
+	fn last_tag(&mut self, pattern: &str, exclude: &[String]) -> anyhow::Result<Option<String>> {
+		let tags = self.tags(pattern, exclude)?;
+		if tags.is_empty() {
+			return Ok(None);
+		}
+
+		let repo = self.repo()?;
+		let walk = repo
+			.rev_walk([repo.head_id()?])
+			.sorting(gix::revision::walk::Sorting::ByCommitTime(
+				Default::default(),
+			))
+			.all()?;
+		for info in walk {
+			if let Some(name) = tags.get(&info?.id) {
+				return Ok(Some(name.clone()));
+			}
+		}
+		Ok(None)
+	}
+
+	fn commit_messages(&mut self, since: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+		let repo = self.repo()?;
+		let mut hidden = HashSet::new();
+		if let Some(since) = since {
+			let since = repo.rev_parse_single(since)?.object()?.peel_to_commit()?;
+			for info in repo.rev_walk([since.id]).all()? {
+				hidden.insert(info?.id);
+			}
+		}
+
+		let walk = repo
+			.rev_walk([repo.head_id()?])
+			.sorting(gix::revision::walk::Sorting::ByCommitTime(
+				Default::default(),
+			))
+			.all()?;
+		let mut commits = Vec::new();
+		for info in walk {
+			let info = info?;
+			if hidden.contains(&info.id) {
+				continue;
+			}
+			let commit = info.id().object()?.into_commit();
+			let message = commit.message_raw()?.to_str_lossy().to_string();
+			commits.push((info.id.to_string(), message));
+		}
+		Ok(commits)
+	}
+
+	fn reset(&mut self, commit: &str) -> anyhow::Result<()> {
+		let repo = self.repo()?;
+		let commit = repo.rev_parse_single(commit)?.object()?.peel_to_commit()?;
+
+		repo.edit_reference(RefEdit {
+			change: Change::Update {
+				log:      LogChange {
+					mode:                RefLog::AndReference,
+					force_create_reflog: false,
+					message:             format!("reset: moving to {}", commit.id).into(),
+				},
+				expected: PreviousValue::Any,
+				new:      Target::Object(commit.id),
+			},
+			name:   "HEAD".try_into()?,
+			deref:  true,
+		})?;
+
+		// mixed, the index matches the commit, and the working tree is kept
+		let old = repo.index_or_empty()?;
+		let mut index = repo.index_from_tree(&commit.tree_id()?)?;
+		for e in index.entries_mut_with_paths() {
+			if let Some(o) = old.entry_by_path(e.1) {
+				if o.id == e.0.id {
+					e.0.stat = o.stat;
+				}
+			}
+		}
+		index.write(Default::default())?;
+		Ok(())
+	}
+
+	fn abort_rebase(&mut self) -> anyhow::Result<()> {
+		self.cli.abort_rebase()
+	}
+
+	fn fetch(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		let repo = self.repo()?;
+		let mut remote = match repo.find_remote(upstream.remote.as_str()) {
+			Ok(remote) => remote,
+			Err(e) => bail!("Couldn't find remote({}): {}", &upstream.remote, &e),
+		};
+		remote.replace_refspecs(
+			[upstream.fetch_refspec().as_str()],
+			gix::remote::Direction::Fetch,
+		)?;
+		let outcome = remote
+			.connect(gix::remote::Direction::Fetch)?
+			.prepare_fetch(gix::progress::Discard, Default::default())?
+			.receive(gix::progress::Discard, &AtomicBool::new(false))?;
+		tracing::debug!("Fetched {:?}", outcome.status);
+		// same as the git cli, fetched changes are handled by the rebase
+		Ok(0)
+	}
+
+	fn reset_hard(&mut self, commit: &str) -> anyhow::Result<()> {
+		self.cli.reset_hard(commit)
+	}
+
+	fn rebase(&mut self, upstream: &Upstream, signing: Option<&Signing>) -> anyhow::Result<()> {
+		self.cli.rebase(upstream, signing)
+	}
+
+	fn push(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		self.cli.push(upstream)
+	}
+
+	fn push_tag(&mut self, remote: &str, tag: &str) -> anyhow::Result<usize> {
+		self.cli.push_tag(remote, tag)
+	}
+
+	fn take_output(&mut self) -> Vec<(String, String)> {
+		self.cli.take_output()
+	}
+}
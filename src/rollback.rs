@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+use crate::event::StatusSink;
+use crate::journal::Journal;
+use crate::vcs::VcsBackend;
+
+/// An action that undoes the local effect of a step.
+#[derive(Debug)]
+enum Compensation {
+	RestoreFile {
+		path:     PathBuf,
+		contents: Option<Vec<u8>>, // `None` if the file didn't exist
+	},
+	ResetHead(String),
+	AbortRebase,
+	DeleteTag(String),
+	/// Something left the machine, everything before this can not be undone.
+	NotReversible {
+		description: String,
+		journal:     Box<Journal>,
+	},
+}
+
+/// Collects compensating actions while a release runs,
+/// and replays them in reverse order if a step fails.
+#[derive(Debug, Default)]
+pub struct Rollback {
+	compensations: Vec<Compensation>,
+	status:        StatusSink,
+}
+
+impl Rollback {
+	pub fn new(status: StatusSink) -> Self {
+		Self {
+			status,
+			..Self::default()
+		}
+	}
+
+	/// Compensations registered after the last non reversible step.
+	fn reversible(&self) -> impl Iterator<Item = &Compensation> {
+		self.compensations
+			.iter()
+			.rev()
+			.take_while(|c| !matches!(c, Compensation::NotReversible { .. }))
+	}
+
+	/// Remembers the current contents of `path`, only the first call per file counts.
+	/// A file that doesn't exist yet is removed again.
+	pub fn restore_file(&mut self, path: &Path) -> anyhow::Result<()> {
+		let known = self.reversible().any(|c| match c {
+			Compensation::RestoreFile { path: p, .. } => p == path,
+			_ => false,
+		});
+		if !known {
+			let contents = if path.exists() {
+				Some(std::fs::read(path)?)
+			} else {
+				None
+			};
+			self.compensations.push(Compensation::RestoreFile {
+				path: path.to_owned(),
+				contents,
+			});
+		}
+		Ok(())
+	}
+
+	/// Remembers `head` to reset to, only the first call counts.
+	pub fn reset_head(&mut self, head: &str) {
+		let known = self
+			.reversible()
+			.any(|c| matches!(c, Compensation::ResetHead(_)));
+		if !known {
+			self.compensations
+				.push(Compensation::ResetHead(head.to_string()));
+		}
+	}
+
+	pub fn abort_rebase(&mut self) {
+		self.compensations.push(Compensation::AbortRebase);
+	}
+
+	pub fn delete_tag(&mut self, tag: &str) {
+		self.compensations
+			.push(Compensation::DeleteTag(tag.to_string()));
+	}
+
+	/// Marks a step that can not be undone, e.g. a push.
+	/// The journal is restored if a rollback stops here, so the release can be resumed.
+	pub fn not_reversible(&mut self, description: &str, journal: &Journal) {
+		self.compensations.push(Compensation::NotReversible {
+			description: description.to_string(),
+			journal:     Box::new(journal.clone()),
+		});
+	}
+
+	/// Undoes everything after the last non reversible step.
+	///
+	/// Returns `true` if everything could be undone.
+	pub fn run(&mut self, repo: &mut dyn VcsBackend) -> anyhow::Result<bool> {
+		status!(self.status, "Rolling back local changes...");
+		let mut errors = Vec::new();
+		while let Some(c) = self.compensations.pop() {
+			let r = match &c {
+				Compensation::RestoreFile {
+					path,
+					contents: Some(contents),
+				} => {
+					status!(self.status, "Restoring {}", path.display());
+					std::fs::write(path, contents).map_err(anyhow::Error::from)
+				},
+				Compensation::RestoreFile {
+					path,
+					contents: None,
+				} => {
+					match std::fs::remove_file(path) {
+						Ok(()) => {
+							status!(self.status, "Removed {}", path.display());
+							Ok(())
+						},
+						// the step never created it
+						Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+						Err(e) => Err(e.into()),
+					}
+				},
+				Compensation::ResetHead(head) => {
+					status!(self.status, "Resetting to {}", &head);
+					repo.reset(head)
+				},
+				Compensation::AbortRebase => repo.abort_rebase(),
+				Compensation::DeleteTag(tag) => {
+					status!(self.status, "Deleting local tag {}", &tag);
+					repo.delete_tag(tag)
+				},
+				Compensation::NotReversible {
+					description,
+					journal,
+				} => {
+					status!(self.status, "Not reversible: {}", &description);
+					status!(
+						self.status,
+						"Rollback stopped. Use --resume to finish the release."
+					);
+					journal.save()?;
+					return Ok(false);
+				},
+			};
+			if let Err(e) = r {
+				status!(self.status, "Rollback step failed: {}", &e);
+				errors.push(e);
+			}
+		}
+
+		if let Some(e) = errors.pop() {
+			return Err(e.context("Rollback incomplete"));
+		}
+		status!(self.status, "Rollback complete");
+		Ok(true)
+	}
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Context};
+
+/// `gpg.format`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureFormat {
+	#[default]
+	OpenPgp,
+	Ssh,
+}
+
+impl fmt::Display for SignatureFormat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SignatureFormat::OpenPgp => write!(f, "openpgp"),
+			SignatureFormat::Ssh => write!(f, "ssh"),
+		}
+	}
+}
+
+impl FromStr for SignatureFormat {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		match s {
+			"openpgp" => Ok(SignatureFormat::OpenPgp),
+			"ssh" => Ok(SignatureFormat::Ssh),
+			o => bail!("Unknown signature format {:?}, should be openpgp/ssh", o),
+		}
+	}
+}
+
+/// How release commits, and tags are signed, unset fields come from the git config.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Signing {
+	pub format:  Option<SignatureFormat>,
+	/// A gpg key id, or the path of an ssh key, `user.signingKey` if unset.
+	pub key:     Option<String>,
+	/// `gpg.program`, or `gpg.ssh.program` if unset.
+	pub program: Option<String>,
+}
+
+impl Signing {
+	/// Fills the unset fields from the git config, `get` returns the value of a config key.
+	pub fn resolve(&self, get: impl Fn(&str) -> Option<String>) -> anyhow::Result<Signing> {
+		let format = match (self.format, get("gpg.format")) {
+			(Some(format), _) => format,
+			(None, Some(format)) => format.parse()?,
+			(None, None) => SignatureFormat::default(),
+		};
+		let program_key = match format {
+			SignatureFormat::OpenPgp => "gpg.program",
+			SignatureFormat::Ssh => "gpg.ssh.program",
+		};
+		Ok(Signing {
+			format:  Some(format),
+			key:     self.key.clone().or_else(|| get("user.signingkey")),
+			program: self.program.clone().or_else(|| get(program_key)),
+		})
+	}
+
+	fn format(&self) -> SignatureFormat {
+		self.format.unwrap_or_default()
+	}
+
+	fn program(&self) -> &str {
+		match (&self.program, self.format()) {
+			(Some(program), _) => program,
+			(None, SignatureFormat::OpenPgp) => "gpg",
+			(None, SignatureFormat::Ssh) => "ssh-keygen",
+		}
+	}
+
+	/// Signs `payload`, returns the armored signature.
+	pub fn sign(&self, payload: &str) -> anyhow::Result<String> {
+		match self.format() {
+			SignatureFormat::OpenPgp => {
+				let mut args = vec!["--status-fd=2", "-bsa"];
+				if let Some(key) = &self.key {
+					args.push("-u");
+					args.push(key);
+				}
+				run(self.program(), &args, payload)
+			},
+			SignatureFormat::Ssh => {
+				let Some(key) = &self.key else {
+					bail!(
+						"Signing with ssh needs a key, set `user.signingKey`, or use --signing-key"
+					);
+				};
+				let key = expand_home(key);
+				run(
+					self.program(),
+					&["-Y", "sign", "-n", "git", "-f", &key.to_string_lossy()],
+					payload,
+				)
+			},
+		}
+	}
+
+	/// Checks that `signature` is a valid signature of `payload`.
+	///
+	/// *Note:* Ssh signatures are not checked against `gpg.ssh.allowedSignersFile`.
+	pub fn verify(&self, payload: &str, signature: &str) -> anyhow::Result<()> {
+		static COUNT: AtomicUsize = AtomicUsize::new(0);
+		let path = std::env::temp_dir().join(format!(
+			"omr-bumper-{}-{}.sig",
+			std::process::id(),
+			COUNT.fetch_add(1, Ordering::Relaxed)
+		));
+		std::fs::write(&path, signature)?;
+		let sig = path.to_string_lossy().to_string();
+		let result = match self.format() {
+			SignatureFormat::OpenPgp => run(
+				self.program(),
+				&["--status-fd=1", "--verify", &sig, "-"],
+				payload,
+			)
+			.and_then(|status| match status.contains("[GNUPG:] GOODSIG") {
+				true => Ok(()),
+				false => bail!("gpg reported no good signature"),
+			}),
+			SignatureFormat::Ssh => run(
+				self.program(),
+				&["-Y", "check-novalidate", "-n", "git", "-s", &sig],
+				payload,
+			)
+			.map(|_| ()),
+		};
+		let _ = std::fs::remove_file(&path);
+		result
+	}
+
+	/// Checks the signature of the annotated tag `tag`, `object` is the content of the tag object.
+	pub fn verify_tag(&self, tag: &str, object: &str) -> anyhow::Result<()> {
+		match split_signature(object) {
+			Some((payload, signature)) => self
+				.verify(payload, signature)
+				.with_context(|| format!("Signature of tag {} is not valid", tag)),
+			None => bail!("Tag {} is not signed", tag),
+		}
+	}
+}
+
+/// Splits a tag object into the signed part, and the signature, `None` if it isn't signed.
+pub fn split_signature(object: &str) -> Option<(&str, &str)> {
+	[
+		"-----BEGIN PGP SIGNATURE-----",
+		"-----BEGIN SSH SIGNATURE-----",
+	]
+	.iter()
+	.find_map(|begin| object.find(begin))
+	.map(|i| object.split_at(i))
+}
+
+/// Runs `program` with `input` on stdin, returns stdout.
+fn run(program: &str, args: &[&str], input: &str) -> anyhow::Result<String> {
+	let mut child = Command::new(program)
+		.args(args)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.with_context(|| format!("error running {}", program))?;
+	if let Some(mut stdin) = child.stdin.take() {
+		stdin.write_all(input.as_bytes())?;
+	}
+	let output = child.wait_with_output()?;
+	if !output.status.success() {
+		bail!(
+			"{} {} failed: {}",
+			program,
+			args.join(" "),
+			String::from_utf8_lossy(&output.stderr).trim()
+		);
+	}
+	Ok(String::from_utf8(output.stdout)?)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+	match path.strip_prefix("~/") {
+		Some(rest) => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(rest),
+		None => PathBuf::from(path),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn resolves_from_git_config() {
+		let config = |key: &str| match key {
+			"gpg.format" => Some("ssh".to_string()),
+			"user.signingkey" => Some("~/.ssh/id_ed25519.pub".to_string()),
+			_ => None,
+		};
+		let resolved = Signing::default().resolve(config).unwrap();
+		assert_eq!(resolved.format, Some(SignatureFormat::Ssh));
+		assert_eq!(resolved.key.as_deref(), Some("~/.ssh/id_ed25519.pub"));
+		assert_eq!(resolved.program(), "ssh-keygen");
+
+		let explicit = Signing {
+			format: Some(SignatureFormat::OpenPgp),
+			key: Some("ABCD1234".to_string()),
+			..Default::default()
+		};
+		let resolved = explicit.resolve(config).unwrap();
+		assert_eq!(resolved.format, Some(SignatureFormat::OpenPgp));
+		assert_eq!(resolved.key.as_deref(), Some("ABCD1234"));
+	}
+
+	#[test]
+	fn ssh_signature_round_trip() {
+		let dir = tempfile::tempdir().unwrap();
+		let key = dir.path().join("id_ed25519");
+		let status = Command::new("ssh-keygen")
+			.args(["-q", "-t", "ed25519", "-N", "", "-f"])
+			.arg(&key)
+			.status()
+			.unwrap();
+		assert!(status.success());
+		let signing = Signing {
+			format: Some(SignatureFormat::Ssh),
+			key: Some(key.to_string_lossy().to_string()),
+			..Default::default()
+		};
+
+		let payload = "object 1234\ntype commit\ntag 0.1.0\n\n. Tag 0.1.0\n";
+		let signature = signing.sign(payload).unwrap();
+		let object = format!("{}{}", payload, signature);
+		assert_eq!(
+			split_signature(&object),
+			Some((payload, signature.as_str()))
+		);
+		signing.verify_tag("0.1.0", &object).unwrap();
+
+		let tampered = object.replace("0.1.0\n\n", "0.1.1\n\n");
+		let err = signing.verify_tag("0.1.1", &tampered).unwrap_err();
+		assert_eq!(err.to_string(), "Signature of tag 0.1.1 is not valid");
+		let err = signing.verify_tag("0.1.0", payload).unwrap_err();
+		assert_eq!(err.to_string(), "Tag 0.1.0 is not signed");
+	}
+}
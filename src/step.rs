@@ -0,0 +1,285 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::tag_template::TagTemplate;
+
+/// What commit message templates, and trailers can contain, in `{}`.
+pub const COMMIT_PLACEHOLDERS: [&str; 5] = [
+	"old_version",
+	"new_version",
+	"bump_level",
+	"suffix",
+	"packages",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitCommitMessage {
+	BumpVersionForRelease,
+	BumpVersionForDev,
+}
+
+impl GitCommitMessage {
+	/// The message unless `release_commit_message`, or `dev_commit_message` is configured.
+	pub fn default_template(&self) -> &'static str {
+		match self {
+			GitCommitMessage::BumpVersionForRelease => {
+				": Bump version for {suffix} release - {new_version}"
+			},
+			GitCommitMessage::BumpVersionForDev => {
+				": Bump version back to dev release, and bump {bump_level} level - {new_version}"
+			},
+		}
+	}
+}
+
+impl fmt::Display for GitCommitMessage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl FromStr for GitCommitMessage {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		match s {
+			"BumpVersionForRelease" => Ok(GitCommitMessage::BumpVersionForRelease),
+			"BumpVersionForDev" => Ok(GitCommitMessage::BumpVersionForDev),
+			o => bail!(
+				"Unknown commit message {:?}, should be BumpVersionForRelease/BumpVersionForDev",
+				o
+			),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+	GitPreflight,   // fails on a branch, or upstream that shouldn't be released from
+	GitEnsureClean, // fails if there are any changes
+	GitShowDirty,
+	GitShowStatus,  // version, branch, upstream, and the last release tag
+	GitUndoRelease, // resets the branch to before the last release, and deletes its tags
+	CargoLoadManifest,
+	ManifestSetVersionSuffix,
+	ManifestSetVersionSuffixDev,
+	ManifestSetVersion, // the exact version given with `--release-version`
+	CargoSaveManifest,
+	CargoUpdateWorkspace,
+	ManifestPrintVersion,
+	GitCommitManifest(GitCommitMessage),
+	GitFetch,
+	GitRebase,
+	GitPush,
+	GitTag(Option<String>), // optional tag template, e.g. `v{version}`
+	GitPushTag(Option<String>),
+	ManifestBumpLevel,
+	ChangelogUpdate,
+}
+
+impl Step {
+	pub fn is_git(&self) -> bool {
+		matches!(
+			self,
+			Step::GitPreflight
+				| Step::GitEnsureClean
+				| Step::GitShowDirty
+				| Step::GitShowStatus
+				| Step::GitUndoRelease
+				| Step::GitCommitManifest(_)
+				| Step::GitFetch
+				| Step::GitRebase
+				| Step::GitPush
+				| Step::GitTag(_)
+				| Step::GitPushTag(_)
+		)
+	}
+
+	/// Steps that talk to the remote.
+	pub fn is_push(&self) -> bool {
+		matches!(
+			self,
+			Step::GitFetch | Step::GitRebase | Step::GitPush | Step::GitPushTag(_)
+		)
+	}
+
+	pub fn is_tag(&self) -> bool {
+		matches!(self, Step::GitTag(_) | Step::GitPushTag(_))
+	}
+
+	/// Steps that change nothing, pipelines of only these don't need a journal.
+	pub fn is_read_only(&self) -> bool {
+		matches!(
+			self,
+			Step::GitPreflight
+				| Step::GitEnsureClean
+				| Step::GitShowDirty
+				| Step::GitShowStatus
+				| Step::CargoLoadManifest
+				| Step::ManifestPrintVersion
+		)
+	}
+
+	/// Checks that every step has what it needs from the steps before it.
+	///
+	/// `source` is used to point to the offending entry.
+	pub fn validate(steps: &[Step], source: &str) -> anyhow::Result<()> {
+		if steps.is_empty() {
+			bail!("{}: No steps configured", source);
+		}
+
+		let mut manifest_loaded = false;
+		let mut version_known = false;
+		let mut tags = Vec::new();
+		for (i, s) in steps.iter().enumerate() {
+			let problem = match s {
+				Step::CargoLoadManifest => {
+					manifest_loaded = true;
+					None
+				},
+				Step::ManifestSetVersionSuffix
+				| Step::ManifestSetVersionSuffixDev
+				| Step::ManifestSetVersion
+				| Step::ManifestBumpLevel
+				| Step::CargoSaveManifest
+				| Step::ManifestPrintVersion
+					if !manifest_loaded =>
+				{
+					Some("needs a manifest, add `CargoLoadManifest` before it")
+				},
+				Step::ManifestPrintVersion => {
+					version_known = true;
+					None
+				},
+				Step::GitCommitManifest(_) | Step::GitTag(_) | Step::ChangelogUpdate
+					if !version_known =>
+				{
+					Some("needs the release version, add `ManifestPrintVersion` before it")
+				},
+				Step::GitTag(name) => {
+					tags.push(name.clone());
+					None
+				},
+				Step::GitPushTag(name) if !tags.contains(name) => {
+					Some("pushes a tag that is never created, add a matching `GitTag` before it")
+				},
+				_ => None,
+			};
+			if let Some(problem) = problem {
+				bail!("{}: step #{} `{}` {}", source, i + 1, s, problem);
+			}
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for Step {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Step::GitCommitManifest(m) => write!(f, "GitCommitManifest({})", m),
+			Step::GitTag(Some(name)) => write!(f, "GitTag({})", name),
+			Step::GitTag(None) => write!(f, "GitTag"),
+			Step::GitPushTag(Some(name)) => write!(f, "GitPushTag({})", name),
+			Step::GitPushTag(None) => write!(f, "GitPushTag"),
+			o => fmt::Debug::fmt(o, f),
+		}
+	}
+}
+
+impl FromStr for Step {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		let s = s.trim();
+		let (name, arg) = match s.split_once('(') {
+			Some((name, rest)) => match rest.strip_suffix(')') {
+				Some(arg) => (name.trim(), Some(arg.trim())),
+				None => bail!("Missing `)` in step {:?}", s),
+			},
+			None => (s, None),
+		};
+
+		let step = match (name, arg) {
+			("GitPreflight", None) => Step::GitPreflight,
+			("GitEnsureClean", None) => Step::GitEnsureClean,
+			("GitShowDirty", None) => Step::GitShowDirty,
+			("GitShowStatus", None) => Step::GitShowStatus,
+			("GitUndoRelease", None) => Step::GitUndoRelease,
+			("CargoLoadManifest", None) => Step::CargoLoadManifest,
+			("ManifestSetVersionSuffix", None) => Step::ManifestSetVersionSuffix,
+			("ManifestSetVersionSuffixDev", None) => Step::ManifestSetVersionSuffixDev,
+			("ManifestSetVersion", None) => Step::ManifestSetVersion,
+			("CargoSaveManifest", None) => Step::CargoSaveManifest,
+			("CargoUpdateWorkspace", None) => Step::CargoUpdateWorkspace,
+			("ManifestPrintVersion", None) => Step::ManifestPrintVersion,
+			("GitCommitManifest", Some(m)) => Step::GitCommitManifest(m.parse()?),
+			("GitFetch", None) => Step::GitFetch,
+			("GitRebase", None) => Step::GitRebase,
+			("GitPush", None) => Step::GitPush,
+			("GitTag", Some(name)) => Step::GitTag(Some(TagTemplate::new(name)?.to_string())),
+			("GitTag", None) => Step::GitTag(None),
+			("GitPushTag", Some(name)) => {
+				Step::GitPushTag(Some(TagTemplate::new(name)?.to_string()))
+			},
+			("GitPushTag", None) => Step::GitPushTag(None),
+			("ManifestBumpLevel", None) => Step::ManifestBumpLevel,
+			("ChangelogUpdate", None) => Step::ChangelogUpdate,
+			("GitCommitManifest", None) => {
+				bail!("Step `GitCommitManifest` needs a message, e.g. `GitCommitManifest(BumpVersionForRelease)`")
+			},
+			(name, Some(_)) if name.parse::<Step>().is_ok() => {
+				bail!("Step `{}` takes no argument", name)
+			},
+			(name, _) => bail!("Unknown step `{}`", name),
+		};
+		Ok(step)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn validate(steps: &[&str]) -> anyhow::Result<()> {
+		let steps: Vec<Step> = steps.iter().map(|s| s.parse().unwrap()).collect();
+		Step::validate(&steps, "omr-bumper.toml")
+	}
+
+	#[test]
+	fn valid_steps() {
+		validate(&[
+			"CargoLoadManifest",
+			"ManifestSetVersionSuffix",
+			"ManifestPrintVersion",
+			"GitCommitManifest(BumpVersionForRelease)",
+			"GitTag(v{version})",
+			"GitPushTag(v{version})",
+		])
+		.unwrap();
+	}
+
+	#[test]
+	fn invalid_steps() {
+		let err = |steps: &[&str]| validate(steps).unwrap_err().to_string();
+		assert_eq!(err(&[]), "omr-bumper.toml: No steps configured");
+		assert_eq!(
+			err(&["ManifestSetVersionSuffix", "CargoLoadManifest"]),
+			"omr-bumper.toml: step #1 `ManifestSetVersionSuffix` needs a manifest, add `CargoLoadManifest` before it"
+		);
+		assert_eq!(
+			err(&["CargoLoadManifest", "GitTag"]),
+			"omr-bumper.toml: step #2 `GitTag` needs the release version, add `ManifestPrintVersion` before it"
+		);
+		assert_eq!(
+			err(&[
+				"CargoLoadManifest",
+				"ManifestPrintVersion",
+				"GitTag(v{version})",
+				"GitPushTag",
+			]),
+			"omr-bumper.toml: step #4 `GitPushTag` pushes a tag that is never created, add a matching `GitTag` before it"
+		);
+	}
+}
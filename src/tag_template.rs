@@ -0,0 +1,197 @@
+use std::fmt;
+
+use anyhow::bail;
+use semver::{BuildMetadata, Prerelease, Version};
+
+use crate::template;
+
+/// What a tag template can contain, in `{}`.
+const PLACEHOLDERS: [&str; 8] = [
+	"version", "major", "minor", "patch", "pre", "build", "package", "date",
+];
+
+/// A tag name with placeholders, e.g. `v{version}`, or `{package}-v{major}.{minor}.{patch}`.
+///
+/// `{pre}` is the pre-release part of the version, empty for stable releases, `{date}` is `2024-01-31`.
+/// `{build}` is the build metadata, `{version}` leaves it out, unless `with_build_metadata` is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagTemplate {
+	template:       String,
+	build_metadata: bool,
+}
+
+impl TagTemplate {
+	pub fn new(template: &str) -> anyhow::Result<Self> {
+		if template.trim().is_empty() {
+			bail!("Tag template must not be empty");
+		}
+		template::check(template, &PLACEHOLDERS, "tag template")?;
+		Ok(Self {
+			template:       template.to_string(),
+			build_metadata: false,
+		})
+	}
+
+	/// Keeps the build metadata in `{version}`, e.g. `1.2.3-dev+g4f012a5`.
+	pub fn with_build_metadata(mut self, build_metadata: bool) -> Self {
+		self.build_metadata = build_metadata;
+		self
+	}
+
+	/// `true` if every package gets a tag of its own.
+	pub fn has_package(&self) -> bool {
+		self.template.contains("{package}")
+	}
+
+	pub fn render(&self, version: &Version, package: Option<&str>, date: &str) -> String {
+		self.expand(|p| match p {
+			"version" if self.build_metadata => version.to_string(),
+			"version" => {
+				let mut version = version.clone();
+				version.build = BuildMetadata::EMPTY;
+				version.to_string()
+			},
+			"major" => version.major.to_string(),
+			"minor" => version.minor.to_string(),
+			"patch" => version.patch.to_string(),
+			"pre" => version.pre.to_string(),
+			"build" => version.build.to_string(),
+			"package" => package.unwrap_or_default().to_string(),
+			"date" => date.to_string(),
+			_ => String::new(),
+		})
+		.unwrap_or_default()
+	}
+
+	/// Glob matching every tag rendered from the template, for `package`, or any package.
+	pub fn pattern(&self, package: Option<&str>) -> String {
+		self.expand(|p| match (p, package) {
+			("package", Some(package)) => package.to_string(),
+			_ => "*".to_string(),
+		})
+		.unwrap_or_default()
+		.replace("**", "*")
+	}
+
+	/// `true` if `tag` is rendered from the template, for `package`, or any package.
+	///
+	/// Unlike `pattern` the placeholders must be what they stand for, `latest` is no `{version}`.
+	pub fn matches(&self, tag: &str, package: Option<&str>) -> bool {
+		matches(&self.template, tag, package)
+	}
+
+	fn expand(&self, f: impl FnMut(&str) -> String) -> anyhow::Result<String> {
+		template::expand(&self.template, "tag template", f)
+	}
+}
+
+fn matches(template: &str, tag: &str, package: Option<&str>) -> bool {
+	let (Some(start), Some(end)) = (template.find('{'), template.find('}')) else {
+		return template == tag;
+	};
+	let Some(rest) = tag.strip_prefix(&template[..start]) else {
+		return false;
+	};
+	let placeholder = &template[start + 1..end];
+	(0..=rest.len())
+		.filter(|i| rest.is_char_boundary(*i))
+		.any(|i| {
+			is_placeholder(placeholder, &rest[..i], package)
+				&& matches(&template[end + 1..], &rest[i..], package)
+		})
+}
+
+/// `true` if `value` can be what `placeholder` renders to.
+fn is_placeholder(placeholder: &str, value: &str, package: Option<&str>) -> bool {
+	match placeholder {
+		"version" => Version::parse(value).is_ok(),
+		"major" | "minor" | "patch" => value.parse::<u64>().is_ok(),
+		"pre" => Prerelease::new(value).is_ok(),
+		"build" => BuildMetadata::new(value).is_ok(),
+		"package" => package.map_or(!value.is_empty(), |p| p == value),
+		_ => !value.is_empty(),
+	}
+}
+
+impl fmt::Display for TagTemplate {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", &self.template)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn renders_placeholders() {
+		let version = Version::parse("0.3.20-alpha").unwrap();
+		let render = |t: &str| {
+			TagTemplate::new(t)
+				.unwrap()
+				.render(&version, Some("foo"), "2024-01-31")
+		};
+		assert_eq!(render("{version}"), "0.3.20-alpha");
+		assert_eq!(render("v{version}"), "v0.3.20-alpha");
+		assert_eq!(render("{package}-v{version}"), "foo-v0.3.20-alpha");
+		assert_eq!(
+			render("{major}.{minor}.{patch}/{pre}/{date}"),
+			"0.3.20/alpha/2024-01-31"
+		);
+	}
+
+	#[test]
+	fn build_metadata_policy() {
+		let version = Version::parse("1.2.3-dev+g4f012a5.20261018").unwrap();
+		let template = TagTemplate::new("v{version}").unwrap();
+		assert_eq!(template.render(&version, None, ""), "v1.2.3-dev");
+		assert_eq!(
+			template
+				.with_build_metadata(true)
+				.render(&version, None, ""),
+			"v1.2.3-dev+g4f012a5.20261018"
+		);
+		let template = TagTemplate::new("v{version}-{build}").unwrap();
+		assert_eq!(
+			template.render(&version, None, ""),
+			"v1.2.3-dev-g4f012a5.20261018"
+		);
+	}
+
+	#[test]
+	fn patterns_match_rendered_tags() {
+		let template = TagTemplate::new("{package}-v{major}.{minor}.{patch}{pre}").unwrap();
+		assert_eq!(template.pattern(Some("foo")), "foo-v*.*.*");
+		assert_eq!(template.pattern(None), "*-v*.*.*");
+		assert!(template.has_package());
+		assert!(!TagTemplate::new("v{version}").unwrap().has_package());
+	}
+
+	#[test]
+	fn matches_only_rendered_tags() {
+		let template = TagTemplate::new("{version}").unwrap();
+		assert!(template.matches("0.3.20", None));
+		assert!(template.matches("0.3.20-alpha.1", None));
+		assert!(!template.matches("latest", None));
+		assert!(!template.matches("nightly", None));
+		assert!(!template.matches("v0.3.20", None));
+
+		let template = TagTemplate::new("{package}-v{major}.{minor}.{patch}{pre}").unwrap();
+		assert!(template.matches("foo-bar-v0.3.20", None));
+		assert!(template.matches("foo-bar-v0.3.20alpha", Some("foo-bar")));
+		assert!(!template.matches("foo-bar-v0.3.20", Some("foo")));
+		assert!(!template.matches("foo-vnext", None));
+	}
+
+	#[test]
+	fn rejects_invalid_templates() {
+		let err = TagTemplate::new("v{verison}").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Unknown placeholder `{verison}` in tag template \"v{verison}\", should be one of {version}/{major}/{minor}/{patch}/{pre}/{build}/{package}/{date}"
+		);
+		let err = TagTemplate::new("v{version").unwrap_err();
+		assert_eq!(err.to_string(), "Missing `}` in tag template \"v{version\"");
+		assert!(TagTemplate::new(" ").is_err());
+	}
+}
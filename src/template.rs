@@ -0,0 +1,53 @@
+use anyhow::bail;
+
+/// Fails on placeholders `template` can't contain, `what` names the template in the error.
+///
+/// `env:NAME` in `placeholders` allows `env:` followed by any name.
+pub fn check(template: &str, placeholders: &[&str], what: &str) -> anyhow::Result<()> {
+	let known = |p: &str| {
+		placeholders.iter().any(|k| match k.strip_suffix("NAME") {
+			Some(prefix) if prefix.ends_with(':') => {
+				p.len() > prefix.len() && p.starts_with(prefix)
+			},
+			_ => *k == p,
+		})
+	};
+	let mut unknown = None;
+	expand(template, what, |p| {
+		if !known(p) && unknown.is_none() {
+			unknown = Some(p.to_string());
+		}
+		String::new()
+	})?;
+	if let Some(p) = unknown {
+		let placeholders: Vec<String> = placeholders.iter().map(|p| format!("{{{}}}", p)).collect();
+		bail!(
+			"Unknown placeholder `{{{}}}` in {} {:?}, should be one of {}",
+			p,
+			what,
+			template,
+			placeholders.join("/")
+		);
+	}
+	Ok(())
+}
+
+/// Replaces every `{placeholder}` with what `f` returns for its name.
+pub fn expand(
+	template: &str,
+	what: &str,
+	mut f: impl FnMut(&str) -> String,
+) -> anyhow::Result<String> {
+	let mut expanded = String::new();
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		let Some(end) = rest[start..].find('}') else {
+			bail!("Missing `}}` in {} {:?}", what, template);
+		};
+		expanded.push_str(&rest[..start]);
+		expanded.push_str(&f(&rest[start + 1..start + end]));
+		rest = &rest[start + end + 1..];
+	}
+	expanded.push_str(rest);
+	Ok(expanded)
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::event::StatusSink;
+use crate::git_status::GitStatus;
+use crate::signing::Signing;
+
+/// Everything a release needs from version control.
+///
+/// Paths are relative to the path the backend was opened with.
+pub trait VcsBackend {
+	/// Changed, untracked, and conflicting files, and the state of the current branch.
+	///
+	/// Ignored files are left out.
+	fn status(&mut self) -> anyhow::Result<GitStatus>;
+	/// Commits `files`, signed if `signing` is given.
+	fn commit(
+		&mut self,
+		files: &[String],
+		message: &str,
+		signing: Option<&Signing>,
+	) -> anyhow::Result<()>;
+	fn check_ignore(&mut self, file: &str) -> anyhow::Result<bool>;
+	/// Creates an annotated tag on HEAD, signed if `signing` is given.
+	fn tag(&mut self, tag: &str, msg: &str, signing: Option<&Signing>) -> anyhow::Result<()>;
+	/// Fails unless `tag` is an annotated tag with a valid signature.
+	fn verify_tag(&mut self, tag: &str, signing: &Signing) -> anyhow::Result<()>;
+	fn delete_tag(&mut self, tag: &str) -> anyhow::Result<()>;
+	/// The commit id of HEAD.
+	fn head(&mut self) -> anyhow::Result<String>;
+	/// The absolute path of the `.git` directory.
+	fn git_dir(&mut self) -> anyhow::Result<PathBuf>;
+	/// Returns the upstream of the current branch, from its tracking config, if one is configured.
+	fn upstream(&mut self) -> anyhow::Result<Option<Upstream>>;
+	/// Returns the most recent tag reachable from HEAD matching the glob `pattern`,
+	/// ignoring the tags in `exclude`.
+	fn last_tag(&mut self, pattern: &str, exclude: &[String]) -> anyhow::Result<Option<String>>;
+	/// Returns all tags matching the glob `pattern`, reachable or not.
+	fn tag_names(&mut self, pattern: &str) -> anyhow::Result<Vec<String>>;
+	/// Returns hash and message of every commit after `since` up to HEAD, newest first.
+	fn commit_messages(&mut self, since: Option<&str>) -> anyhow::Result<Vec<(String, String)>>;
+	/// Resets the current branch to `commit`, keeping the working tree.
+	fn reset(&mut self, commit: &str) -> anyhow::Result<()>;
+	/// Resets the current branch, the index, and the working tree to `commit`, e.g. `v1.0.0^{commit}~1`.
+	fn reset_hard(&mut self, commit: &str) -> anyhow::Result<()>;
+	/// Aborts a rebase, if one is in progress.
+	fn abort_rebase(&mut self) -> anyhow::Result<()>;
+	/// Fetches the branch of `upstream` into its remote tracking branch.
+	fn fetch(&mut self, upstream: &Upstream) -> anyhow::Result<usize>;
+	/// Rebases the current branch onto the remote tracking branch of `upstream`,
+	/// the rewritten commits are signed again if `signing` is given.
+	fn rebase(&mut self, upstream: &Upstream, signing: Option<&Signing>) -> anyhow::Result<()>;
+	/// Pushes the current branch to the branch of `upstream`.
+	fn push(&mut self, upstream: &Upstream) -> anyhow::Result<usize>;
+	fn push_tag(&mut self, remote: &str, tag: &str) -> anyhow::Result<usize>;
+	/// Takes command, and output of everything that printed something since the last call.
+	fn take_output(&mut self) -> Vec<(String, String)> {
+		Vec::new()
+	}
+}
+
+/// A branch on a remote, e.g. `main` on `origin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+	pub remote: String,
+	pub branch: String,
+}
+
+impl Upstream {
+	pub fn new(remote: &str, branch: &str) -> Self {
+		Self {
+			remote: remote.to_string(),
+			branch: branch.to_string(),
+		}
+	}
+
+	/// e.g. `refs/heads/main`
+	pub fn remote_ref(&self) -> String {
+		format!("refs/heads/{}", self.branch)
+	}
+
+	/// e.g. `refs/remotes/origin/main`
+	pub fn tracking_ref(&self) -> String {
+		format!("refs/remotes/{}/{}", self.remote, self.branch)
+	}
+
+	/// Fetches the branch into its remote tracking branch, independent of the configured refspecs.
+	pub fn fetch_refspec(&self) -> String {
+		format!("+{}:{}", self.remote_ref(), self.tracking_ref())
+	}
+}
+
+impl fmt::Display for Upstream {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}/{}", self.remote, self.branch)
+	}
+}
+
+impl fmt::Debug for dyn VcsBackend {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "VcsBackend")
+	}
+}
+
+/// The available `VcsBackend` implementations.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Vcs {
+	/// Runs the `git` binary.
+	#[default]
+	GitCli,
+	/// libgit2, via the `git2` crate.
+	Git2,
+	/// gitoxide, via the `gix` crate.
+	Gix,
+}
+
+impl Vcs {
+	pub fn open(&self, path: &Path) -> anyhow::Result<Box<dyn VcsBackend>> {
+		self.open_with_status(path, StatusSink::default())
+	}
+
+	/// Like `open`, the backend prints its status to `status`.
+	pub(crate) fn open_with_status(
+		&self,
+		path: &Path,
+		status: StatusSink,
+	) -> anyhow::Result<Box<dyn VcsBackend>> {
+		let backend: Box<dyn VcsBackend> = match self {
+			Vcs::GitCli => Box::new(crate::repository_cmdgit::Repository::new(path)),
+			Vcs::Git2 => {
+				let mut repo = crate::repository::Repository::new(path, status);
+				repo.open()?;
+				Box::new(repo)
+			},
+			Vcs::Gix => {
+				let mut repo = crate::repository_gix::Repository::new(path, status);
+				repo.open()?;
+				Box::new(repo)
+			},
+		};
+		Ok(backend)
+	}
+}
+
+impl fmt::Display for Vcs {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Vcs::GitCli => write!(f, "git-cli"),
+			Vcs::Git2 => write!(f, "git2"),
+			Vcs::Gix => write!(f, "gix"),
+		}
+	}
+}
+
+impl FromStr for Vcs {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		match s {
+			"git-cli" => Ok(Vcs::GitCli),
+			"git2" => Ok(Vcs::Git2),
+			"gix" => Ok(Vcs::Gix),
+			o => bail!("Unknown vcs {:?}, should be git-cli/git2/gix", o),
+		}
+	}
+}
+
+/// Matches `name` against a glob with `*` wildcards, as used for tag patterns.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+	match pattern.split_once('*') {
+		None => pattern == name,
+		Some((prefix, rest)) => {
+			let Some(name) = name.strip_prefix(prefix) else {
+				return false;
+			};
+			if rest.is_empty() {
+				return true;
+			}
+			(0..=name.len())
+				.filter(|i| name.is_char_boundary(*i))
+				.any(|i| glob_match(rest, &name[i..]))
+		},
+	}
+}
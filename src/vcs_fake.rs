@@ -0,0 +1,515 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::bail;
+
+use crate::git_status::{BranchStatus, FileState, GitStatus, StatusEntry};
+use crate::signing::Signing;
+use crate::vcs::{glob_match, Upstream, VcsBackend};
+
+/// The only branch on the only remote.
+const REMOTE: &str = "origin";
+const REMOTE_BRANCH: &str = "main";
+
+/// A call to the backend, as recorded by `FakeVcs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+	Status,
+	Commit {
+		files:   Vec<String>,
+		message: String,
+	},
+	CheckIgnore(String),
+	Tag(String),
+	VerifyTag(String),
+	DeleteTag(String),
+	Head,
+	GitDir,
+	Upstream,
+	LastTag(String),
+	TagNames(String),
+	CommitMessages(Option<String>),
+	Reset(String),
+	ResetHard(String),
+	AbortRebase,
+	/// e.g. `origin/main`
+	Fetch(String),
+	Rebase(String),
+	Push(String),
+	/// Remote, and tag.
+	PushTag(String, String),
+}
+
+impl Call {
+	/// Calls that change the repository, or the remote.
+	pub fn is_write(&self) -> bool {
+		matches!(
+			self,
+			Call::Commit { .. }
+				| Call::Tag(_)
+				| Call::DeleteTag(_)
+				| Call::Reset(_)
+				| Call::ResetHard(_)
+				| Call::AbortRebase
+				| Call::Rebase(_)
+				| Call::Push(_)
+				| Call::PushTag(..)
+		)
+	}
+
+	/// The name of the `VcsBackend` method, e.g. `push_tag`.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Call::Status => "status",
+			Call::Commit { .. } => "commit",
+			Call::CheckIgnore(_) => "check_ignore",
+			Call::Tag(_) => "tag",
+			Call::VerifyTag(_) => "verify_tag",
+			Call::DeleteTag(_) => "delete_tag",
+			Call::Head => "head",
+			Call::GitDir => "git_dir",
+			Call::Upstream => "upstream",
+			Call::LastTag(_) => "last_tag",
+			Call::TagNames(_) => "tag_names",
+			Call::CommitMessages(_) => "commit_messages",
+			Call::Reset(_) => "reset",
+			Call::ResetHard(_) => "reset_hard",
+			Call::AbortRebase => "abort_rebase",
+			Call::Fetch(_) => "fetch",
+			Call::Rebase(_) => "rebase",
+			Call::Push(_) => "push",
+			Call::PushTag(..) => "push_tag",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeCommit {
+	pub id:      String,
+	pub message: String,
+	pub files:   Vec<String>,
+	pub signed:  bool,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+	git_dir:     PathBuf,
+	entries:     Vec<StatusEntry>,
+	ignored:     Vec<String>,
+	/// `None` for a detached HEAD.
+	branch:      Option<String>,
+	upstream:    Option<String>,
+	/// Oldest first.
+	local:       Vec<FakeCommit>,
+	remote:      Vec<FakeCommit>,
+	fetched:     Vec<FakeCommit>,
+	tags:        Vec<(String, String)>,
+	signed_tags: Vec<String>,
+	remote_tags: Vec<(String, String)>,
+	next_id:     usize,
+	failures:    Vec<String>,
+	/// Fails the call with this index in `calls`.
+	fail_call:   Option<usize>,
+	calls:       Vec<Call>,
+}
+
+impl FakeState {
+	fn check_upstream(upstream: &Upstream) -> anyhow::Result<()> {
+		if upstream.remote != REMOTE {
+			bail!(
+				"'{}' does not appear to be a git repository",
+				upstream.remote
+			);
+		}
+		if upstream.branch != REMOTE_BRANCH {
+			bail!("couldn't find remote ref {}", upstream.remote_ref());
+		}
+		Ok(())
+	}
+
+	fn new_commit(&mut self, message: &str, files: &[String]) -> FakeCommit {
+		self.next_id += 1;
+		FakeCommit {
+			id:      format!("{:040x}", self.next_id),
+			message: message.to_string(),
+			files:   files.to_vec(),
+			signed:  false,
+		}
+	}
+
+	fn head(&self) -> anyhow::Result<&FakeCommit> {
+		match self.local.last() {
+			Some(head) => Ok(head),
+			None => bail!("No commits"),
+		}
+	}
+
+	/// Resolves a commit id, or a tag.
+	fn resolve(&self, rev: &str) -> anyhow::Result<usize> {
+		// `rev^{commit}` is `rev`, and `rev~n` its n-th ancestor
+		if let Some(rev) = rev.strip_suffix("^{commit}") {
+			return self.resolve(rev);
+		}
+		if let Some((rev, n)) = rev.rsplit_once('~') {
+			let p = self.resolve(rev)?;
+			return match n.parse::<usize>().ok().and_then(|n| p.checked_sub(n)) {
+				Some(p) => Ok(p),
+				None => bail!("Unknown revision {}~{}", rev, n),
+			};
+		}
+		let id = match self.tags.iter().find(|(t, _)| t == rev) {
+			Some((_, id)) => id.as_str(),
+			None => rev,
+		};
+		match self.local.iter().position(|c| c.id == id) {
+			Some(p) => Ok(p),
+			None => bail!("Unknown revision {}", rev),
+		}
+	}
+}
+
+/// An in-memory `VcsBackend` that records every call.
+///
+/// Clones share the same repository, keep one to inspect it after `Release::run`.
+#[derive(Debug, Clone, Default)]
+pub struct FakeVcs {
+	state: Rc<RefCell<FakeState>>,
+}
+
+impl FakeVcs {
+	/// A repository with one commit, that is in sync with `origin/main`.
+	pub fn new(git_dir: &Path) -> Self {
+		let fake = Self::default();
+		{
+			let mut s = fake.state.borrow_mut();
+			s.git_dir = git_dir.to_owned();
+			s.branch = Some("main".to_string());
+			s.upstream = Some("origin/main".to_string());
+			let initial = s.new_commit("init", &[]);
+			s.local.push(initial);
+			s.remote = s.local.clone();
+			s.fetched = s.local.clone();
+		}
+		fake
+	}
+
+	/// Modifies `files` in the worktree.
+	pub fn set_dirty(&self, files: &[&str]) {
+		self.state.borrow_mut().entries = files
+			.iter()
+			.map(|f| StatusEntry::tracked(f, FileState::Unmodified, FileState::Modified))
+			.collect();
+	}
+
+	pub fn add_status_entry(&self, entry: StatusEntry) {
+		self.state.borrow_mut().entries.push(entry);
+	}
+
+	pub fn set_ignored(&self, files: &[&str]) {
+		self.state.borrow_mut().ignored = files.iter().map(|f| f.to_string()).collect();
+	}
+
+	pub fn set_branch(&self, branch: Option<&str>) {
+		self.state.borrow_mut().branch = branch.map(|b| b.to_string());
+	}
+
+	pub fn set_upstream(&self, upstream: Option<&str>) {
+		self.state.borrow_mut().upstream = upstream.map(|u| u.to_string());
+	}
+
+	/// Adds a local commit.
+	pub fn add_commit(&self, message: &str) {
+		let mut s = self.state.borrow_mut();
+		let commit = s.new_commit(message, &[]);
+		s.local.push(commit);
+	}
+
+	/// Adds a commit to the remote only, the next push fails unless it is fetched, and rebased first.
+	pub fn diverge(&self, message: &str) {
+		let mut s = self.state.borrow_mut();
+		let commit = s.new_commit(message, &[]);
+		s.remote.push(commit);
+	}
+
+	/// Fetches the remote before the release starts, e.g. to be behind after `diverge`.
+	pub fn fetch_remote(&self) {
+		let mut s = self.state.borrow_mut();
+		s.fetched = s.remote.clone();
+	}
+
+	pub fn add_tag(&self, tag: &str) {
+		let mut s = self.state.borrow_mut();
+		let head = s.local.last().map(|c| c.id.clone()).unwrap_or_default();
+		s.tags.push((tag.to_string(), head));
+	}
+
+	/// Makes the next call to `method`, e.g. `push`, fail.
+	pub fn fail_on(&self, method: &str) {
+		self.state.borrow_mut().failures.push(method.to_string());
+	}
+
+	/// Makes the call with the index `index` in `calls` fail, whatever it is.
+	pub fn fail_on_call(&self, index: usize) {
+		self.state.borrow_mut().fail_call = Some(index);
+	}
+
+	pub fn calls(&self) -> Vec<Call> {
+		self.state.borrow().calls.clone()
+	}
+
+	/// The calls that changed the repository, or the remote.
+	pub fn writes(&self) -> Vec<Call> {
+		self.calls().into_iter().filter(Call::is_write).collect()
+	}
+
+	/// Local commits, oldest first.
+	pub fn commits(&self) -> Vec<FakeCommit> {
+		self.state.borrow().local.clone()
+	}
+
+	pub fn remote_commits(&self) -> Vec<FakeCommit> {
+		self.state.borrow().remote.clone()
+	}
+
+	pub fn tags(&self) -> Vec<String> {
+		self.state
+			.borrow()
+			.tags
+			.iter()
+			.map(|(t, _)| t.clone())
+			.collect()
+	}
+
+	pub fn remote_tags(&self) -> Vec<String> {
+		self.state
+			.borrow()
+			.remote_tags
+			.iter()
+			.map(|(t, _)| t.clone())
+			.collect()
+	}
+
+	/// Records `call`, and fails if a failure was injected for it.
+	fn record(&self, call: Call) -> anyhow::Result<std::cell::RefMut<'_, FakeState>> {
+		let mut s = self.state.borrow_mut();
+		let name = call.name();
+		s.calls.push(call);
+		if let Some(p) = s.failures.iter().position(|f| f == name) {
+			s.failures.remove(p);
+			bail!("injected failure in {}", name);
+		}
+		if s.fail_call == Some(s.calls.len() - 1) {
+			bail!("injected failure in {}", name);
+		}
+		Ok(s)
+	}
+}
+
+impl VcsBackend for FakeVcs {
+	fn status(&mut self) -> anyhow::Result<GitStatus> {
+		let s = self.record(Call::Status)?;
+		let base = s
+			.local
+			.iter()
+			.zip(s.fetched.iter())
+			.take_while(|(l, f)| l == f)
+			.count();
+		Ok(GitStatus {
+			branch:  BranchStatus {
+				oid:      s.local.last().map(|c| c.id.clone()),
+				head:     s.branch.clone(),
+				upstream: s.upstream.clone(),
+				ahead:    s.local.len() - base,
+				behind:   s.fetched.len() - base,
+			},
+			entries: s.entries.clone(),
+		})
+	}
+
+	fn commit(
+		&mut self,
+		files: &[String],
+		message: &str,
+		signing: Option<&Signing>,
+	) -> anyhow::Result<()> {
+		let mut s = self.record(Call::Commit {
+			files:   files.to_vec(),
+			message: message.to_string(),
+		})?;
+		let mut commit = s.new_commit(message, files);
+		commit.signed = signing.is_some();
+		s.local.push(commit);
+		s.entries.retain(|e| !files.contains(&e.path));
+		Ok(())
+	}
+
+	fn check_ignore(&mut self, file: &str) -> anyhow::Result<bool> {
+		let s = self.record(Call::CheckIgnore(file.to_string()))?;
+		Ok(s.ignored.iter().any(|i| i == file))
+	}
+
+	fn tag(&mut self, tag: &str, _msg: &str, signing: Option<&Signing>) -> anyhow::Result<()> {
+		let mut s = self.record(Call::Tag(tag.to_string()))?;
+		if s.tags.iter().any(|(t, _)| t == tag) {
+			bail!("tag '{}' already exists", tag);
+		}
+		let head = s.head()?.id.clone();
+		s.tags.push((tag.to_string(), head));
+		if signing.is_some() {
+			s.signed_tags.push(tag.to_string());
+		}
+		Ok(())
+	}
+
+	fn verify_tag(&mut self, tag: &str, _signing: &Signing) -> anyhow::Result<()> {
+		let s = self.record(Call::VerifyTag(tag.to_string()))?;
+		if !s.signed_tags.iter().any(|t| t == tag) {
+			bail!("Tag {} is not signed", tag);
+		}
+		Ok(())
+	}
+
+	fn delete_tag(&mut self, tag: &str) -> anyhow::Result<()> {
+		let mut s = self.record(Call::DeleteTag(tag.to_string()))?;
+		let Some(p) = s.tags.iter().position(|(t, _)| t == tag) else {
+			bail!("tag '{}' not found", tag);
+		};
+		s.tags.remove(p);
+		s.signed_tags.retain(|t| t != tag);
+		Ok(())
+	}
+
+	fn head(&mut self) -> anyhow::Result<String> {
+		let s = self.record(Call::Head)?;
+		Ok(s.head()?.id.clone())
+	}
+
+	fn git_dir(&mut self) -> anyhow::Result<PathBuf> {
+		let s = self.record(Call::GitDir)?;
+		Ok(s.git_dir.clone())
+	}
+
+	fn upstream(&mut self) -> anyhow::Result<Option<Upstream>> {
+		let s = self.record(Call::Upstream)?;
+		let upstream = s.upstream.as_ref().and_then(|u| u.split_once('/'));
+		Ok(upstream.map(|(remote, branch)| Upstream::new(remote, branch)))
+	}
+
+	fn tag_names(&mut self, pattern: &str) -> anyhow::Result<Vec<String>> {
+		let s = self.record(Call::TagNames(pattern.to_string()))?;
+		Ok(s.tags
+			.iter()
+			.map(|(t, _)| t.clone())
+			.filter(|t| glob_match(pattern, t))
+			.collect())
+	}
+
+	fn last_tag(&mut self, pattern: &str, exclude: &[String]) -> anyhow::Result<Option<String>> {
+		let s = self.record(Call::LastTag(pattern.to_string()))?;
+		for c in s.local.iter().rev() {
+			let tag = s
+				.tags
+				.iter()
+				.find(|(t, id)| *id == c.id && glob_match(pattern, t) && !exclude.contains(t));
+			if let Some((t, _)) = tag {
+				return Ok(Some(t.clone()));
+			}
+		}
+		Ok(None)
+	}
+
+	fn commit_messages(&mut self, since: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+		let s = self.record(Call::CommitMessages(since.map(|s| s.to_string())))?;
+		let first = match since {
+			Some(since) => s.resolve(since)? + 1,
+			None => 0,
+		};
+		Ok(s.local[first..]
+			.iter()
+			.rev()
+			.map(|c| (c.id.clone(), c.message.clone()))
+			.collect())
+	}
+
+	fn reset(&mut self, commit: &str) -> anyhow::Result<()> {
+		let mut s = self.record(Call::Reset(commit.to_string()))?;
+		let p = s.resolve(commit)?;
+		s.local.truncate(p + 1);
+		Ok(())
+	}
+
+	fn reset_hard(&mut self, commit: &str) -> anyhow::Result<()> {
+		let mut s = self.record(Call::ResetHard(commit.to_string()))?;
+		let p = s.resolve(commit)?;
+		s.local.truncate(p + 1);
+		Ok(())
+	}
+
+	fn abort_rebase(&mut self) -> anyhow::Result<()> {
+		self.record(Call::AbortRebase)?;
+		Ok(())
+	}
+
+	fn fetch(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		let mut s = self.record(Call::Fetch(upstream.to_string()))?;
+		FakeState::check_upstream(upstream)?;
+		s.fetched = s.remote.clone();
+		Ok(0)
+	}
+
+	fn rebase(&mut self, upstream: &Upstream, signing: Option<&Signing>) -> anyhow::Result<()> {
+		let mut s = self.record(Call::Rebase(upstream.to_string()))?;
+		FakeState::check_upstream(upstream)?;
+		let base = s
+			.local
+			.iter()
+			.zip(s.fetched.iter())
+			.take_while(|(l, f)| l == f)
+			.count();
+		if base == s.fetched.len() {
+			// up to date, nothing is rewritten
+			return Ok(());
+		}
+		let mut rebased = s.fetched.clone();
+		let local: Vec<FakeCommit> = s.local.drain(base..).collect();
+		for commit in local {
+			// rewritten commits are only signed if asked to, like with git
+			let mut commit = FakeCommit {
+				signed: signing.is_some(),
+				..commit
+			};
+			s.next_id += 1;
+			commit.id = format!("{:040x}", s.next_id);
+			rebased.push(commit);
+		}
+		s.local = rebased;
+		Ok(())
+	}
+
+	fn push(&mut self, upstream: &Upstream) -> anyhow::Result<usize> {
+		let mut s = self.record(Call::Push(upstream.to_string()))?;
+		FakeState::check_upstream(upstream)?;
+		if !s.local.starts_with(&s.remote) {
+			bail!("Updates were rejected because the remote contains work that you do not have locally");
+		}
+		s.remote = s.local.clone();
+		s.fetched = s.local.clone();
+		Ok(0)
+	}
+
+	fn push_tag(&mut self, remote: &str, tag: &str) -> anyhow::Result<usize> {
+		let mut s = self.record(Call::PushTag(remote.to_string(), tag.to_string()))?;
+		if remote != REMOTE {
+			bail!("'{}' does not appear to be a git repository", remote);
+		}
+		let Some(t) = s.tags.iter().find(|(t, _)| t == tag).cloned() else {
+			bail!("src refspec {} does not match any", tag);
+		};
+		// like git, pushing the same tag again is up to date
+		if !s.remote_tags.contains(&t) {
+			s.remote_tags.push(t);
+		}
+		Ok(0)
+	}
+}
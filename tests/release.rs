@@ -0,0 +1,621 @@
+//! End to end releases against a temporary working repository, and a local bare `origin`.
+//!
+//! Every scenario runs with each `--vcs` backend.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use omr_bumper::{Command as ReleaseCommand, Release};
+
+const BACKENDS: [&str; 3] = ["git-cli", "git2", "gix"];
+
+const MANIFEST: &str = r#"[package]
+name = "test-project"
+version = "0.1.0-dev"
+edition = "2021"
+"#;
+
+const LOCKFILE: &str = r#"# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "test-project"
+version = "0.1.0-dev"
+"#;
+
+struct TestRepo {
+	// removed on drop
+	_dir:   tempfile::TempDir,
+	work:   PathBuf,
+	remote: PathBuf,
+}
+
+impl TestRepo {
+	/// A project at `0.1.0-dev`, pushed to `origin/main`, with `extra` files committed too.
+	fn new(extra: &[(&str, &str)]) -> Self {
+		let dir = tempfile::tempdir().unwrap();
+		let work = dir.path().join("work");
+		let remote = dir.path().join("remote.git");
+		git(
+			dir.path(),
+			&["init", "-q", "--bare", "-b", "main", "remote.git"],
+		);
+		git(dir.path(), &["init", "-q", "-b", "main", "work"]);
+		configure(&work);
+
+		std::fs::create_dir(work.join("src")).unwrap();
+		write(&work, "src/main.rs", "fn main() {}\n");
+		write(&work, "Cargo.toml", MANIFEST);
+		write(&work, "Cargo.lock", LOCKFILE);
+		for (path, contents) in extra {
+			write(&work, path, contents);
+		}
+		git(&work, &["add", "."]);
+		git(&work, &["commit", "-q", "-m", "Initial commit"]);
+		git(
+			&work,
+			&["remote", "add", "origin", remote.to_str().unwrap()],
+		);
+		git(&work, &["push", "-q", "-u", "origin", "main"]);
+
+		Self {
+			_dir: dir,
+			work,
+			remote,
+		}
+	}
+
+	fn release(&self, vcs: &str) -> anyhow::Result<()> {
+		self.release_with(vcs, |_| {})
+	}
+
+	/// Like `release`, with additional settings.
+	fn release_with(&self, vcs: &str, settings: impl FnOnce(&mut Release)) -> anyhow::Result<()> {
+		let mut release = Release::new();
+		release.set_pre_release_suffix("alpha").unwrap();
+		release.set_bump_level("patch")?;
+		release.set_path(self.work.to_str().unwrap());
+		release.set_vcs(vcs)?;
+		release.load_config()?;
+		settings(&mut release);
+		release.run()
+	}
+
+	/// A second clone of `origin`, e.g. to push from somewhere else.
+	fn clone(&self, name: &str) -> PathBuf {
+		let parent = self.remote.parent().unwrap();
+		git(
+			parent,
+			&["clone", "-q", self.remote.to_str().unwrap(), name],
+		);
+		let clone = parent.join(name);
+		configure(&clone);
+		clone
+	}
+
+	fn remote_git(&self, args: &[&str]) -> String {
+		git(&self.remote, args)
+	}
+
+	fn remote_tags(&self) -> Vec<String> {
+		lines(&self.remote_git(&["tag", "-l"]))
+	}
+
+	/// Subjects of the commits on `origin/main`, newest first.
+	fn remote_log(&self) -> Vec<String> {
+		lines(&self.remote_git(&["log", "--format=%s", "main"]))
+	}
+
+	fn remote_file(&self, rev: &str, path: &str) -> String {
+		self.remote_git(&["show", &format!("{}:{}", rev, path)])
+	}
+
+	fn remote_files(&self, rev: &str) -> Vec<String> {
+		lines(&self.remote_git(&["ls-tree", "-r", "--name-only", rev]))
+	}
+
+	/// Checks `origin` after a successful release of `0.1.0-alpha`.
+	fn assert_released(&self, vcs: &str) {
+		assert_eq!(self.remote_tags(), ["0.1.0-alpha"], "{}", vcs);
+		let log = self.remote_log();
+		assert_eq!(
+			log[..2],
+			[
+				": Bump version back to dev release, and bump patch level - 0.1.1-dev",
+				": Bump version for alpha release - 0.1.0-alpha",
+			],
+			"{}",
+			vcs
+		);
+		assert!(
+			self.remote_file("0.1.0-alpha", "Cargo.toml")
+				.contains(r#"version = "0.1.0-alpha""#),
+			"{}",
+			vcs
+		);
+		assert!(
+			self.remote_file("main", "Cargo.toml")
+				.contains(r#"version = "0.1.1-dev""#),
+			"{}",
+			vcs
+		);
+		// the tag points to the release commit
+		assert_eq!(
+			self.remote_git(&["rev-parse", "0.1.0-alpha^{commit}"]),
+			self.remote_git(&["rev-parse", "main~1"]),
+			"{}",
+			vcs
+		);
+		assert_eq!(
+			git(&self.work, &["status", "--porcelain", "-uno"]),
+			"",
+			"{}",
+			vcs
+		);
+	}
+
+	/// Checks nothing reached `origin`, and the working copy is back where it started.
+	fn assert_untouched(&self, vcs: &str, remote_head: &str) {
+		assert_eq!(self.remote_tags(), Vec::<String>::new(), "{}", vcs);
+		assert_eq!(
+			self.remote_git(&["rev-parse", "main"]),
+			remote_head,
+			"{}",
+			vcs
+		);
+		assert_eq!(read(&self.work, "Cargo.toml"), MANIFEST, "{}", vcs);
+		assert_eq!(git(&self.work, &["tag", "-l"]), "", "{}", vcs);
+	}
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(dir)
+		.args(args)
+		.output()
+		.unwrap();
+	assert!(
+		output.status.success(),
+		"git {} failed: {}",
+		args.join(" "),
+		String::from_utf8_lossy(&output.stderr)
+	);
+	String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+fn configure(dir: &Path) {
+	git(dir, &["config", "user.name", "omr-bumper test"]);
+	git(dir, &["config", "user.email", "test@example.com"]);
+	git(dir, &["config", "commit.gpgsign", "false"]);
+	git(dir, &["config", "tag.gpgsign", "false"]);
+}
+
+fn write(dir: &Path, path: &str, contents: &str) {
+	std::fs::write(dir.join(path), contents).unwrap();
+}
+
+fn read(dir: &Path, path: &str) -> String {
+	std::fs::read_to_string(dir.join(path)).unwrap()
+}
+
+fn lines(s: &str) -> Vec<String> {
+	s.lines().map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn simple() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		repo.release(vcs).unwrap();
+
+		repo.assert_released(vcs);
+		assert_eq!(
+			repo.remote_files("main"),
+			["Cargo.lock", "Cargo.toml", "src/main.rs"],
+			"{}",
+			vcs
+		);
+		assert!(
+			repo.remote_file("main", "Cargo.lock")
+				.contains(r#"version = "0.1.1-dev""#),
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn untracked_files() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		write(&repo.work, "untracked.md", "# This is an untracked file\n");
+		std::fs::create_dir(repo.work.join("untracked_dir")).unwrap();
+		write(
+			&repo.work,
+			"untracked_dir/file.txt",
+			"# File in untracked directory\n",
+		);
+		repo.release(vcs).unwrap();
+
+		repo.assert_released(vcs);
+		assert!(repo.work.join("untracked.md").exists(), "{}", vcs);
+		assert!(repo.work.join("untracked_dir/file.txt").exists(), "{}", vcs);
+		assert_eq!(
+			repo.remote_files("main"),
+			["Cargo.lock", "Cargo.toml", "src/main.rs"],
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn ignored_cargo_lock() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[(".gitignore", "Cargo.lock\n")]);
+		repo.release(vcs).unwrap();
+
+		repo.assert_released(vcs);
+		assert_eq!(
+			repo.remote_files("main"),
+			[".gitignore", "Cargo.toml", "src/main.rs"],
+			"{}",
+			vcs
+		);
+		assert!(read(&repo.work, "Cargo.lock").contains(r#"version = "0.1.1-dev""#));
+	}
+}
+
+#[test]
+fn staged_changes() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		let remote_head = repo.remote_git(&["rev-parse", "main"]);
+		write(
+			&repo.work,
+			"src/main.rs",
+			"fn main() { println!(\"staged\"); }\n",
+		);
+		write(&repo.work, "src/lib.rs", "// new, and staged\n");
+		git(&repo.work, &["add", "src"]);
+		// a dirty repository stops the release without an error
+		repo.release(vcs).unwrap();
+
+		repo.assert_untouched(vcs, &remote_head);
+		assert_eq!(
+			git(&repo.work, &["status", "--porcelain"]),
+			"A  src/lib.rs\nM  src/main.rs",
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn diverged_remote() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		let other = repo.clone("other");
+		write(&other, "README.md", "pushed from elsewhere\n");
+		git(&other, &["add", "README.md"]);
+		git(&other, &["commit", "-q", "-m", "Change from elsewhere"]);
+		git(&other, &["push", "-q"]);
+		repo.release(vcs).unwrap();
+
+		repo.assert_released(vcs);
+		assert_eq!(
+			repo.remote_log()[2..],
+			["Change from elsewhere", "Initial commit"],
+			"{}",
+			vcs
+		);
+		assert!(
+			repo.remote_files("main").contains(&"README.md".to_string()),
+			"{}",
+			vcs
+		);
+		// linear history, nothing was merged
+		assert_eq!(
+			repo.remote_git(&["rev-list", "--merges", "main"]),
+			"",
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn detached_head() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		git(&repo.work, &["checkout", "-q", "--detach"]);
+		let remote_head = repo.remote_git(&["rev-parse", "main"]);
+		let head = git(&repo.work, &["rev-parse", "HEAD"]);
+		assert!(repo.release(vcs).is_err(), "{}", vcs);
+
+		repo.assert_untouched(vcs, &remote_head);
+		assert_eq!(git(&repo.work, &["rev-parse", "HEAD"]), head, "{}", vcs);
+	}
+}
+
+#[test]
+fn feature_branch() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		git(&repo.work, &["checkout", "-q", "-b", "feature"]);
+		let remote_head = repo.remote_git(&["rev-parse", "main"]);
+		let err = repo.release(vcs).unwrap_err();
+
+		assert!(
+			err.to_string()
+				.starts_with("Branch feature is not allowed for releases"),
+			"{} {}",
+			vcs,
+			err
+		);
+		repo.assert_untouched(vcs, &remote_head);
+	}
+}
+
+#[test]
+fn behind_origin() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		let other = repo.clone("other");
+		write(&other, "README.md", "pushed from elsewhere\n");
+		git(&other, &["add", "README.md"]);
+		git(&other, &["commit", "-q", "-m", "Change from elsewhere"]);
+		git(&other, &["push", "-q"]);
+		git(&repo.work, &["fetch", "-q"]);
+		let remote_head = repo.remote_git(&["rev-parse", "main"]);
+		let err = repo.release(vcs).unwrap_err();
+
+		assert_eq!(
+			err.to_string(),
+			"Branch main is 1 commit(s) behind origin/main. Pull first, or use --allow-behind",
+			"{}",
+			vcs
+		);
+		repo.assert_untouched(vcs, &remote_head);
+	}
+}
+
+#[test]
+fn configured_remote_and_target_branch() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		git(&repo.work, &["push", "-q", "origin", "main:release"]);
+		git(&repo.work, &["branch", "-q", "--unset-upstream"]);
+		git(&repo.work, &["remote", "rename", "origin", "upstream"]);
+		// untracked, so not dirty
+		write(
+			&repo.work,
+			"omr-bumper.toml",
+			"remote = \"upstream\"\ntarget_branch = \"release\"\n",
+		);
+		let main = repo.remote_git(&["rev-parse", "main"]);
+		repo.release(vcs).unwrap();
+
+		assert_eq!(repo.remote_git(&["rev-parse", "main"]), main, "{}", vcs);
+		assert_eq!(repo.remote_tags(), ["0.1.0-alpha"], "{}", vcs);
+		assert_eq!(
+			repo.remote_git(&["rev-parse", "0.1.0-alpha^{commit}"]),
+			repo.remote_git(&["rev-parse", "release~1"]),
+			"{}",
+			vcs
+		);
+		assert!(
+			repo.remote_file("release", "Cargo.toml")
+				.contains(r#"version = "0.1.1-dev""#),
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn pre_release_counter() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		// an earlier alpha, which never made it to `main`
+		git(&repo.work, &["tag", "-a", "0.1.0-alpha.1", "-m", "earlier"]);
+		git(&repo.work, &["push", "-q", "origin", "0.1.0-alpha.1"]);
+		repo.release_with(vcs, |r| r.set_pre_release_counter(true))
+			.unwrap();
+
+		assert_eq!(
+			repo.remote_tags(),
+			["0.1.0-alpha.1", "0.1.0-alpha.2"],
+			"{}",
+			vcs
+		);
+		assert_eq!(
+			repo.remote_log()[1],
+			": Bump version for alpha release - 0.1.0-alpha.2",
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn undo_release() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		let initial = git(&repo.work, &["rev-parse", "HEAD"]);
+		repo.release(vcs).unwrap();
+		repo.release_with(vcs, |r| r.set_command(ReleaseCommand::Undo))
+			.unwrap();
+
+		assert_eq!(git(&repo.work, &["rev-parse", "HEAD"]), initial, "{}", vcs);
+		assert_eq!(git(&repo.work, &["tag", "-l"]), "", "{}", vcs);
+		assert_eq!(read(&repo.work, "Cargo.toml"), MANIFEST, "{}", vcs);
+		assert_eq!(read(&repo.work, "Cargo.lock"), LOCKFILE, "{}", vcs);
+		// only undone locally
+		repo.assert_released(vcs);
+
+		// nothing left to undo
+		let err = repo
+			.release_with(vcs, |r| r.set_command(ReleaseCommand::Undo))
+			.unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"No release tag matches *, nothing to undo",
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn bump_and_tag() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		let initial = git(&repo.work, &["rev-parse", "HEAD"]);
+		repo.release_with(vcs, |r| {
+			r.set_command(ReleaseCommand::Bump);
+			r.set_bump_level("minor").unwrap();
+		})
+		.unwrap();
+
+		assert_eq!(git(&repo.work, &["rev-parse", "HEAD"]), initial, "{}", vcs);
+		assert!(read(&repo.work, "Cargo.toml").contains(r#"version = "0.2.0-dev""#));
+		assert!(read(&repo.work, "Cargo.lock").contains(r#"version = "0.2.0-dev""#));
+
+		git(&repo.work, &["commit", "-q", "-am", "Start 0.2.0"]);
+		git(&repo.work, &["push", "-q"]);
+		repo.release_with(vcs, |r| r.set_command(ReleaseCommand::Tag))
+			.unwrap();
+		assert_eq!(repo.remote_tags(), ["0.2.0-dev"], "{}", vcs);
+		assert_eq!(
+			repo.remote_git(&["rev-parse", "0.2.0-dev^{commit}"]),
+			repo.remote_git(&["rev-parse", "main"]),
+			"{}",
+			vcs
+		);
+	}
+}
+
+#[test]
+fn ssh_signed() {
+	for (vcs, diverged) in BACKENDS
+		.iter()
+		.flat_map(|vcs| [(*vcs, false), (*vcs, true)])
+	{
+		let repo = TestRepo::new(&[]);
+		if diverged {
+			// the release commits are rewritten by the rebase
+			let other = repo.clone("other");
+			write(&other, "README.md", "pushed from elsewhere\n");
+			git(&other, &["add", "README.md"]);
+			git(&other, &["commit", "-q", "-m", "Change from elsewhere"]);
+			git(&other, &["push", "-q"]);
+		}
+		let key = repo.remote.parent().unwrap().join("id_ed25519");
+		let output = Command::new("ssh-keygen")
+			.args([
+				"-q",
+				"-t",
+				"ed25519",
+				"-N",
+				"",
+				"-C",
+				"test@example.com",
+				"-f",
+			])
+			.arg(&key)
+			.output()
+			.unwrap();
+		assert!(output.status.success());
+		let key = key.to_str().unwrap().to_string();
+		repo.release_with(vcs, |r| {
+			r.set_sign(true);
+			r.set_signing_format("ssh").unwrap();
+			r.set_signing_key(&key);
+		})
+		.unwrap();
+
+		repo.assert_released(vcs);
+		assert_eq!(
+			repo.remote_log()[2] == "Change from elsewhere",
+			diverged,
+			"{}",
+			vcs
+		);
+		// check with git itself
+		let public = std::fs::read_to_string(format!("{}.pub", key)).unwrap();
+		let signers = repo.remote.parent().unwrap().join("allowed_signers");
+		std::fs::write(&signers, format!("test@example.com {}", public)).unwrap();
+		let signers = format!("gpg.ssh.allowedSignersFile={}", signers.display());
+		git(&repo.work, &["fetch", "-q", "--tags"]);
+		for verify in [
+			["verify-tag", "0.1.0-alpha"],
+			["verify-commit", "origin/main"],
+			["verify-commit", "origin/main~1"],
+		] {
+			git(&repo.work, &["-c", &signers, verify[0], verify[1]]);
+		}
+	}
+}
+
+#[test]
+fn missing_origin_head() {
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		// the remote's HEAD points to a branch that doesn't exist, so a clone has no `origin/HEAD`
+		repo.remote_git(&["symbolic-ref", "HEAD", "refs/heads/does-not-exist"]);
+		let clone = repo.clone("clone");
+		git(&clone, &["checkout", "-q", "-b", "main", "origin/main"]);
+		assert!(
+			git(&clone, &["branch", "-r"])
+				.lines()
+				.all(|b| !b.contains("origin/HEAD")),
+			"{}",
+			vcs
+		);
+		let repo = TestRepo {
+			work: clone,
+			..repo
+		};
+		repo.release(vcs).unwrap();
+
+		repo.assert_released(vcs);
+	}
+}
+
+#[test]
+fn command_line_versions() {
+	let omr_bumper = |args: &[&str]| {
+		let output = Command::new(env!("CARGO_BIN_EXE_omr-bumper"))
+			.args(args)
+			.output()
+			.unwrap();
+		(
+			output.status.success(),
+			String::from_utf8_lossy(&output.stdout).to_string(),
+		)
+	};
+	for flag in ["--version", "-V"] {
+		assert_eq!(
+			omr_bumper(&[flag]),
+			(true, format!("omr-bumper {}\n", env!("CARGO_PKG_VERSION")))
+		);
+	}
+
+	for vcs in BACKENDS {
+		let repo = TestRepo::new(&[]);
+		let path = repo.work.display().to_string();
+		let (success, stdout) =
+			omr_bumper(&["--path", &path, "--vcs", vcs, "--release-version", "1.0.0"]);
+		assert!(success, "{} {}", vcs, stdout);
+		assert_eq!(repo.remote_tags(), ["1.0.0"], "{}", vcs);
+		assert!(read(&repo.work, "Cargo.toml").contains(r#"version = "1.0.1-dev""#));
+
+		// a failed release exits with an error
+		let (success, stdout) =
+			omr_bumper(&["--path", &path, "--vcs", vcs, "--release-version", "1.0.0"]);
+		assert!(!success, "{} {}", vcs, stdout);
+	}
+}